@@ -0,0 +1,148 @@
+use std::{
+  process::{Child, Command, Stdio},
+  thread,
+  time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::constants::DEFAULT_INTERPRETER;
+
+/// The window that has the focus.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ActiveWindow {
+  /// e.g. `firefox` or `Alacritty`.
+  pub class: String,
+  pub title: String,
+}
+
+/// Tells which window has the focus, to pick between commands that depend on
+/// it.
+pub trait WindowProvider: Send + Sync {
+  fn active_window(&self) -> Result<ActiveWindow>;
+}
+
+/// The matched commands wait for the window, so a command that hangs is
+/// killed instead.
+const WINDOW_COMMAND_TIMEOUT: Duration = Duration::from_millis(500);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Runs a shell command that prints the class of the active window on the
+/// first line, and its title on the second one.
+pub struct CommandWindowProvider {
+  command: String,
+  timeout: Duration,
+}
+
+impl CommandWindowProvider {
+  pub fn new(command: &str) -> Self {
+    Self {
+      command: command.to_owned(),
+      timeout: WINDOW_COMMAND_TIMEOUT,
+    }
+  }
+}
+
+fn parse_window(output: &str) -> ActiveWindow {
+  let mut lines = output.lines().map(str::trim);
+
+  ActiveWindow {
+    class: lines.next().unwrap_or_default().to_owned(),
+    title: lines.next().unwrap_or_default().to_owned(),
+  }
+}
+
+impl CommandWindowProvider {
+  fn spawn(&self) -> Result<Child> {
+    Command::new(DEFAULT_INTERPRETER)
+      .args(["-c", &self.command])
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped())
+      .spawn()
+      .with_context(|| format!("Cannot run window command: {}", self.command))
+  }
+
+  /// Kills the command if it's still running after the timeout.
+  fn wait(&self, child: &mut Child) -> Result<()> {
+    let deadline = Instant::now() + self.timeout;
+
+    while child.try_wait()?.is_none() {
+      if Instant::now() >= deadline {
+        child.kill().ok();
+        child.wait().ok();
+        bail!(
+          "Window command timed out after {}ms: {}",
+          self.timeout.as_millis(),
+          self.command
+        );
+      }
+
+      thread::sleep(POLL_INTERVAL);
+    }
+
+    Ok(())
+  }
+}
+
+impl WindowProvider for CommandWindowProvider {
+  fn active_window(&self) -> Result<ActiveWindow> {
+    let mut child = self.spawn()?;
+    self.wait(&mut child)?;
+    let output = child.wait_with_output()?;
+
+    if !output.status.success() {
+      bail!(
+        "Window command failed ({}): {}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+      );
+    }
+
+    Ok(parse_window(&String::from_utf8_lossy(&output.stdout)))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn window(class: &str, title: &str) -> ActiveWindow {
+    ActiveWindow {
+      class: class.to_owned(),
+      title: title.to_owned(),
+    }
+  }
+
+  #[test]
+  fn test_parse_window() {
+    assert_eq!(
+      parse_window("firefox\nGitHub\n"),
+      window("firefox", "GitHub")
+    );
+    assert_eq!(parse_window("xterm"), window("xterm", ""));
+  }
+
+  #[test]
+  fn test_command_window_provider() {
+    let provider = CommandWindowProvider::new("printf 'Alacritty\\nvim notes.md\\n'");
+    assert_eq!(
+      provider.active_window().unwrap(),
+      window("Alacritty", "vim notes.md")
+    );
+    assert!(CommandWindowProvider::new("exit 1")
+      .active_window()
+      .is_err());
+  }
+
+  #[test]
+  fn test_command_window_provider_timeout() {
+    let provider = CommandWindowProvider {
+      command: "sleep 5".to_owned(),
+      timeout: Duration::from_millis(50),
+    };
+    let start = Instant::now();
+    assert!(provider.active_window().is_err());
+    assert!(start.elapsed() < Duration::from_secs(1));
+  }
+}
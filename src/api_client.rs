@@ -59,3 +59,36 @@ pub fn send_sequence(port: u16, sequence: &str) -> Result<String> {
     _ => Err(anyhow::anyhow!("{}", res.text()?)),
   }
 }
+
+pub fn run_command(port: u16, name: &str, argument: Option<&str>) -> Result<String> {
+  let client = reqwest::blocking::Client::new();
+  let mut query = vec![("name", name)];
+  query.extend(argument.map(|arg| ("argument", arg)));
+  let res = client.post(build_url(port, "run")).query(&query).send()?;
+
+  match res.status() {
+    StatusCode::OK => Ok(res.text()?),
+    _ => Err(anyhow::anyhow!("{}", res.text()?)),
+  }
+}
+
+pub fn get_mode(port: u16) -> Result<String> {
+  Ok(
+    reqwest::blocking::get(build_url(port, "mode"))?
+      .error_for_status()?
+      .text()?,
+  )
+}
+
+pub fn set_mode(port: u16, name: &str) -> Result<String> {
+  let client = reqwest::blocking::Client::new();
+  let res = client
+    .post(build_url(port, "mode"))
+    .query(&[("name", name)])
+    .send()?;
+
+  match res.status() {
+    StatusCode::OK => Ok(res.text()?),
+    _ => Err(anyhow::anyhow!("{}", res.text()?)),
+  }
+}
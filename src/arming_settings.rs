@@ -0,0 +1,113 @@
+use rdev::Key;
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{
+  constants::DEFAULT_ARM_TIMEOUT_MS, input_settings::override_key, timing_settings::parse_ms_value,
+};
+
+/// When a key or a sequence is configured, sequences are only collected while
+/// the key is held, or for a while after the sequence is clicked.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArmingSettings {
+  pub key: Option<Key>,
+  pub sequence: Option<String>,
+  pub timeout_ms: u128,
+}
+
+impl Default for ArmingSettings {
+  fn default() -> Self {
+    Self {
+      key: None,
+      sequence: None,
+      timeout_ms: DEFAULT_ARM_TIMEOUT_MS,
+    }
+  }
+}
+
+impl ArmingSettings {
+  pub const fn is_enabled(&self) -> bool {
+    self.key.is_some() || self.sequence.is_some()
+  }
+
+  pub fn with_overrides(&self, settings: &HashMap<String, String>) -> Self {
+    let get = |key: &str| settings.get(key).map(String::as_ref);
+
+    Self {
+      key: override_key(get("ARM_KEY"), self.key),
+      sequence: match get("ARM_SEQUENCE") {
+        Some("none") => None,
+        Some(sequence) => Some(sequence.to_owned()),
+        None => self.sequence.clone(),
+      },
+      timeout_ms: get("ARM_TIMEOUT_MS")
+        .and_then(parse_ms_value)
+        .unwrap_or(self.timeout_ms),
+    }
+  }
+}
+
+impl Display for ArmingSettings {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let key = self
+      .key
+      .map_or_else(|| "none".to_owned(), |k| format!("{k:?}"));
+    let sequence = self.sequence.as_deref().unwrap_or("none");
+
+    writeln!(f, "arm_key={key}")?;
+    writeln!(f, "arm_sequence={sequence}")?;
+    write!(f, "arm_timeout_ms={}", self.timeout_ms)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn settings<const N: usize>(pairs: [(&str, &str); N]) -> HashMap<String, String> {
+    pairs
+      .iter()
+      .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+      .collect()
+  }
+
+  #[test]
+  fn test_disabled_by_default() {
+    assert!(!ArmingSettings::default().is_enabled());
+  }
+
+  #[test]
+  fn test_with_overrides() {
+    let arming = ArmingSettings::default().with_overrides(&settings([
+      ("ARM_SEQUENCE", "..--"),
+      ("ARM_TIMEOUT_MS", "3000"),
+    ]));
+    assert!(arming.is_enabled());
+    assert_eq!(arming.sequence.as_deref(), Some("..--"));
+    assert_eq!(arming.timeout_ms, 3000);
+    assert_eq!(arming.key, None);
+  }
+
+  #[test]
+  fn test_with_overrides_none_disables() {
+    let base = ArmingSettings {
+      key: Some(Key::ControlRight),
+      ..ArmingSettings::default()
+    };
+    assert_eq!(base.with_overrides(&settings([])), base);
+    assert!(!base
+      .with_overrides(&settings([("ARM_KEY", "none")]))
+      .is_enabled());
+  }
+
+  #[test]
+  fn test_display() {
+    let arming = ArmingSettings {
+      key: Some(Key::ControlRight),
+      ..ArmingSettings::default()
+    };
+    assert_eq!(
+      arming.to_string(),
+      "arm_key=ControlRight\narm_sequence=none\narm_timeout_ms=5000"
+    );
+  }
+}
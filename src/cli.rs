@@ -1,2 +1,4 @@
+mod calibrate;
+mod check;
 pub mod main;
 mod subcommands;
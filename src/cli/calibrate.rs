@@ -0,0 +1,71 @@
+use anyhow::{Context, Result};
+use rdev::{listen, Event, EventType};
+use std::io::Write;
+use std::sync::mpsc::{self, Receiver};
+use std::time::UNIX_EPOCH;
+
+use crate::click_sequence_detector::ClickSequenceDetector;
+
+pub static CALIBRATION_PATTERN: &str = "..--.-.-";
+pub static CALIBRATION_ROUNDS: usize = 3;
+
+#[derive(Copy, Clone)]
+enum ButtonAction {
+  Press(u128),
+  Release(u128),
+}
+
+fn to_button_action(event: &Event) -> Option<ButtonAction> {
+  let time_ms = event.time.duration_since(UNIX_EPOCH).ok()?.as_millis();
+
+  match event.event_type {
+    EventType::ButtonPress(_) => Some(ButtonAction::Press(time_ms)),
+    EventType::ButtonRelease(_) => Some(ButtonAction::Release(time_ms)),
+    _ => None,
+  }
+}
+
+fn listen_buttons() -> Receiver<ButtonAction> {
+  let (sender, receiver) = mpsc::channel();
+
+  std::thread::spawn(move || {
+    let listen_result = listen(move |event| {
+      if let Some(action) = to_button_action(&event) {
+        sender.send(action).unwrap_or_default();
+      }
+    });
+
+    if let Err(error) = listen_result {
+      eprintln!("Device error: {error:?}");
+    }
+  });
+
+  receiver
+}
+
+fn print_progress() -> Result<()> {
+  print!("*");
+  Ok(std::io::stdout().flush()?)
+}
+
+/// Records `(gap, press)` durations for the given amount of clicks.
+pub fn record_clicks(count: usize) -> Result<Vec<(u128, u128)>> {
+  let actions = listen_buttons();
+  let mut detector = ClickSequenceDetector::new();
+  let mut clicks = vec![];
+  let mut gap = 0;
+
+  while clicks.len() < count {
+    match actions.recv().context("Stopped receiving mouse events")? {
+      ButtonAction::Press(now) => gap = detector.click(now),
+      ButtonAction::Release(now) => {
+        clicks.push((gap, detector.release(now)));
+        print_progress()?;
+      }
+    }
+  }
+
+  println!();
+
+  Ok(clicks)
+}
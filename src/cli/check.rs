@@ -0,0 +1,238 @@
+use anyhow::{anyhow, bail, Context, Result};
+use regex::{Captures, Regex};
+use std::{
+  cell::OnceCell,
+  io::Write,
+  path::Path,
+  process::{Command, Stdio},
+  sync::Mutex,
+};
+
+use crate::{
+  cmd::continues,
+  fuzzy_matcher::SYMBOLS_PER_EDIT,
+  match_settings::MatchSettings,
+  server::{
+    config_diagnostics::Location,
+    config_file_parser::Configuration,
+    config_includes::{conf_d_for, resolve, ConfigSource},
+  },
+  timing_settings::TimingSettings,
+};
+
+/// The files without their commands (including the lines they continue on),
+/// so that each line number in syntax errors is a line of the source.
+fn script_without_commands(lines: &[String], command_lines: &[usize]) -> String {
+  let mut continued = false;
+
+  (1..)
+    .zip(lines)
+    .map(|(number, line)| {
+      let blank = continued || command_lines.contains(&number);
+      continued = blank && continues(line);
+      if blank {
+        ""
+      } else {
+        line.as_str()
+      }
+    })
+    .collect::<Vec<&str>>()
+    .join("\n")
+}
+
+/// Runs the script with `-n` (read it without executing it).
+fn check_syntax(interpreter: &str, script: &str) -> Result<()> {
+  let mut child = Command::new(interpreter)
+    .arg("-n")
+    .stdin(Stdio::piped())
+    .stdout(Stdio::null())
+    .stderr(Stdio::piped())
+    .spawn()
+    .with_context(|| format!("Cannot run {interpreter}"))?;
+
+  if let Some(mut stdin) = child.stdin.take() {
+    stdin.write_all(script.as_bytes())?;
+  }
+
+  let output = child.wait_with_output()?;
+
+  if !output.status.success() {
+    bail!("{}", String::from_utf8_lossy(&output.stderr).trim_end());
+  }
+
+  Ok(())
+}
+
+static ERROR_LINE_REGEX: Mutex<OnceCell<Regex>> = Mutex::new(OnceCell::new());
+
+/// Replaces the line numbers in syntax errors (`sh: 3: ...`, or
+/// `bash: line 3: ...`) with the file and the line they are in.
+fn locate_errors(errors: &str, locations: &[Location]) -> String {
+  let guard = ERROR_LINE_REGEX.lock().unwrap();
+  let re = guard.get_or_init(|| Regex::new(r"(?m)^([^:\n]+: )(?:line )?(\d+):").unwrap());
+
+  re.replace_all(errors, |c: &Captures| {
+    let location = c[2]
+      .parse::<usize>()
+      .ok()
+      .and_then(|number| locations.get(number.checked_sub(1)?));
+
+    location.map_or_else(|| c[0].to_owned(), |l| format!("{}{l}:", &c[1]))
+  })
+  .into_owned()
+}
+
+fn find_problems(
+  source: &ConfigSource,
+  config: &Configuration,
+  interpreter: Option<&str>,
+) -> Vec<String> {
+  let mut problems: Vec<String> = config.diagnostics.iter().map(ToString::to_string).collect();
+
+  if let Some(shell) = interpreter {
+    let script = script_without_commands(&source.lines, &config.command_lines);
+
+    if let Err(err) = check_syntax(shell, &script) {
+      problems.push(locate_errors(&err.to_string(), &source.locations));
+    }
+  }
+
+  problems
+}
+
+fn read_source(config_path: &str) -> Result<ConfigSource> {
+  let content =
+    std::fs::read_to_string(config_path).with_context(|| format!("Cannot read {config_path}"))?;
+
+  let conf_d = conf_d_for(config_path);
+
+  resolve(Path::new(config_path), &content, conf_d.as_deref())
+    .map_err(|err| anyhow!("{config_path}: {err}"))
+}
+
+/// Short sequences get fewer mistakes than the configured fuzzy distance, so
+/// the number of clicks each one needs is shown with it.
+fn fuzzy_note(config_path: &str, config: &Configuration) -> Option<String> {
+  let distance = MatchSettings::from_settings(&config.settings).fuzzy_distance;
+
+  (distance > 0).then(|| {
+    format!(
+      "\n{config_path}: HORSETAB_FUZZY_DISTANCE={distance} allows one mistake for each \
+       {SYMBOLS_PER_EDIT} clicks ({distance} from {} clicks)",
+      distance * SYMBOLS_PER_EDIT
+    )
+  })
+}
+
+/// Checks a configuration file (and the files it includes) without installing
+/// it. With an interpreter, the lines that aren't commands are also checked
+/// for syntax errors.
+pub fn check(config_path: &str, interpreter: Option<&str>) -> Result<String> {
+  let source = read_source(config_path)?;
+  let config = Configuration::from_source(&source, TimingSettings::default());
+
+  let problems: Vec<String> = find_problems(&source, &config, interpreter)
+    .iter()
+    .map(|p| format!("{config_path}: {p}"))
+    .collect();
+
+  if !problems.is_empty() {
+    bail!("{}", problems.join("\n"));
+  }
+
+  Ok(format!(
+    "{config_path}: {} commands, no problems found{}",
+    config.commands.len(),
+    fuzzy_note(config_path, &config).unwrap_or_default()
+  ))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+  use test_case::test_case;
+
+  fn check_content(content: &str, interpreter: Option<&str>) -> Result<String> {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(file, "{content}").unwrap();
+    let path = file.path().to_str().unwrap().to_owned();
+    check(&path, interpreter).map_err(|e| anyhow::anyhow!(e.to_string().replace(&path, "FILE")))
+  }
+
+  #[test]
+  fn test_check_ok() {
+    let result = check_content("A=1\n..-- echo a\n-..- echo b", Some("sh"));
+    assert!(result.unwrap().ends_with(": 2 commands, no problems found"));
+  }
+
+  #[test]
+  fn test_check_fuzzy_distance() {
+    let result = check_content("HORSETAB_FUZZY_DISTANCE=2\n..-- echo a", None).unwrap();
+    let note = ": HORSETAB_FUZZY_DISTANCE=2 allows one mistake for each 3 clicks (2 from 6 clicks)";
+    assert!(result.ends_with(note), "{result}");
+    assert!(check_content("..-- echo a", None)
+      .unwrap()
+      .ends_with("found"));
+  }
+
+  #[test]
+  fn test_check_diagnostics() {
+    let result = check_content("..-- echo a\n\n..-- echo b", None);
+    assert_eq!(
+      result.unwrap_err().to_string(),
+      "FILE: line 3: ..-- is also on line 1"
+    );
+  }
+
+  #[test]
+  fn test_check_syntax() {
+    let content = "..-- echo a\nif true; then\n  echo b\n";
+    assert!(check_content(content, None).is_ok());
+    let error = check_content(content, Some("sh")).unwrap_err().to_string();
+    assert!(error.starts_with("FILE: "));
+  }
+
+  #[test]
+  fn test_check_syntax_in_included_file() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let main = dir.path().join("main.conf");
+    std::fs::write(&main, "A=1\ninclude b.conf\n..-- echo a").unwrap();
+    std::fs::write(dir.path().join("b.conf"), "B=2\nfi\n").unwrap();
+    let error = check(main.to_str().unwrap(), Some("bash"))
+      .unwrap_err()
+      .to_string();
+    assert!(error.contains("bash: line 2 of b.conf: "), "{error}");
+  }
+
+  #[test_case("sh: 2: Syntax error: \"fi\" unexpected" => "sh: line 1 of b.conf: Syntax error: \"fi\" unexpected"; "dash")]
+  #[test_case("bash: line 1: syntax error" => "bash: line 1: syntax error"; "main file")]
+  #[test_case("bash: line 9: x\nbash: line 2: y" => "bash: line 9: x\nbash: line 1 of b.conf: y"; "out of range")]
+  fn test_locate_errors(errors: &str) -> String {
+    let locations = [
+      Location::new(1),
+      Location {
+        file: Some("b.conf".to_owned()),
+        line: 1,
+      },
+    ];
+    locate_errors(errors, &locations)
+  }
+
+  #[test]
+  fn test_check_missing_file() {
+    assert!(check("/nonexistent/horsetab.conf", None).is_err());
+  }
+
+  #[test]
+  fn test_script_without_commands() {
+    let lines = ["A=1", ".- x", "echo", "-. y"].map(str::to_owned);
+    assert_eq!(script_without_commands(&lines, &[2, 4]), "A=1\n\necho\n");
+  }
+
+  #[test]
+  fn test_script_without_commands_continued() {
+    let lines = [".- x \\", "  y", "echo \\", "  z"].map(str::to_owned);
+    assert_eq!(script_without_commands(&lines, &[1]), "\n\necho \\\n  z");
+  }
+}
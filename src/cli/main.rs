@@ -1,14 +1,157 @@
-use super::subcommands::{
-  edit_subcommand, ps_subcommand, send_sequence_subcommand, show_subcommand,
-  watch_sequences_subcommand,
+use super::{
+  check::check,
+  subcommands::{
+    calibrate_subcommand, edit_subcommand, mode_subcommand, ps_subcommand, run_subcommand,
+    send_sequence_subcommand, show_subcommand, watch_sequences_subcommand,
+  },
 };
 use crate::{
-  constants::{get_default_config_path, DEFAULT_INTERPRETER, DEFAULT_PORT},
-  server,
+  arming_settings::ArmingSettings,
+  constants::{
+    get_default_calibration_path, get_default_config_path, DEFAULT_ARM_TIMEOUT_MS,
+    DEFAULT_INTERPRETER, DEFAULT_LONG_PRESS_MS, DEFAULT_PORT, DEFAULT_SEQUENCE_RESET_MS,
+    DEFAULT_WINDOW_COMMAND,
+  },
+  input_settings::{parse_key, InputButton, InputSettings},
+  input_source::InputSourceKind,
+  server::{self, main::ServerOptions},
+  timing_settings::TimingSettings,
 };
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use colored::Colorize;
+use rdev::Key;
+
+#[derive(Args)]
+pub struct TimingArgs {
+  #[arg(
+    long,
+    default_value_t = DEFAULT_LONG_PRESS_MS,
+    help = "Presses longer than this (in milliseconds) are long clicks"
+  )]
+  long_press_ms: u128,
+
+  #[arg(
+    long,
+    default_value_t = DEFAULT_SEQUENCE_RESET_MS,
+    help = "A pause longer than this (in milliseconds) starts a new sequence"
+  )]
+  sequence_reset_ms: u128,
+
+  #[arg(
+    long,
+    default_value_t = false,
+    help = "Learn the thresholds from the way you click"
+  )]
+  adaptive: bool,
+
+  #[arg(
+    long,
+    default_value_t = false,
+    help = "When a sequence is also the start of a longer one, wait for the pause after it before executing it"
+  )]
+  wait_for_longer: bool,
+}
+
+impl From<&TimingArgs> for TimingSettings {
+  fn from(args: &TimingArgs) -> Self {
+    Self {
+      long_press_ms: args.long_press_ms,
+      sequence_reset_ms: args.sequence_reset_ms,
+      adaptive: args.adaptive,
+      wait_for_longer: args.wait_for_longer,
+    }
+  }
+}
+
+#[derive(Args)]
+pub struct InputArgs {
+  #[arg(long, help = "Only accept clicks from this button")]
+  button: Option<InputButton>,
+
+  #[arg(
+    long,
+    default_value_t = false,
+    help = "Each button produces its own symbols (right: `,` `_`, middle: `:` `=`)"
+  )]
+  button_symbols: bool,
+
+  #[arg(
+    long,
+    value_parser = parse_key_arg,
+    help = "Tap this key (e.g. ScrollLock, ControlRight) short/long to input sequences"
+  )]
+  key: Option<Key>,
+
+  #[arg(
+    long,
+    default_value_t = false,
+    help = "Scrolling up/down produces `U`/`D`"
+  )]
+  wheel: bool,
+
+  #[arg(
+    long,
+    default_value_t = false,
+    help = "Dragging up/down/left/right produces `^`/`v`/`<`/`>`"
+  )]
+  gestures: bool,
+}
+
+fn parse_key_arg(name: &str) -> Result<Key, String> {
+  parse_key(name).ok_or_else(|| format!("unknown key `{name}`"))
+}
+
+impl From<&InputArgs> for InputSettings {
+  fn from(args: &InputArgs) -> Self {
+    Self {
+      button: args.button,
+      button_symbols: args.button_symbols,
+      key: args.key,
+      wheel: args.wheel,
+      gestures: args.gestures,
+    }
+  }
+}
+
+#[derive(Args)]
+pub struct ArmingArgs {
+  #[arg(
+    id = "arm_key",
+    value_name = "ARM_KEY",
+    long = "arm-key",
+    value_parser = parse_key_arg,
+    help = "Only accept sequences while this key is held"
+  )]
+  key: Option<Key>,
+
+  #[arg(
+    id = "arm_sequence",
+    value_name = "ARM_SEQUENCE",
+    long = "arm-sequence",
+    help = "Only accept sequences for a while after this one"
+  )]
+  sequence: Option<String>,
+
+  #[arg(
+    id = "arm_timeout_ms",
+    value_name = "ARM_TIMEOUT_MS",
+    long = "arm-timeout-ms",
+    default_value_t = DEFAULT_ARM_TIMEOUT_MS,
+    help = "Disarm after this long (in milliseconds) without clicks"
+  )]
+  timeout_ms: u128,
+}
+
+impl From<&ArmingArgs> for ArmingSettings {
+  fn from(args: &ArmingArgs) -> Self {
+    Self {
+      key: args.key,
+      sequence: args.sequence.clone(),
+      timeout_ms: args.timeout_ms,
+    }
+  }
+}
 
 #[derive(Subcommand)]
 pub enum Commands {
@@ -22,6 +165,75 @@ pub enum Commands {
 
     #[arg(short, long, default_value_t = DEFAULT_INTERPRETER.to_owned())]
     interpreter: String,
+
+    #[command(flatten)]
+    timing: TimingArgs,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[command(flatten)]
+    arming: ArmingArgs,
+
+    #[arg(long, default_value_t = get_default_calibration_path())]
+    calibration_path: String,
+
+    #[arg(long, help = "Write the raw input events to this file")]
+    record: Option<String>,
+
+    #[arg(
+      long,
+      default_value = "rdev",
+      help = "Where input comes from: `rdev` (X11), `stdin`, `pipe:PATH` (events in the recording format), or `evdev[:PATH,...]` (/dev/input devices)"
+    )]
+    input_source: InputSourceKind,
+
+    #[arg(
+      long,
+      default_value_t = DEFAULT_WINDOW_COMMAND.to_owned(),
+      help = "Prints the class and the title of the active window, for commands with a `class` or `title` condition"
+    )]
+    window_command: String,
+  },
+
+  #[command(about = "Replay a recorded input session and print the matched commands")]
+  Replay {
+    file: String,
+
+    #[arg(short, long, default_value_t = get_default_config_path())]
+    config_path: String,
+
+    #[command(flatten)]
+    timing: TimingArgs,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[command(flatten)]
+    arming: ArmingArgs,
+  },
+
+  #[command(about = "Check a configuration file for problems, without installing it")]
+  Check {
+    #[arg(default_value_t = get_default_config_path())]
+    file: String,
+
+    #[arg(
+      long,
+      help = "Also check the shell syntax of the lines that aren't commands (with `INTERPRETER -n`)"
+    )]
+    syntax: bool,
+
+    #[arg(short, long, default_value_t = DEFAULT_INTERPRETER.to_owned())]
+    interpreter: String,
+  },
+
+  #[command(
+    about = "Print a configuration file in the TOML format (save it as a .toml file to use it)"
+  )]
+  Convert {
+    #[arg(default_value_t = get_default_config_path())]
+    file: String,
   },
 
   #[command(about = "Show current commands")]
@@ -52,12 +264,37 @@ pub enum Commands {
     sequence: String,
   },
 
+  #[command(about = "Run a command by its name (set with `# @name NAME` above it)")]
+  Run {
+    #[arg(short, long, default_value_t = DEFAULT_PORT)]
+    port: u16,
+
+    name: String,
+
+    #[arg(help = "Given to the command as $1, as if it was clicked after the sequence")]
+    argument: Option<String>,
+  },
+
+  #[command(about = "Show the current mode, or switch to another one (set with `[mode NAME]`)")]
+  Mode {
+    #[arg(short, long, default_value_t = DEFAULT_PORT)]
+    port: u16,
+
+    name: Option<String>,
+  },
+
   #[command(about = "Watch sequences")]
   Watch {
     #[arg(short, long, default_value_t = DEFAULT_PORT)]
     port: u16,
   },
 
+  #[command(about = "Learn your click timing by clicking a known pattern")]
+  Calibrate {
+    #[arg(long, default_value_t = get_default_calibration_path())]
+    calibration_path: String,
+  },
+
   #[command(about = "Display status information about processes")]
   Ps {
     #[arg(short, long, default_value_t = DEFAULT_PORT)]
@@ -72,21 +309,66 @@ pub struct Cli {
   pub command: Option<Commands>,
 }
 
+#[allow(clippy::too_many_lines)]
 fn match_cli_subcommand(command: &Commands) -> Result<String> {
   match command {
     Commands::Serve {
       port,
       config_path,
       interpreter,
+      timing,
+      input,
+      arming,
+      calibration_path,
+      record,
+      input_source,
+      window_command,
     } => {
-      server::main::start(*port, config_path, interpreter);
+      server::main::start(&ServerOptions {
+        port: *port,
+        config_path,
+        interpreter,
+        timing: timing.into(),
+        input: input.into(),
+        arming: arming.into(),
+        calibration_path,
+        record_path: record.as_deref(),
+        input_source,
+        window_command,
+      });
       Ok(String::new())
     }
+    Commands::Replay {
+      file,
+      config_path,
+      timing,
+      input,
+      arming,
+    } => server::replay::replay(
+      file,
+      config_path,
+      timing.into(),
+      input.into(),
+      arming.into(),
+    ),
+    Commands::Check {
+      file,
+      syntax,
+      interpreter,
+    } => check(file, syntax.then_some(interpreter.as_str())),
+    Commands::Convert { file } => server::config_toml::convert_file(file),
     Commands::Edit { port } => edit_subcommand(*port),
     Commands::Show { port, raw } => show_subcommand(*port, *raw),
     Commands::SendSequence { port, sequence } => send_sequence_subcommand(*port, sequence),
+    Commands::Run {
+      port,
+      name,
+      argument,
+    } => run_subcommand(*port, name, argument.as_deref()),
+    Commands::Mode { port, name } => mode_subcommand(*port, name.as_deref()),
     Commands::Watch { port } => watch_sequences_subcommand(*port),
     Commands::Ps { port } => ps_subcommand(*port),
+    Commands::Calibrate { calibration_path } => calibrate_subcommand(calibration_path),
   }
 }
 
@@ -118,3 +400,14 @@ pub fn start_cli_app() {
     handle_subcommand_result(subcommand_result);
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use clap::CommandFactory;
+
+  #[test]
+  fn test_cli_arguments() {
+    Cli::command().debug_assert();
+  }
+}
@@ -1,11 +1,17 @@
+use super::calibrate::{record_clicks, CALIBRATION_PATTERN, CALIBRATION_ROUNDS};
 use crate::constants::DEFAULT_COMMAND_CONFIG_FILE_CONTENT;
 use crate::event_observe::EventType;
+use crate::input_settings::InputButton;
 use crate::ipc_tcp::{connect_tcp, TcpAction};
+use crate::morse::decode_letter;
+use crate::timing_calibration::LabeledSamples;
+use crate::timing_settings::TimingSettings;
 use crate::{
   api_client::{self},
-  cmd::Cmd,
+  cmd::{parse_commands, Annotation, Cmd},
+  mode::{ModeHeader, DEFAULT_MODE},
 };
-use anyhow::Result;
+use anyhow::{Context, Result};
 use colored::Colorize;
 use std::io::{BufReader, Read, Write};
 
@@ -18,17 +24,29 @@ pub fn show_subcommand(port: u16, raw: bool) -> Result<String> {
       if raw {
         Ok(text)
       } else {
-        Ok(format_commands(&text))
+        Ok(format_commands(&text, &api_client::get_mode(port)?))
       }
     }
     Err(_) => current_config,
   }
 }
 
+/// Shows the current mode, or switches to another one.
+pub fn mode_subcommand(port: u16, name: Option<&str>) -> Result<String> {
+  name.map_or_else(
+    || api_client::get_mode(port),
+    |mode| api_client::set_mode(port, mode),
+  )
+}
+
 pub fn ps_subcommand(port: u16) -> Result<String> {
   api_client::get_ps(port)
 }
 
+pub fn run_subcommand(port: u16, name: &str, argument: Option<&str>) -> Result<String> {
+  api_client::run_command(port, name, argument)
+}
+
 pub fn edit_subcommand(port: u16) -> Result<String> {
   let current_config = api_client::get_current_config(port)?;
 
@@ -47,18 +65,106 @@ pub fn edit_subcommand(port: u16) -> Result<String> {
   }
 }
 
-fn format_commands(commands_text: &str) -> String {
-  commands_text
-    .split('\n')
-    .filter(|s| !s.is_empty())
-    .map(|s| Cmd::parse(s).expect("Should have correct format"))
-    .map(|cmd| format!("{}\t{}", cmd.sequence.yellow().bold(), cmd.command))
+fn format_annotation(annotation: &Annotation) -> String {
+  let name = annotation.name.green().bold();
+
+  annotation.description.as_ref().map_or_else(
+    || name.to_string(),
+    |description| format!("{name}: {description}"),
+  )
+}
+
+/// An alias is followed by its morse, and morse that is a single letter by
+/// it.
+fn format_sequence(cmd: &Cmd) -> String {
+  cmd.alias.as_ref().map_or_else(
+    || {
+      let sequence = cmd.sequence.yellow().bold();
+      decode_letter(&cmd.sequence).map_or_else(
+        || sequence.to_string(),
+        |text| format!("{sequence} {}", format!("({text})").dimmed()),
+      )
+    },
+    |alias| {
+      let written = format!("@{alias}").yellow().bold();
+      format!("{written} {}", cmd.sequence.dimmed())
+    },
+  )
+}
+
+/// The sequence, options and parameter.
+fn format_prefix(cmd: &Cmd) -> String {
+  let sequence = format_sequence(cmd);
+  let options = (!cmd.options.is_empty()).then(|| cmd.options.to_string().dimmed().to_string());
+  let tag = cmd.parameter.map(|p| p.tag().yellow().to_string());
+
+  [Some(sequence), options, tag]
+    .into_iter()
+    .flatten()
     .collect::<Vec<String>>()
-    .join("\n")
+    .join(" ")
+}
+
+/// The name (if any) goes above the command, and the lines a command
+/// continues on are aligned with its first line.
+fn format_command(cmd: &Cmd) -> String {
+  let command = cmd.command.replace('\n', "\n\t");
+  let line = format!("{}\t{command}", format_prefix(cmd));
+
+  cmd.annotation.as_ref().map_or_else(
+    || line.clone(),
+    |annotation| format!("{}\n{line}", format_annotation(annotation)),
+  )
+}
+
+fn format_mode_header(mode: &str, current_mode: &str) -> String {
+  let header = ModeHeader::new(mode).to_string().cyan().bold();
+
+  if mode == current_mode {
+    format!("{header} {}", "(current)".dimmed())
+  } else {
+    header.to_string()
+  }
+}
+
+/// The current mode goes first when there's more than the default one.
+fn format_current_mode(commands: &[Cmd], current_mode: &str) -> Option<String> {
+  let has_modes = commands.iter().any(|cmd| cmd.mode != DEFAULT_MODE);
+  (has_modes || current_mode != DEFAULT_MODE)
+    .then(|| format!("Mode: {}", current_mode.cyan().bold()))
+}
+
+/// The commands in each mode go after its header.
+fn format_commands(commands_text: &str, current_mode: &str) -> String {
+  let commands = parse_commands(commands_text);
+  let mut lines: Vec<String> = format_current_mode(&commands, current_mode)
+    .into_iter()
+    .collect();
+  let mut mode = DEFAULT_MODE;
+
+  for cmd in &commands {
+    if cmd.mode != mode {
+      mode = &cmd.mode;
+      lines.push(format_mode_header(mode, current_mode));
+    }
+    lines.push(format_command(cmd));
+  }
+
+  lines.join("\n")
+}
+
+fn format_symbol(symbol: char) -> String {
+  let text = symbol.to_string();
+
+  match InputButton::of_symbol(symbol) {
+    Some(InputButton::Right) => text.cyan().to_string(),
+    Some(InputButton::Middle) => text.magenta().to_string(),
+    _ => text,
+  }
 }
 
 fn newline_or_flush<W: Write>(
-  event_type: EventType,
+  event_type: &EventType,
   out: &mut W,
   last_is_newline: &mut bool,
 ) -> Result<()> {
@@ -73,21 +179,72 @@ fn newline_or_flush<W: Write>(
   Ok(())
 }
 
+/// Arming and mode changes, and suggestions, are shown on their own line.
+fn start_line<W: Write>(
+  event_type: &EventType,
+  out: &mut W,
+  last_is_newline: &mut bool,
+) -> Result<()> {
+  if matches!(
+    event_type,
+    EventType::Armed | EventType::Disarmed | EventType::NearMiss(_) | EventType::ModeChanged(_)
+  ) {
+    if !*last_is_newline {
+      writeln!(out)?;
+    }
+    *last_is_newline = false;
+  }
+
+  Ok(())
+}
+
+fn format_near_miss(sequences: &[String]) -> String {
+  format!("Did you mean {}?", sequences.join(" or "))
+    .dimmed()
+    .to_string()
+}
+
+fn format_event(event_type: &EventType) -> String {
+  match event_type {
+    EventType::FoundResults => " * Match found".yellow().to_string(),
+    EventType::FoundNamedResults(names) => format!(" * Match found: {}", names.join(", "))
+      .yellow()
+      .to_string(),
+    EventType::SequenceItem(c) => format_symbol(*c),
+    EventType::SequenceReset => String::new(),
+    EventType::Armed => "Armed".green().to_string(),
+    EventType::Disarmed => "Disarmed".dimmed().to_string(),
+    EventType::NearMiss(sequences) => format_near_miss(sequences),
+    EventType::ModeChanged(mode) => format!("Mode: {}", mode.cyan().bold()),
+  }
+}
+
+/// Symbols are collected until another event ends them, and then followed by
+/// the letter they are (if they are one).
+fn end_burst<W: Write>(event_type: &EventType, out: &mut W, burst: &mut String) -> Result<()> {
+  if let EventType::SequenceItem(c) = event_type {
+    burst.push(*c);
+  } else if let Some(text) = decode_letter(&std::mem::take(burst)) {
+    write!(out, " {}", format!("({text})").dimmed())?;
+  }
+
+  Ok(())
+}
+
 fn watch_sequences_print_formatted<R, W>(mut buf: BufReader<R>, mut out: W) -> Result<()>
 where
   R: Read,
   W: Write,
 {
   let mut last_is_newline = true;
+  let mut burst = String::new();
 
   while let Ok(event_type) = bincode::deserialize_from(&mut buf) {
-    match event_type {
-      EventType::FoundResults => write!(out, "{}", " * Match found".yellow())?,
-      EventType::SequenceItem(c) => write!(out, "{c}")?,
-      EventType::SequenceReset => {}
-    }
+    end_burst(&event_type, &mut out, &mut burst)?;
+    start_line(&event_type, &mut out, &mut last_is_newline)?;
 
-    newline_or_flush(event_type, &mut out, &mut last_is_newline)?;
+    write!(out, "{}", format_event(&event_type))?;
+    newline_or_flush(&event_type, &mut out, &mut last_is_newline)?;
   }
 
   Ok(())
@@ -106,6 +263,34 @@ pub fn send_sequence_subcommand(port: u16, sequence: &str) -> Result<String> {
   Ok(String::new())
 }
 
+fn format_calibration_result(calibration_path: &str, timing: TimingSettings) -> String {
+  format!(
+    "Saved calibration to {calibration_path} (used by `serve --adaptive`)\n\
+     To use it without adaptive mode, add this to the config file:\n\
+     HORSETAB_LONG_PRESS_MS={}\n\
+     HORSETAB_SEQUENCE_RESET_MS={}",
+    timing.long_press_ms, timing.sequence_reset_ms
+  )
+}
+
+pub fn calibrate_subcommand(calibration_path: &str) -> Result<String> {
+  println!(
+    "Click this pattern {CALIBRATION_ROUNDS} times, pausing for a second between repetitions:"
+  );
+  println!("{}", CALIBRATION_PATTERN.yellow().bold());
+
+  let clicks = record_clicks(CALIBRATION_PATTERN.len() * CALIBRATION_ROUNDS)?;
+  let samples = LabeledSamples::from_clicks(CALIBRATION_PATTERN, &clicks);
+
+  let timing = samples
+    .timing(TimingSettings::default())
+    .context("Short and long clicks (or pauses) could not be told apart, try again")?;
+
+  samples.to_calibration().save(calibration_path)?;
+
+  Ok(format_calibration_result(calibration_path, timing))
+}
+
 #[cfg(test)]
 mod tests {
   use std::io::Cursor;
@@ -117,12 +302,28 @@ mod tests {
     " * Match found".yellow().to_string()
   }
 
+  fn print_events(events: &[EventType]) -> String {
+    let bytes: Vec<u8> = events
+      .iter()
+      .flat_map(|ev| bincode::serialize(ev).unwrap())
+      .collect();
+    let mut write = vec![];
+    watch_sequences_print_formatted(BufReader::new(Cursor::new(bytes)), &mut write).unwrap();
+    String::from_utf8_lossy(&write).into_owned()
+  }
+
+  fn decoded(text: &str) -> String {
+    format!(" {}", format!("({text})").dimmed())
+  }
+
   fn events_to_bytes(event_string: &str) -> Vec<u8> {
     event_string
       .chars()
       .map(|c| match c {
         'F' => EventType::FoundResults,
         'R' => EventType::SequenceReset,
+        'A' => EventType::Armed,
+        'D' => EventType::Disarmed,
         item => EventType::SequenceItem(item),
       })
       .flat_map(|ev| bincode::serialize(&ev).unwrap())
@@ -132,10 +333,15 @@ mod tests {
   #[test_case("R..-..-F..-..-FR", &format!("..-..-{}\n..-..-{}\n", found(), found()))]
   #[test_case("R..-..-F..-..-R", &format!("..-..-{}\n..-..-\n", found()))]
   #[test_case("RRRRRRRRRRRRR", "")]
-  #[test_case("RRRRRRRRR..-.RRR.-R.-RRRR.", "..-.\n.-\n.-\n.")]
-  #[test_case("RRRRRRRRR..-.RRR.-R.-RRRR.RRRR", "..-.\n.-\n.-\n.\n")]
-  #[test_case("R..FRR--F", &format!("..{}\n--{}\n", found(), found()))]
-  #[test_case("R..FRR--FR", &format!("..{}\n--{}\n", found(), found()))]
+  #[test_case("RRRRRRRRR..-.RRR.-R.-RRRR.", &format!("..-.{}\n.-{}\n.-{}\n.", decoded("F"), decoded("A"), decoded("A")))]
+  #[test_case("RRRRRRRRR..-.RRR.-R.-RRRR.RRRR", &format!("..-.{}\n.-{}\n.-{}\n.{}\n", decoded("F"), decoded("A"), decoded("A"), decoded("E")))]
+  #[test_case("R..FRR--F", &format!("..{}{}\n--{}{}\n", decoded("I"), found(), decoded("M"), found()))]
+  #[test_case("R..FRR--FR", &format!("..{}{}\n--{}{}\n", decoded("I"), found(), decoded("M"), found()))]
+  #[test_case("AR..-FRDR", &format!("{}\n..-{}{}\n{}\n", "Armed".green(), decoded("U"), found(), "Disarmed".dimmed()))]
+  #[test_case("R..D.AR", &format!("..{}\n{}\n.{}\n{}\n", decoded("I"), "Disarmed".dimmed(), decoded("E"), "Armed".green()))]
+  #[test_case("R...,---,...R", &format!("...{}---{}...\n", ",".cyan(), ",".cyan()))]
+  #[test_case("R.,.R", &format!(".{}.\n", ",".cyan()))]
+  #[test_case("R.,=F", &format!(".{}{}{}\n", ",".cyan(), "=".magenta(), found()))]
   fn test_watch_sequences_print_formatted(event_string: &str, expected: &str) {
     let read = BufReader::new(Cursor::new(events_to_bytes(event_string)));
 
@@ -148,4 +354,121 @@ mod tests {
     assert!(!result.starts_with('\n'));
     assert_eq!(result, expected);
   }
+
+  #[test]
+  fn test_watch_near_miss() {
+    let events = [
+      EventType::SequenceItem('.'),
+      EventType::SequenceReset,
+      EventType::NearMiss(vec!["..".to_owned(), ".-".to_owned()]),
+    ];
+    let bytes: Vec<u8> = events
+      .iter()
+      .flat_map(|ev| bincode::serialize(ev).unwrap())
+      .collect();
+    let mut write = vec![];
+
+    watch_sequences_print_formatted(BufReader::new(Cursor::new(bytes)), &mut write).unwrap();
+
+    let expected = format!(".{}\n{}\n", decoded("E"), "Did you mean .. or .-?".dimmed());
+    assert_eq!(String::from_utf8_lossy(&write), expected);
+  }
+
+  #[test]
+  fn test_format_commands_multiple_lines() {
+    let text = ".-.- echo a\n..-- \\\n  echo b; \\\n  echo c\n";
+    let expected = format!(
+      "{}\techo a\n{}\t\\\n\t  echo b; \\\n\t  echo c",
+      ".-.-".yellow().bold(),
+      "..--".yellow().bold()
+    );
+    assert_eq!(format_commands(text, DEFAULT_MODE), expected);
+  }
+
+  #[test]
+  fn test_format_commands_alias() {
+    let text = "@SOS echo a\n..-. echo b\n.,. echo c";
+    let expected = format!(
+      "{} {}\techo a\n{} {}\techo b\n{}\techo c",
+      "@SOS".yellow().bold(),
+      "...---...".dimmed(),
+      "..-.".yellow().bold(),
+      "(F)".dimmed(),
+      ".,.".yellow().bold()
+    );
+    assert_eq!(format_commands(text, DEFAULT_MODE), expected);
+  }
+
+  #[test]
+  fn test_format_commands_annotation() {
+    let text = "# @name lock: Lock the screen\n.-.- loginctl lock-session\n# @name b\n..-- b";
+    let expected = format!(
+      "{}: Lock the screen\n{}\tloginctl lock-session\n{}\n{}\tb",
+      "lock".green().bold(),
+      ".-.-".yellow().bold(),
+      "b".green().bold(),
+      "..--".yellow().bold()
+    );
+    assert_eq!(format_commands(text, DEFAULT_MODE), expected);
+  }
+
+  #[test]
+  fn test_watch_named_results() {
+    let events = [
+      EventType::SequenceItem('.'),
+      EventType::FoundNamedResults(vec!["a".to_owned(), "b".to_owned()]),
+    ];
+    let bytes: Vec<u8> = events
+      .iter()
+      .flat_map(|ev| bincode::serialize(ev).unwrap())
+      .collect();
+    let mut write = vec![];
+
+    watch_sequences_print_formatted(BufReader::new(Cursor::new(bytes)), &mut write).unwrap();
+
+    let expected = format!(".{}{}\n", decoded("E"), " * Match found: a, b".yellow());
+    assert_eq!(String::from_utf8_lossy(&write), expected);
+  }
+
+  #[test]
+  fn test_format_commands_options() {
+    let expected = format!(
+      "{} {} {}\techo $1",
+      ".-.-".yellow().bold(),
+      "[cwd=/tmp]".dimmed(),
+      "#n".yellow()
+    );
+    assert_eq!(
+      format_commands(".-.- [cwd=/tmp] #n echo $1", DEFAULT_MODE),
+      expected
+    );
+  }
+
+  #[test]
+  fn test_format_commands_modes() {
+    let text = "..-- echo a\n[mode browser]\n.-.- echo b";
+    let expected = format!(
+      "Mode: {}\n{}\techo a\n{} {}\n{}\techo b",
+      "browser".cyan().bold(),
+      "..--".yellow().bold(),
+      "[mode browser]".cyan().bold(),
+      "(current)".dimmed(),
+      ".-.-".yellow().bold()
+    );
+    assert_eq!(format_commands(text, "browser"), expected);
+  }
+
+  #[test]
+  fn test_watch_mode_changed() {
+    let output = print_events(&[
+      EventType::SequenceItem('.'),
+      EventType::FoundResults,
+      EventType::ModeChanged("browser".to_owned()),
+    ]);
+    let mode = "browser".cyan().bold();
+    assert_eq!(
+      output,
+      format!(".{}{}\nMode: {mode}\n", decoded("E"), found())
+    );
+  }
 }
@@ -1,39 +1,69 @@
-use std::time::Instant;
-
 #[derive(Copy, Clone)]
 pub enum MouseClickKind {
   Short,
   Long,
 }
 
+impl MouseClickKind {
+  pub const fn from_duration(press_ms: u128, long_ms: u128) -> Self {
+    if press_ms > long_ms {
+      Self::Long
+    } else {
+      Self::Short
+    }
+  }
+}
+
+/// Measures presses and the pauses between them. Times are in milliseconds.
 pub struct ClickSequenceDetector {
-  timestamp: Instant,
-  long_ms: u128,
+  timestamp: u128,
 }
 
 impl ClickSequenceDetector {
-  pub fn new(long_ms: u128) -> Self {
-    Self {
-      timestamp: Instant::now(),
-      long_ms,
-    }
+  pub const fn new() -> Self {
+    Self { timestamp: 0 }
   }
 
   #[must_use]
-  pub fn click(&mut self) -> u128 {
-    let elapsed_since_last = self.timestamp.elapsed().as_millis();
-    self.timestamp = Instant::now();
+  pub const fn click(&mut self, now: u128) -> u128 {
+    let elapsed_since_last = now.saturating_sub(self.timestamp);
+    self.timestamp = now;
 
     elapsed_since_last
   }
 
-  pub fn release(&mut self) -> MouseClickKind {
-    let elapsed = self.timestamp.elapsed().as_millis();
-    self.timestamp = Instant::now();
-    if elapsed > self.long_ms {
-      MouseClickKind::Long
-    } else {
-      MouseClickKind::Short
-    }
+  #[must_use]
+  pub const fn release(&mut self, now: u128) -> u128 {
+    let press_duration = now.saturating_sub(self.timestamp);
+    self.timestamp = now;
+
+    press_duration
+  }
+
+  /// Ends a press that must not be classified (e.g. it made a gesture).
+  pub const fn cancel(&mut self, now: u128) {
+    self.timestamp = now;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_click_and_release() {
+    let mut detector = ClickSequenceDetector::new();
+    assert_eq!(detector.click(1000), 1000);
+    assert_eq!(detector.release(1150), 150);
+    assert_eq!(detector.click(1400), 250);
+    detector.cancel(1500);
+    assert_eq!(detector.click(1600), 100);
+  }
+
+  #[test]
+  fn test_clock_going_backwards() {
+    let mut detector = ClickSequenceDetector::new();
+    assert_eq!(detector.click(1000), 1000);
+    assert_eq!(detector.release(900), 0);
   }
 }
@@ -1,43 +1,292 @@
-use std::{cell::OnceCell, sync::Mutex};
+use std::{cell::OnceCell, fmt::Display, sync::Mutex};
 
 use anyhow::{bail, Result};
 use regex::Regex;
 
+use crate::{
+  command_options::CommandOptions,
+  input_settings::SEQUENCE_SYMBOLS,
+  mode::{ModeHeader, DEFAULT_MODE},
+  morse::{decode_number, encode_text, GAP_SYMBOLS},
+  sequence_pattern::Pattern,
+};
+
+/// Clicks after the sequence (until the pause that ends it) that are given to
+/// the command as an argument.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Parameter {
+  /// `#n`: a number, in morse digits.
+  Number,
+  /// `#s`: the symbols as they were clicked.
+  Symbols,
+}
+
+impl Parameter {
+  fn from_tag(tag: &str) -> Option<Self> {
+    match tag {
+      "#n" => Some(Self::Number),
+      "#s" => Some(Self::Symbols),
+      _ => None,
+    }
+  }
+
+  pub const fn tag(self) -> &'static str {
+    match self {
+      Self::Number => "#n",
+      Self::Symbols => "#s",
+    }
+  }
+
+  pub fn decode(self, symbols: &str) -> Option<String> {
+    match self {
+      Self::Number => decode_number(symbols),
+      Self::Symbols if symbols.is_empty() => None,
+      Self::Symbols => Some(symbols.to_owned()),
+    }
+  }
+}
+
+/// `# @name NAME: description` on the line above a command (the description
+/// is optional).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Annotation {
+  pub name: String,
+  pub description: Option<String>,
+}
+
+static ANNOTATION_REGEX: Mutex<OnceCell<Regex>> = Mutex::new(OnceCell::new());
+
+impl Annotation {
+  pub fn parse(line: &str) -> Option<Self> {
+    let guard = ANNOTATION_REGEX.lock().unwrap();
+    let re =
+      guard.get_or_init(|| Regex::new(r"^\s*#\s*@name\s+([\w.-]+)\s*(?::\s*(.*?))?\s*$").unwrap());
+    let captures = re.captures(line)?;
+
+    Some(Self {
+      name: captures[1].to_owned(),
+      description: captures
+        .get(2)
+        .map(|d| d.as_str().to_owned())
+        .filter(|d| !d.is_empty()),
+    })
+  }
+}
+
+impl Display for Annotation {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match &self.description {
+      Some(description) => write!(f, "# @name {}: {description}", self.name),
+      None => write!(f, "# @name {}", self.name),
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Cmd {
+  pub annotation: Option<Annotation>,
+  /// Letters and digits the sequence was written as (e.g. `SOS` for `@SOS`).
+  pub alias: Option<String>,
   pub sequence: String,
+  pub options: CommandOptions,
+  pub parameter: Option<Parameter>,
   pub command: String,
+  /// The mode it's matched in (the section it's written in).
+  pub mode: String,
 }
 
 static REGEX: Mutex<OnceCell<Regex>> = Mutex::new(OnceCell::new());
 
+/// A sequence (symbols, or `@` and an alias) and the rest of the line.
+fn line_regex() -> Regex {
+  let symbols = regex::escape(SEQUENCE_SYMBOLS);
+  let gaps = regex::escape(GAP_SYMBOLS);
+
+  Regex::new(&format!(
+    r"(?s)^\s*([{symbols}?(|){{}}0-9]{{2,}}|@[0-9A-Za-z{gaps}]+)\s+(.+)$"
+  ))
+  .unwrap()
+}
+
 fn match_line(line: &str) -> Option<(&str, &str)> {
   let guard = REGEX.lock().unwrap();
-  let re = guard.get_or_init(|| Regex::new(r"^\s*([.-]{2,})\s+(.+)$").unwrap());
+  let re = guard.get_or_init(line_regex);
 
   let mut capture = re.captures_iter(line).map(|c| c.extract());
 
   capture
     .next()
     .map(|(_, [sequence, command])| (sequence, command.trim()))
+    .filter(|(sequence, _)| {
+      sequence.strip_prefix('@').map_or_else(
+        || is_valid_sequence(sequence),
+        |a| encode_alias(a).is_some(),
+      )
+    })
+}
+
+/// The morse of an alias, which starts and ends with a letter or digit and
+/// can have gap symbols between them (e.g. `S,O,S`).
+pub fn encode_alias(alias: &str) -> Option<String> {
+  let is_edge_valid = |edge: Option<char>| edge.is_some_and(|c| c.is_ascii_alphanumeric());
+
+  encode_text(alias)
+    .filter(|_| is_edge_valid(alias.chars().next()) && is_edge_valid(alias.chars().last()))
+    .filter(|sequence| sequence.len() >= 2)
+}
+
+/// Sequences can be patterns, as long as they only use known symbols and
+/// can't be shorter than two symbols.
+fn is_valid_sequence(sequence: &str) -> bool {
+  Pattern::parse(sequence).is_ok_and(|pattern| {
+    pattern.min_len() >= 2
+      && pattern
+        .symbols()
+        .iter()
+        .all(|c| SEQUENCE_SYMBOLS.contains(*c))
+  })
+}
+
+/// The sequence as it's matched, for sequences written as an alias or as
+/// symbols.
+fn translate(written: &str) -> Option<String> {
+  written.strip_prefix('@').map_or_else(
+    || is_valid_sequence(written).then(|| written.to_owned()),
+    encode_alias,
+  )
+}
+
+/// Whether the line ends with a `\` that continues the command on the next
+/// line (`\\` is an escaped backslash instead).
+pub fn continues(line: &str) -> bool {
+  let backslashes = line.chars().rev().take_while(|c| *c == '\\').count();
+  !backslashes.is_multiple_of(2)
+}
+
+/// Joins the lines that continue the ones before them, so that each command
+/// is a single item.
+pub fn join_continued<'a>(lines: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+  let mut joined: Vec<String> = vec![];
+  let mut continued = false;
+
+  for line in lines {
+    match joined.last_mut() {
+      Some(last) if continued => *last = format!("{last}\n{line}"),
+      _ => joined.push(line.to_owned()),
+    }
+    continued = continues(line);
+  }
+
+  joined
+}
+
+/// Splits the parameter (e.g. `#n`) from the start of the command.
+fn split_parameter(command: &str) -> (Option<Parameter>, &str) {
+  command
+    .split_once(char::is_whitespace)
+    .and_then(|(tag, rest)| Some((Some(Parameter::from_tag(tag)?), rest.trim_start())))
+    .unwrap_or((None, command))
 }
 
 impl Cmd {
   pub fn parse(line: &str) -> Result<Self> {
-    match match_line(line) {
-      Some((sequence, command)) => Ok(Self {
-        sequence: sequence.into(),
-        command: command.into(),
-      }),
-      None => {
-        bail!("Some commands have incorrect format")
-      }
+    let Some((written, rest)) = match_line(line) else {
+      bail!("Some commands have incorrect format")
+    };
+    let (options, after_options) = CommandOptions::split(rest);
+    let (parameter, command) = split_parameter(after_options);
+
+    Ok(Self {
+      annotation: None,
+      alias: written.strip_prefix('@').map(str::to_owned),
+      sequence: translate(written).unwrap_or_default(),
+      options,
+      parameter,
+      command: command.into(),
+      mode: DEFAULT_MODE.to_owned(),
+    })
+  }
+
+  pub fn annotated(self, annotation: Option<Annotation>) -> Self {
+    Self { annotation, ..self }
+  }
+
+  pub fn in_mode(self, mode: &str) -> Self {
+    Self {
+      mode: mode.to_owned(),
+      ..self
+    }
+  }
+
+  pub fn name(&self) -> Option<&str> {
+    self.annotation.as_ref().map(|a| a.name.as_str())
+  }
+}
+
+/// Commands as they are shown by [`Cmd`] (one after the other, each with its
+/// annotation above it, and the commands of each mode after its header).
+pub fn parse_commands(text: &str) -> Vec<Cmd> {
+  let mut commands = vec![];
+  let mut annotation = None;
+  let mut mode = DEFAULT_MODE.to_owned();
+
+  for line in join_continued(text.lines()) {
+    if let Ok(cmd) = Cmd::parse(&line) {
+      commands.push(cmd.annotated(annotation.take()).in_mode(&mode));
+    } else if let Some(header) = ModeHeader::parse(&line) {
+      mode = header.name;
+    } else {
+      annotation = Annotation::parse(&line);
+    }
+  }
+
+  commands
+}
+
+/// Commands as [`parse_commands`] reads them, with a header before the ones
+/// in another mode than the command before them.
+pub fn format_commands(commands: &[Cmd]) -> String {
+  let mut lines = vec![];
+  let mut mode = DEFAULT_MODE;
+
+  for cmd in commands {
+    if cmd.mode != mode {
+      mode = &cmd.mode;
+      lines.push(ModeHeader::new(mode).to_string());
+    }
+    lines.push(cmd.to_string());
+  }
+
+  lines.join("\n")
+}
+
+impl Display for Cmd {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    if let Some(annotation) = &self.annotation {
+      writeln!(f, "{annotation}")?;
+    }
+
+    match &self.alias {
+      Some(alias) => write!(f, "@{alias}")?,
+      None => write!(f, "{}", self.sequence)?,
     }
+
+    if !self.options.is_empty() {
+      write!(f, " {}", self.options)?;
+    }
+
+    if let Some(parameter) = self.parameter {
+      write!(f, " {}", parameter.tag())?;
+    }
+
+    write!(f, " {}", self.command)
   }
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use test_case::test_case;
 
   #[test]
   fn test_match_line() {
@@ -47,6 +296,25 @@ mod tests {
     assert_eq!(match_line("    yx  ..--  "), None);
     assert_eq!(match_line(" .-.-x.- xxx "), None);
     assert_eq!(match_line(" .-.- x .-.- y"), Some((".-.-", "x .-.- y")));
+    assert_eq!(match_line(" .,_:=- x"), Some((".,_:=-", "x")));
+    assert_eq!(match_line(" ,, x"), Some((",,", "x")));
+    assert_eq!(match_line(" rm -rf x"), None);
+    assert_eq!(match_line(".-U x"), Some((".-U", "x")));
+    assert_eq!(match_line("v>^< x"), Some(("v>^<", "x")));
+  }
+
+  #[test]
+  fn test_match_line_patterns() {
+    assert_eq!(match_line(".? x"), Some((".?", "x")));
+    assert_eq!(match_line(" -{3} x"), Some(("-{3}", "x")));
+    assert_eq!(match_line("(..|--)- x"), Some(("(..|--)-", "x")));
+    assert_eq!(match_line(".{1,3}- x"), Some((".{1,3}-", "x")));
+    assert_eq!(match_line("? x"), None);
+    assert_eq!(match_line(".{0,1} x"), None);
+    assert_eq!(match_line("(.. x"), None);
+    assert_eq!(match_line("((x)) y"), None);
+    assert_eq!(match_line("12 x"), None);
+    assert_eq!(match_line("{ x; }"), None);
   }
 
   #[test]
@@ -86,4 +354,113 @@ mod tests {
     assert_eq!(cmd.sequence, ".-.-");
     assert_eq!(cmd.command, "one .-.- two")
   }
+
+  #[test_case(".-.- #n echo $1", Some(Parameter::Number), "echo $1")]
+  #[test_case(".-.- #s  echo $1", Some(Parameter::Symbols), "echo $1")]
+  #[test_case(".-.- #x echo", None, "#x echo")]
+  #[test_case(".-.- #n", None, "#n")]
+  #[test_case(".-.- echo #n", None, "echo #n")]
+  fn test_command_parse_parameter(line: &str, parameter: Option<Parameter>, command: &str) {
+    let cmd = Cmd::parse(line).unwrap();
+    assert_eq!(cmd.parameter, parameter);
+    assert_eq!(cmd.command, command);
+  }
+
+  #[test_case("echo \\" => true; "continued")]
+  #[test_case("echo \\\\" => false; "escaped backslash")]
+  #[test_case("echo \\ " => false; "space after")]
+  #[test_case("echo" => false; "not continued")]
+  fn test_continues(line: &str) -> bool {
+    continues(line)
+  }
+
+  #[test]
+  fn test_join_continued() {
+    let lines = [".-.- a \\", "  b \\", "  c", "d", "-.-. e \\"];
+    assert_eq!(
+      join_continued(lines),
+      [".-.- a \\\n  b \\\n  c", "d", "-.-. e \\"]
+    );
+  }
+
+  fn annotation(name: &str, description: Option<&str>) -> Annotation {
+    Annotation {
+      name: name.to_owned(),
+      description: description.map(str::to_owned),
+    }
+  }
+
+  #[test_case("# @name lock-screen: Lock the screen" => Some(annotation("lock-screen", Some("Lock the screen"))); "description")]
+  #[test_case("  #@name lock_2  " => Some(annotation("lock_2", None)); "no description")]
+  #[test_case("# @name lock:" => Some(annotation("lock", None)); "empty description")]
+  #[test_case("# @name" => None; "no name")]
+  #[test_case("# name lock" => None; "not an annotation")]
+  fn test_annotation_parse(line: &str) -> Option<Annotation> {
+    Annotation::parse(line)
+  }
+
+  #[test]
+  fn test_parse_commands() {
+    let cmd = Cmd::parse(".-.- \\\n  echo a")
+      .unwrap()
+      .annotated(Annotation::parse("# @name a: Say a"));
+    let text = format!("{cmd}\n..-- echo b");
+    let commands = parse_commands(&text);
+    assert_eq!(commands.len(), 2);
+    assert_eq!(commands[0].to_string(), cmd.to_string());
+    assert_eq!(commands[0].name(), Some("a"));
+    assert_eq!(commands[1].annotation, None);
+  }
+
+  #[test]
+  fn test_format_commands_modes() {
+    let text = ".-.- echo a\n[mode browser]\n..-- echo b\n[mode default]\n-..- echo c";
+    let commands = parse_commands(text);
+    let modes: Vec<&str> = commands.iter().map(|c| c.mode.as_str()).collect();
+    assert_eq!(modes, ["default", "browser", "default"]);
+    assert_eq!(format_commands(&commands), text);
+  }
+
+  #[test]
+  fn test_command_parse_options() {
+    let cmd = Cmd::parse(".-.- [interpreter=python3] #s print('$1')").unwrap();
+    assert_eq!(cmd.options.interpreter.as_deref(), Some("python3"));
+    assert_eq!(cmd.parameter, Some(Parameter::Symbols));
+    assert_eq!(cmd.command, "print('$1')");
+  }
+
+  #[test]
+  fn test_command_parse_multiple_lines() {
+    let cmd = Cmd::parse(".-.- #n \\\n  echo $1; \\\n  echo b").unwrap();
+    assert_eq!(cmd.parameter, Some(Parameter::Number));
+    assert_eq!(cmd.command, "\\\n  echo $1; \\\n  echo b");
+  }
+
+  #[test_case(".-.- #n echo $1")]
+  #[test_case(".-.- echo x")]
+  #[test_case(".-.- echo a \\\n  && echo b")]
+  #[test_case(".-.- [cwd=/tmp env.A=1] #n echo $1")]
+  #[test_case(".-.- [ -f x ] && echo x")]
+  #[test_case("@S,O,S [timeout=2s] echo x")]
+  fn test_display_round_trip(line: &str) {
+    assert_eq!(Cmd::parse(line).unwrap().to_string(), line);
+  }
+
+  #[test_case("@SOS lock" => Some(("...---...".to_owned(), "lock".to_owned())); "concatenated")]
+  #[test_case("@k,b x" => Some(("-.-,-...".to_owned(), "x".to_owned())); "gap")]
+  #[test_case("@E x" => None; "too short")]
+  #[test_case("@,E x" => None; "starts with gap")]
+  #[test_case("@S! x" => None; "unknown character")]
+  #[test_case("@ x" => None; "empty")]
+  fn test_command_parse_alias(line: &str) -> Option<(String, String)> {
+    Cmd::parse(line).ok().map(|cmd| (cmd.sequence, cmd.command))
+  }
+
+  #[test]
+  fn test_parameter_decode() {
+    assert_eq!(Parameter::Number.decode("...--"), Some("3".to_owned()));
+    assert_eq!(Parameter::Number.decode("...-"), None);
+    assert_eq!(Parameter::Symbols.decode(".-U"), Some(".-U".to_owned()));
+    assert_eq!(Parameter::Symbols.decode(""), None);
+  }
 }
@@ -0,0 +1,275 @@
+use std::{cell::OnceCell, fmt::Display, path::PathBuf, sync::Mutex, time::Duration};
+
+use regex::Regex;
+
+use crate::{active_window::ActiveWindow, util::expand_home};
+
+/// Options written in brackets after the sequence, e.g.
+/// `[cwd=~/proj timeout=30s interpreter=bash switch=browser class=firefox
+/// env.FOO=1]`. Values with spaces or `]` are quoted (`title="a - b"`).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CommandOptions {
+  /// Working directory (`~` is the home directory).
+  pub cwd: Option<String>,
+  /// The process is killed if it's still running after this long.
+  pub timeout: Option<Duration>,
+  /// Runs the command with this instead of the default interpreter.
+  pub interpreter: Option<String>,
+  /// Switches to this mode when the sequence matches.
+  pub switch: Option<String>,
+  /// Only runs when the class of the active window matches this pattern.
+  pub class: Option<String>,
+  /// Only runs when the title of the active window matches this pattern.
+  pub title: Option<String>,
+  /// Environment variables.
+  pub env: Vec<(String, String)>,
+}
+
+/// `30` and `30s` are seconds, `500ms` milliseconds, `2m` minutes.
+pub fn parse_timeout(value: &str) -> Option<Duration> {
+  let unit_start = value
+    .find(|c: char| !c.is_ascii_digit())
+    .unwrap_or(value.len());
+  let (number, unit) = value.split_at(unit_start);
+  let amount: u64 = number.parse().ok()?;
+
+  match unit {
+    "ms" => Some(Duration::from_millis(amount)),
+    "" | "s" => Some(Duration::from_secs(amount)),
+    "m" => Some(Duration::from_mins(amount)),
+    _ => None,
+  }
+}
+
+pub fn format_timeout(timeout: Duration) -> String {
+  if timeout.subsec_millis() == 0 {
+    format!("{}s", timeout.as_secs())
+  } else {
+    format!("{}ms", timeout.as_millis())
+  }
+}
+
+/// Window patterns are regular expressions that ignore case, and match
+/// anywhere in the value.
+fn window_regex(pattern: &str) -> Option<Regex> {
+  Regex::new(&format!("(?i){pattern}")).ok()
+}
+
+pub fn is_window_pattern(pattern: &str) -> bool {
+  window_regex(pattern).is_some()
+}
+
+fn window_pattern(value: &str) -> Option<String> {
+  is_window_pattern(value).then(|| value.to_owned())
+}
+
+fn is_variable_name(name: &str) -> bool {
+  !name.is_empty()
+    && !name.starts_with(|c: char| c.is_ascii_digit())
+    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Splits `key=value` (or `key="value"`) from the start of the text, and
+/// returns the rest.
+fn next_item(text: &str) -> Option<(&str, &str, &str)> {
+  let (key, rest) = text
+    .split_once('=')
+    .filter(|(k, _)| !k.contains(char::is_whitespace))?;
+
+  let (value, after) = match rest.strip_prefix('"') {
+    Some(quoted) => quoted
+      .split_once('"')
+      .filter(|(_, a)| a.is_empty() || a.starts_with(char::is_whitespace))?,
+    None => rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len())),
+  };
+
+  (!value.is_empty()).then(|| (key, value, after.trim_start()))
+}
+
+/// Values are quoted when they couldn't be read back otherwise.
+fn format_value(value: &str) -> String {
+  if value.contains(|c: char| c.is_whitespace() || c == ']') || value.starts_with('"') {
+    format!("\"{value}\"")
+  } else {
+    value.to_owned()
+  }
+}
+
+static OPTIONS_REGEX: Mutex<OnceCell<Regex>> = Mutex::new(OnceCell::new());
+
+impl CommandOptions {
+  pub fn is_empty(&self) -> bool {
+    *self == Self::default()
+  }
+
+  fn set(&mut self, key: &str, value: &str) -> Option<()> {
+    match key {
+      "cwd" => self.cwd = Some(value.to_owned()),
+      "timeout" => self.timeout = Some(parse_timeout(value)?),
+      "interpreter" => self.interpreter = Some(value.to_owned()),
+      "switch" => self.switch = Some(value.to_owned()),
+      "class" => self.class = Some(window_pattern(value)?),
+      "title" => self.title = Some(window_pattern(value)?),
+      _ => {
+        let name = key.strip_prefix("env.").filter(|n| is_variable_name(n))?;
+        self.env.push((name.to_owned(), value.to_owned()));
+      }
+    }
+
+    Some(())
+  }
+
+  /// Every item has to be a known option with a value.
+  fn parse(text: &str) -> Option<Self> {
+    let mut options = Self::default();
+    let mut rest = text.trim();
+
+    while !rest.is_empty() {
+      let (key, value, after) = next_item(rest)?;
+      options.set(key, value)?;
+      rest = after;
+    }
+
+    Some(options)
+  }
+
+  /// Splits the options from the start of the command. Brackets that don't
+  /// only have options are part of the command (e.g. `[ -f file ]`).
+  pub fn split(command: &str) -> (Self, &str) {
+    let guard = OPTIONS_REGEX.lock().unwrap();
+    let re =
+      guard.get_or_init(|| Regex::new(r#"(?s)^\[((?:"[^"\n]*"|[^\]\n"])+)\]\s+(.+)$"#).unwrap());
+
+    re.captures(command)
+      .and_then(|c| Some((Self::parse(c.get(1)?.as_str())?, c.get(2)?.as_str())))
+      .unwrap_or_else(|| (Self::default(), command))
+  }
+
+  pub fn working_dir(&self) -> Option<PathBuf> {
+    self.cwd.as_deref().map(expand_home)
+  }
+
+  pub const fn has_window_condition(&self) -> bool {
+    self.class.is_some() || self.title.is_some()
+  }
+
+  /// Whether the window's class and title match the patterns that are set.
+  pub fn matches_window(&self, window: &ActiveWindow) -> bool {
+    let matches = |pattern: Option<&str>, value: &str| {
+      pattern.is_none_or(|p| window_regex(p).is_some_and(|re| re.is_match(value)))
+    };
+
+    matches(self.class.as_deref(), &window.class) && matches(self.title.as_deref(), &window.title)
+  }
+}
+
+impl Display for CommandOptions {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let named = [
+      ("cwd", self.cwd.clone()),
+      ("timeout", self.timeout.map(format_timeout)),
+      ("interpreter", self.interpreter.clone()),
+      ("switch", self.switch.clone()),
+      ("class", self.class.clone()),
+      ("title", self.title.clone()),
+    ];
+    let env = (self.env.iter()).map(|(name, value)| format!("env.{name}={}", format_value(value)));
+    let items: Vec<String> = (named.into_iter())
+      .filter_map(|(key, value)| Some(format!("{key}={}", format_value(&value?))))
+      .chain(env)
+      .collect();
+
+    write!(f, "[{}]", items.join(" "))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use home::home_dir;
+  use test_case::test_case;
+
+  #[test_case("30" => Some(Duration::from_secs(30)); "seconds by default")]
+  #[test_case("30s" => Some(Duration::from_secs(30)); "seconds")]
+  #[test_case("500ms" => Some(Duration::from_millis(500)); "milliseconds")]
+  #[test_case("2m" => Some(Duration::from_mins(2)); "minutes")]
+  #[test_case("2h" => None; "unknown unit")]
+  #[test_case("s" => None; "no number")]
+  fn test_parse_timeout(value: &str) -> Option<Duration> {
+    parse_timeout(value)
+  }
+
+  #[test]
+  fn test_split() {
+    let (options, command) =
+      CommandOptions::split("[cwd=~/proj timeout=30s interpreter=bash env.FOO=1] make all");
+    assert_eq!(command, "make all");
+    assert_eq!(options.cwd.as_deref(), Some("~/proj"));
+    assert_eq!(options.timeout, Some(Duration::from_secs(30)));
+    assert_eq!(options.interpreter.as_deref(), Some("bash"));
+    assert_eq!(options.env, [("FOO".to_owned(), "1".to_owned())]);
+  }
+
+  #[test_case("[ -f file ] && echo a"; "test command")]
+  #[test_case("[[ -f file ]] && echo a"; "double brackets")]
+  #[test_case("[cwd=] echo a"; "empty value")]
+  #[test_case("[user=me] echo a"; "unknown option")]
+  #[test_case("[env.1A=1] echo a"; "invalid variable")]
+  #[test_case("[timeout=soon] echo a"; "invalid timeout")]
+  #[test_case("[class=(firefox] echo a"; "invalid pattern")]
+  #[test_case("[cwd=/tmp]"; "no command")]
+  #[test_case("[cwd=\"/tmp] echo a"; "unterminated quote")]
+  #[test_case("[cwd=\"/tmp\"x] echo a"; "text after the quote")]
+  #[test_case("[cwd=\"\"] echo a"; "empty quoted value")]
+  fn test_split_not_options(command: &str) {
+    assert_eq!(
+      CommandOptions::split(command),
+      (CommandOptions::default(), command)
+    );
+  }
+
+  #[test_case("[cwd=/tmp timeout=1500ms env.A=x env.B=y]")]
+  #[test_case("[timeout=2s interpreter=python3]")]
+  #[test_case("[switch=browser env.A=x]")]
+  #[test_case("[class=^firefox$ title=GitHub]")]
+  #[test_case("[cwd=\"~/My Projects\" title=\"GitHub - Firefox\"]")]
+  #[test_case("[title=\"[draft]\" env.A=\"x y\"]")]
+  fn test_display_round_trip(text: &str) {
+    let (options, _) = CommandOptions::split(&format!("{text} cmd"));
+    assert_eq!(options.to_string(), text);
+  }
+
+  #[test_case("[class=firefox]", "Firefox", "GitHub" => true; "class ignores case")]
+  #[test_case("[title=\"GitHub - Firefox\"]", "firefox", "GitHub - Firefox" => true; "quoted title")]
+  #[test_case("[class=^term]", "Alacritty", "" => false; "class anchored")]
+  #[test_case("[class=firefox title=GitHub]", "firefox", "Inbox" => false; "both have to match")]
+  #[test_case("[title=vim|nano]", "xterm", "nano notes.md" => true; "title alternatives")]
+  fn test_matches_window(options: &str, class: &str, title: &str) -> bool {
+    let (parsed, _) = CommandOptions::split(&format!("{options} cmd"));
+    let window = ActiveWindow {
+      class: class.to_owned(),
+      title: title.to_owned(),
+    };
+    parsed.matches_window(&window)
+  }
+
+  #[test]
+  fn test_split_quoted_values() {
+    let (options, command) =
+      CommandOptions::split("[cwd=\"~/My Projects\" title=\"a ] b\" env.A=\"x\"] echo a");
+    assert_eq!(command, "echo a");
+    assert_eq!(options.cwd.as_deref(), Some("~/My Projects"));
+    assert_eq!(options.title.as_deref(), Some("a ] b"));
+    assert_eq!(options.env, [("A".to_owned(), "x".to_owned())]);
+  }
+
+  #[test]
+  fn test_working_dir() {
+    let options = CommandOptions {
+      cwd: Some("~/proj".to_owned()),
+      ..CommandOptions::default()
+    };
+    assert_eq!(options.working_dir(), home_dir().map(|h| h.join("proj")));
+    assert_eq!(CommandOptions::default().working_dir(), None);
+  }
+}
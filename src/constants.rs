@@ -2,17 +2,39 @@ use home::home_dir;
 
 pub static DEFAULT_PORT: u16 = 17757;
 static DEFAULT_CONFIG_FILE_NAME: &str = ".horsetab.conf";
+static DEFAULT_CONF_D_DIR_NAME: &str = ".config/horsetab/conf.d";
+static DEFAULT_CALIBRATION_FILE_NAME: &str = ".horsetab_calibration";
 
 pub static DEFAULT_INTERPRETER: &str = "sh";
 
+/// Prints the class and the title of the active window, on separate lines.
+pub static DEFAULT_WINDOW_COMMAND: &str =
+  "xdotool getactivewindow getwindowclassname getwindowname";
+
+pub static DEFAULT_LONG_PRESS_MS: u128 = 200;
+pub static DEFAULT_SEQUENCE_RESET_MS: u128 = 500;
+pub static DEFAULT_ARM_TIMEOUT_MS: u128 = 5000;
+
 pub static DEFAULT_COMMAND_CONFIG_FILE_CONTENT: &str =
   include_str!("../assets/default_config.conf");
 
-pub fn get_default_config_path() -> String {
+fn get_home_file_path(file_name: &str) -> String {
   home_dir()
-    .map(|p| p.join(DEFAULT_CONFIG_FILE_NAME))
+    .map(|p| p.join(file_name))
     .unwrap_or_default()
     .to_str()
     .unwrap_or_default()
     .to_owned()
 }
+
+pub fn get_default_config_path() -> String {
+  get_home_file_path(DEFAULT_CONFIG_FILE_NAME)
+}
+
+pub fn get_default_conf_d_path() -> String {
+  get_home_file_path(DEFAULT_CONF_D_DIR_NAME)
+}
+
+pub fn get_default_calibration_path() -> String {
+  get_home_file_path(DEFAULT_CALIBRATION_FILE_NAME)
+}
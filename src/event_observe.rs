@@ -3,11 +3,19 @@ use serde::{Deserialize, Serialize};
 use crate::util::PayloadOverwriter;
 use std::{collections::HashMap, io::Write, net::TcpStream, sync::Mutex};
 
-#[derive(Copy, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub enum EventType {
   SequenceReset,
   FoundResults,
+  /// Found results, with the names of the commands that have one.
+  FoundNamedResults(Vec<String>),
   SequenceItem(char),
+  Armed,
+  Disarmed,
+  /// A sequence didn't match, and these are just as close to it.
+  NearMiss(Vec<String>),
+  /// The mode the commands are matched in changed (to this one).
+  ModeChanged(String),
 }
 
 pub fn notify_watch_observers<I, W>(events: I, observers: &Mutex<HashMap<u16, W>>)
@@ -0,0 +1,163 @@
+use crate::{
+  sequence_automata::AutomataInstruction,
+  sequence_pattern::{Pattern, PatternNfa},
+};
+
+/// Each edit needs this many clicked symbols, so that short bursts (e.g. a
+/// double click) don't match approximately.
+pub const SYMBOLS_PER_EDIT: usize = 3;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FuzzyResult {
+  /// Ids of the sequences closest to the input (the same sequence can be
+  /// configured more than once).
+  Match(Vec<usize>),
+  /// Different sequences are just as close, so none of them is chosen.
+  Ambiguous(Vec<String>),
+}
+
+/// Matches a sequence that ended without matching to the closest configured
+/// one, allowing some clicks to be missing, extra or wrong.
+pub struct FuzzyMatcher {
+  nfa: PatternNfa,
+  sequences: Vec<String>,
+  max_distance: usize,
+}
+
+impl FuzzyMatcher {
+  pub fn new(sequences: &[&str], max_distance: usize) -> Self {
+    let patterns: Vec<Pattern> = sequences
+      .iter()
+      .map(|s| Pattern::parse(s).unwrap_or_else(|_| Pattern::nothing()))
+      .collect();
+
+    Self {
+      nfa: PatternNfa::new(&patterns),
+      sequences: sequences.iter().map(|s| (*s).to_owned()).collect(),
+      max_distance,
+    }
+  }
+
+  fn max_distance_for(&self, input: &str) -> usize {
+    let limit = input.chars().count().div_euclid(SYMBOLS_PER_EDIT);
+    self.max_distance.min(limit)
+  }
+
+  /// Ids of the closest sequences, in the order they are configured.
+  fn closest_ids(&self, input: &str) -> Option<Vec<usize>> {
+    let distances = self.nfa.distances(input);
+    let best = *distances.values().min()?;
+
+    if best > self.max_distance_for(input) {
+      return None;
+    }
+
+    let mut ids: Vec<usize> = distances
+      .into_iter()
+      .filter(|(_, distance)| *distance == best)
+      .map(|(id, _)| id)
+      .collect();
+    ids.sort_unstable();
+    Some(ids)
+  }
+
+  pub fn closest(&self, input: &str) -> Option<FuzzyResult> {
+    let ids = self.closest_ids(input)?;
+    let mut sequences: Vec<String> = vec![];
+
+    for id in &ids {
+      if !sequences.contains(&self.sequences[*id]) {
+        sequences.push(self.sequences[*id].clone());
+      }
+    }
+
+    Some(if sequences.len() == 1 {
+      FuzzyResult::Match(ids)
+    } else {
+      FuzzyResult::Ambiguous(sequences)
+    })
+  }
+}
+
+/// Symbols clicked since the last reset, kept until the sequence ends in case
+/// nothing matches.
+#[derive(Default)]
+pub struct Burst {
+  symbols: String,
+  matched: bool,
+}
+
+impl Burst {
+  /// Returns the symbols when the sequence ends without matching anything.
+  pub fn put(&mut self, instruction: AutomataInstruction, matched: bool) -> Option<String> {
+    self.matched |= matched;
+
+    match instruction {
+      AutomataInstruction::Char(c) => self.symbols.push(c),
+      AutomataInstruction::Reset => {
+        let ended = std::mem::take(self);
+        return (!ended.matched && !ended.symbols.is_empty()).then_some(ended.symbols);
+      }
+      AutomataInstruction::Arm
+      | AutomataInstruction::Disarm
+      | AutomataInstruction::SentStart
+      | AutomataInstruction::SentEnd
+      | AutomataInstruction::Wake
+      | AutomataInstruction::Press => {}
+    }
+
+    None
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test_case::test_case;
+
+  #[test_case(".-." => Some(FuzzyResult::Match(vec![0])); "missing symbol")]
+  #[test_case("..-.." => Some(FuzzyResult::Match(vec![0])); "extra symbol")]
+  #[test_case("---.." => Some(FuzzyResult::Match(vec![2])); "pattern")]
+  #[test_case("....-" => None; "too far")]
+  #[test_case("..-" => Some(FuzzyResult::Ambiguous(vec!["..-.".to_owned(), "..--".to_owned()])); "ambiguous")]
+  fn test_closest(input: &str) -> Option<FuzzyResult> {
+    let matcher = FuzzyMatcher::new(&["..-.", "..--", "-{3}.?", "(.."], 1);
+    matcher.closest(input)
+  }
+
+  #[test]
+  fn test_closest_same_sequence_twice() {
+    let matcher = FuzzyMatcher::new(&["..-.", "-..-", "..-."], 1);
+    assert_eq!(
+      matcher.closest("...-."),
+      Some(FuzzyResult::Match(vec![0, 2]))
+    );
+  }
+
+  #[test]
+  fn test_closest_short_input() {
+    let matcher = FuzzyMatcher::new(&["..-"], 1);
+    assert_eq!(matcher.closest(".-"), None);
+    assert_eq!(matcher.closest("..--"), Some(FuzzyResult::Match(vec![0])));
+  }
+
+  fn put_all(burst: &mut Burst, symbols: &str) {
+    for c in symbols.chars() {
+      assert_eq!(burst.put(AutomataInstruction::Char(c), false), None);
+    }
+  }
+
+  #[test]
+  fn test_burst() {
+    let mut burst = Burst::default();
+    put_all(&mut burst, "..-");
+    assert_eq!(
+      burst.put(AutomataInstruction::Reset, false),
+      Some("..-".to_owned())
+    );
+    assert_eq!(burst.put(AutomataInstruction::Reset, false), None);
+    put_all(&mut burst, "..");
+    assert_eq!(burst.put(AutomataInstruction::Char('-'), true), None);
+    assert_eq!(burst.put(AutomataInstruction::Reset, false), None);
+  }
+}
@@ -0,0 +1,91 @@
+use crate::input_settings::{GESTURE_DOWN, GESTURE_LEFT, GESTURE_RIGHT, GESTURE_UP};
+
+fn direction_symbol(dx: f64, dy: f64) -> char {
+  if dx.abs() > dy.abs() {
+    if dx > 0.0 {
+      GESTURE_RIGHT
+    } else {
+      GESTURE_LEFT
+    }
+  } else if dy > 0.0 {
+    GESTURE_DOWN
+  } else {
+    GESTURE_UP
+  }
+}
+
+/// Splits the pointer movement made while a button is held into strokes,
+/// producing one symbol each time the direction changes.
+pub struct GestureDetector {
+  origin: (f64, f64),
+  last_symbol: Option<char>,
+  min_distance: f64,
+}
+
+impl GestureDetector {
+  pub const fn new(origin: (f64, f64), min_distance: f64) -> Self {
+    Self {
+      origin,
+      last_symbol: None,
+      min_distance,
+    }
+  }
+
+  pub fn moved(&mut self, (x, y): (f64, f64)) -> Option<char> {
+    let (dx, dy) = (x - self.origin.0, y - self.origin.1);
+
+    if dx.hypot(dy) < self.min_distance {
+      return None;
+    }
+
+    self.origin = (x, y);
+    let symbol = direction_symbol(dx, dy);
+
+    if self.last_symbol == Some(symbol) {
+      None
+    } else {
+      self.last_symbol = Some(symbol);
+      Some(symbol)
+    }
+  }
+
+  pub const fn has_gestures(&self) -> bool {
+    self.last_symbol.is_some()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn run(points: &[(f64, f64)]) -> String {
+    let mut detector = GestureDetector::new((0.0, 0.0), 50.0);
+    points.iter().filter_map(|p| detector.moved(*p)).collect()
+  }
+
+  #[test]
+  fn test_small_movement_is_not_a_gesture() {
+    let mut detector = GestureDetector::new((100.0, 100.0), 50.0);
+    assert_eq!(detector.moved((120.0, 130.0)), None);
+    assert!(!detector.has_gestures());
+  }
+
+  #[test]
+  fn test_single_stroke() {
+    assert_eq!(
+      run(&[(0.0, 30.0), (0.0, 60.0), (5.0, 120.0), (5.0, 200.0)]),
+      "v"
+    );
+  }
+
+  #[test]
+  fn test_multiple_strokes() {
+    assert_eq!(run(&[(0.0, -60.0), (70.0, -60.0), (0.0, -60.0)]), "^><");
+  }
+
+  #[test]
+  fn test_diagonal_uses_dominant_axis() {
+    assert_eq!(run(&[(60.0, 40.0)]), ">");
+    assert_eq!(run(&[(-40.0, 60.0)]), "v");
+  }
+}
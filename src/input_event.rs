@@ -0,0 +1,204 @@
+use anyhow::{bail, Context, Result};
+use rdev::Key;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::str::FromStr;
+
+use crate::input_settings::{parse_key, InputButton};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InputEventKind {
+  ButtonPress(Option<InputButton>),
+  ButtonRelease(Option<InputButton>),
+  KeyPress(Key),
+  KeyRelease(Key),
+  MouseMove(f64, f64),
+  Wheel(i64),
+}
+
+/// An input event, with its time in milliseconds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct InputEvent {
+  pub time_ms: u128,
+  pub kind: InputEventKind,
+}
+
+struct ButtonName(Option<InputButton>);
+
+impl Display for ButtonName {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self.0 {
+      Some(button) => write!(f, "{button}"),
+      None => write!(f, "other"),
+    }
+  }
+}
+
+struct KeyName(Key);
+
+impl Display for KeyName {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self.0 {
+      Key::Unknown(code) => write!(f, "{code}"),
+      key => write!(f, "{key:?}"),
+    }
+  }
+}
+
+impl Display for InputEvent {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let time = self.time_ms;
+
+    match self.kind {
+      InputEventKind::ButtonPress(b) => write!(f, "{time} press {}", ButtonName(b)),
+      InputEventKind::ButtonRelease(b) => write!(f, "{time} release {}", ButtonName(b)),
+      InputEventKind::KeyPress(key) => write!(f, "{time} key-press {}", KeyName(key)),
+      InputEventKind::KeyRelease(key) => write!(f, "{time} key-release {}", KeyName(key)),
+      InputEventKind::MouseMove(x, y) => write!(f, "{time} move {x} {y}"),
+      InputEventKind::Wheel(delta) => write!(f, "{time} wheel {delta}"),
+    }
+  }
+}
+
+fn parse_button(name: &str) -> Result<Option<InputButton>> {
+  if name == "other" {
+    return Ok(None);
+  }
+
+  InputButton::from_name(name)
+    .map(Some)
+    .with_context(|| format!("Unknown button `{name}`"))
+}
+
+fn parse_key_name(name: &str) -> Result<Key> {
+  parse_key(name).with_context(|| format!("Unknown key `{name}`"))
+}
+
+fn parse_kind(name: &str, args: &[&str]) -> Result<InputEventKind> {
+  Ok(match (name, args) {
+    ("press", [b]) => InputEventKind::ButtonPress(parse_button(b)?),
+    ("release", [b]) => InputEventKind::ButtonRelease(parse_button(b)?),
+    ("key-press", [k]) => InputEventKind::KeyPress(parse_key_name(k)?),
+    ("key-release", [k]) => InputEventKind::KeyRelease(parse_key_name(k)?),
+    ("move", [x, y]) => InputEventKind::MouseMove(x.parse()?, y.parse()?),
+    ("wheel", [delta]) => InputEventKind::Wheel(delta.parse()?),
+    _ => bail!("Unknown input event `{name}`"),
+  })
+}
+
+impl FromStr for InputEvent {
+  type Err = anyhow::Error;
+
+  fn from_str(line: &str) -> Result<Self> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+
+    let [time, name, args @ ..] = words.as_slice() else {
+      bail!("Incorrect input event `{line}`");
+    };
+
+    Ok(Self {
+      time_ms: time.parse()?,
+      kind: parse_kind(name, args)?,
+    })
+  }
+}
+
+/// Parses recorded events, skipping empty lines and comments.
+pub fn parse_events<I, S>(lines: I) -> Result<Vec<InputEvent>>
+where
+  I: Iterator<Item = S>,
+  S: AsRef<str>,
+{
+  lines
+    .enumerate()
+    .filter(|(_, line)| !line.as_ref().trim().is_empty() && !line.as_ref().starts_with('#'))
+    .map(|(i, line)| {
+      line
+        .as_ref()
+        .parse()
+        .with_context(|| format!("Line {}", i + 1))
+    })
+    .collect()
+}
+
+pub fn read_recording(path: &str) -> Result<Vec<InputEvent>> {
+  let file = File::open(path).with_context(|| format!("Cannot open {path}"))?;
+  let lines = BufReader::new(file)
+    .lines()
+    .collect::<Result<Vec<String>, _>>()?;
+  parse_events(lines.iter())
+}
+
+/// Writes events to a file, with times relative to the first one.
+pub struct InputRecorder {
+  out: BufWriter<File>,
+  start_ms: Option<u128>,
+}
+
+impl InputRecorder {
+  pub fn create(path: &str) -> Result<Self> {
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "# horsetab input recording")?;
+
+    Ok(Self {
+      out,
+      start_ms: None,
+    })
+  }
+
+  pub fn write(&mut self, event: InputEvent) -> Result<()> {
+    let start_ms = *self.start_ms.get_or_insert(event.time_ms);
+
+    let relative = InputEvent {
+      time_ms: event.time_ms.saturating_sub(start_ms),
+      ..event
+    };
+
+    writeln!(self.out, "{relative}")?;
+    Ok(self.out.flush()?)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test_case::test_case;
+
+  #[test_case("0 press left", InputEventKind::ButtonPress(Some(InputButton::Left)))]
+  #[test_case("0 release other", InputEventKind::ButtonRelease(None))]
+  #[test_case("0 key-press ScrollLock", InputEventKind::KeyPress(Key::ScrollLock))]
+  #[test_case("0 key-release 78", InputEventKind::KeyRelease(Key::Unknown(78)))]
+  #[test_case("0 move 10.5 -3", InputEventKind::MouseMove(10.5, -3.0))]
+  #[test_case("0 wheel -1", InputEventKind::Wheel(-1))]
+  fn test_round_trip(line: &str, kind: InputEventKind) {
+    let event: InputEvent = line.parse().unwrap();
+    assert_eq!(event, InputEvent { time_ms: 0, kind });
+    assert_eq!(event.to_string(), line);
+  }
+
+  #[test_case("press left")]
+  #[test_case("10 press")]
+  #[test_case("10 press nose")]
+  #[test_case("10 jump")]
+  #[test_case("10 move 1")]
+  #[test_case("")]
+  fn test_parse_error(line: &str) {
+    assert!(line.parse::<InputEvent>().is_err());
+  }
+
+  #[test]
+  fn test_parse_events() {
+    let lines = ["# comment", "", "5 press left", "  ", "90 release left"];
+    let events = parse_events(lines.iter()).unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[1].time_ms, 90);
+  }
+
+  #[test]
+  fn test_parse_events_error_line_number() {
+    let lines = ["# comment", "5 press left", "x"];
+    let err = parse_events(lines.iter()).err().unwrap();
+    assert_eq!(err.to_string(), "Line 3");
+  }
+}
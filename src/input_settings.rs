@@ -0,0 +1,239 @@
+use rdev::Key;
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{click_sequence_detector::MouseClickKind, timing_settings::parse_flag};
+
+/// Symbols produced by each button, as `(short, long)`.
+static LEFT_SYMBOLS: (char, char) = ('.', '-');
+static RIGHT_SYMBOLS: (char, char) = (',', '_');
+static MIDDLE_SYMBOLS: (char, char) = (':', '=');
+
+pub static WHEEL_UP: char = 'U';
+pub static WHEEL_DOWN: char = 'D';
+
+/// Pointer strokes made while a button is held.
+pub static GESTURE_UP: char = '^';
+pub static GESTURE_DOWN: char = 'v';
+pub static GESTURE_LEFT: char = '<';
+pub static GESTURE_RIGHT: char = '>';
+
+/// Every symbol a sequence can be made of.
+pub static SEQUENCE_SYMBOLS: &str = ".-,_:=UD^v<>";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum InputButton {
+  Left,
+  Right,
+  Middle,
+}
+
+impl InputButton {
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name.to_lowercase().as_ref() {
+      "left" => Some(Self::Left),
+      "right" => Some(Self::Right),
+      "middle" => Some(Self::Middle),
+      _ => None,
+    }
+  }
+
+  const fn symbols(self) -> (char, char) {
+    match self {
+      Self::Left => LEFT_SYMBOLS,
+      Self::Right => RIGHT_SYMBOLS,
+      Self::Middle => MIDDLE_SYMBOLS,
+    }
+  }
+
+  pub const fn symbol(self, click_kind: MouseClickKind) -> char {
+    let (short, long) = self.symbols();
+
+    match click_kind {
+      MouseClickKind::Short => short,
+      MouseClickKind::Long => long,
+    }
+  }
+
+  pub fn of_symbol(symbol: char) -> Option<Self> {
+    [Self::Left, Self::Right, Self::Middle]
+      .into_iter()
+      .find(|b| b.symbols().0 == symbol || b.symbols().1 == symbol)
+  }
+}
+
+impl Display for InputButton {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let name = match self {
+      Self::Left => "left",
+      Self::Right => "right",
+      Self::Middle => "middle",
+    };
+
+    write!(f, "{name}")
+  }
+}
+
+/// Keys that can be used to tap sequences (their names are the ones `rdev`
+/// uses). Any other key can be given by its numeric code.
+static TRIGGER_KEYS: [Key; 30] = [
+  Key::Alt,
+  Key::AltGr,
+  Key::CapsLock,
+  Key::ControlLeft,
+  Key::ControlRight,
+  Key::MetaLeft,
+  Key::MetaRight,
+  Key::ShiftLeft,
+  Key::ShiftRight,
+  Key::PrintScreen,
+  Key::ScrollLock,
+  Key::Pause,
+  Key::NumLock,
+  Key::Insert,
+  Key::Home,
+  Key::End,
+  Key::PageUp,
+  Key::PageDown,
+  Key::F1,
+  Key::F2,
+  Key::F3,
+  Key::F4,
+  Key::F5,
+  Key::F6,
+  Key::F7,
+  Key::F8,
+  Key::F9,
+  Key::F10,
+  Key::F11,
+  Key::F12,
+];
+
+pub fn parse_key(name: &str) -> Option<Key> {
+  name.parse().map(Key::Unknown).ok().or_else(|| {
+    TRIGGER_KEYS
+      .iter()
+      .copied()
+      .find(|key| format!("{key:?}").eq_ignore_ascii_case(name))
+  })
+}
+
+/// Parses a key setting, where `none` disables the key, and invalid names keep
+/// the current one.
+pub fn override_key(value: Option<&str>, current: Option<Key>) -> Option<Key> {
+  match value {
+    Some("none") => None,
+    Some(name) => parse_key(name).or(current),
+    None => current,
+  }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct InputSettings {
+  pub button: Option<InputButton>,
+  pub button_symbols: bool,
+  pub key: Option<Key>,
+  pub wheel: bool,
+  pub gestures: bool,
+}
+
+impl InputSettings {
+  pub fn with_overrides(self, settings: &HashMap<String, String>) -> Self {
+    Self {
+      button: match settings.get("BUTTON").map(String::as_ref) {
+        Some("any") => None,
+        Some(name) => InputButton::from_name(name).or(self.button),
+        None => self.button,
+      },
+      button_symbols: parse_flag(settings, "BUTTON_SYMBOLS").unwrap_or(self.button_symbols),
+      key: override_key(settings.get("KEY").map(String::as_ref), self.key),
+      wheel: parse_flag(settings, "WHEEL").unwrap_or(self.wheel),
+      gestures: parse_flag(settings, "GESTURES").unwrap_or(self.gestures),
+    }
+  }
+
+  /// Which button's symbols a click produces, or `None` if the click must be
+  /// ignored. Unrecognized buttons are treated as the left one.
+  pub fn symbols_for(self, button: Option<InputButton>) -> Option<InputButton> {
+    match self.button {
+      Some(only) if Some(only) != button => None,
+      _ if self.button_symbols => Some(button.unwrap_or(InputButton::Left)),
+      _ => Some(InputButton::Left),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test_case::test_case;
+
+  #[test_case(None, false, Some(InputButton::Right), Some(InputButton::Left))]
+  #[test_case(None, false, None, Some(InputButton::Left))]
+  #[test_case(None, true, Some(InputButton::Right), Some(InputButton::Right))]
+  #[test_case(None, true, None, Some(InputButton::Left))]
+  #[test_case(
+    Some(InputButton::Middle),
+    false,
+    Some(InputButton::Middle),
+    Some(InputButton::Left)
+  )]
+  #[test_case(
+    Some(InputButton::Middle),
+    true,
+    Some(InputButton::Middle),
+    Some(InputButton::Middle)
+  )]
+  #[test_case(Some(InputButton::Middle), true, Some(InputButton::Left), None)]
+  #[test_case(Some(InputButton::Middle), false, None, None)]
+  fn test_symbols_for(
+    button: Option<InputButton>,
+    button_symbols: bool,
+    clicked: Option<InputButton>,
+    expected: Option<InputButton>,
+  ) {
+    let settings = InputSettings {
+      button,
+      button_symbols,
+      ..InputSettings::default()
+    };
+    assert_eq!(settings.symbols_for(clicked), expected);
+  }
+
+  #[test]
+  fn test_of_symbol() {
+    assert_eq!(InputButton::of_symbol('-'), Some(InputButton::Left));
+    assert_eq!(InputButton::of_symbol(','), Some(InputButton::Right));
+    assert_eq!(InputButton::of_symbol('='), Some(InputButton::Middle));
+    assert_eq!(InputButton::of_symbol('x'), None);
+  }
+
+  #[test_case("ScrollLock", Some(Key::ScrollLock))]
+  #[test_case("controlright", Some(Key::ControlRight))]
+  #[test_case("F12", Some(Key::F12))]
+  #[test_case("78", Some(Key::Unknown(78)))]
+  #[test_case("KeyA", None)]
+  #[test_case("", None)]
+  fn test_parse_key(name: &str, expected: Option<Key>) {
+    assert_eq!(parse_key(name), expected);
+  }
+
+  #[test]
+  fn test_with_overrides() {
+    let settings: HashMap<String, String> = [
+      ("BUTTON", "Right"),
+      ("BUTTON_SYMBOLS", "1"),
+      ("KEY", "Pause"),
+      ("GESTURES", "true"),
+    ]
+    .iter()
+    .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+    .collect();
+
+    let input = InputSettings::default().with_overrides(&settings);
+    assert_eq!(input.button, Some(InputButton::Right));
+    assert!(input.button_symbols);
+    assert_eq!(input.key, Some(Key::Pause));
+    assert!(input.gestures);
+    assert!(!input.wheel);
+  }
+}
@@ -0,0 +1,85 @@
+mod evdev_source;
+mod rdev_source;
+mod text_source;
+
+use anyhow::{bail, Result};
+use std::str::FromStr;
+
+use crate::input_event::InputEvent;
+
+pub type EventCallback = Box<dyn FnMut(InputEvent) + Send>;
+
+/// Something that produces timed input events (e.g. a display server, or a
+/// device file).
+pub trait InputSource: Send {
+  /// Calls `on_event` for every event, and blocks until the source ends.
+  fn listen(self: Box<Self>, on_event: EventCallback) -> Result<()>;
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum InputSourceKind {
+  #[default]
+  Rdev,
+  Stdin,
+  Pipe(String),
+  Evdev(Vec<String>),
+}
+
+impl InputSourceKind {
+  pub fn create(&self) -> Result<Box<dyn InputSource>> {
+    Ok(match self {
+      Self::Rdev => Box::new(rdev_source::RdevSource),
+      Self::Stdin => Box::new(text_source::TextSource::Stdin),
+      Self::Pipe(path) => Box::new(text_source::TextSource::Pipe(path.clone())),
+      Self::Evdev(paths) if paths.is_empty() => {
+        Box::new(evdev_source::EvdevSource::new(evdev_source::find_devices()?))
+      }
+      Self::Evdev(paths) => Box::new(evdev_source::EvdevSource::new(paths.clone())),
+    })
+  }
+}
+
+impl FromStr for InputSourceKind {
+  type Err = anyhow::Error;
+
+  fn from_str(value: &str) -> Result<Self> {
+    let (name, path) = value
+      .split_once(':')
+      .map_or((value, None), |(name, path)| (name, Some(path)));
+
+    Ok(match (name, path) {
+      ("rdev", None) => Self::Rdev,
+      ("stdin", None) => Self::Stdin,
+      ("pipe", Some(pipe_path)) if !pipe_path.is_empty() => Self::Pipe(pipe_path.to_owned()),
+      ("evdev", None) => Self::Evdev(vec![]),
+      ("evdev", Some(paths)) => Self::Evdev(paths.split(',').map(str::to_owned).collect()),
+      _ => bail!("Unknown input source `{value}`"),
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test_case::test_case;
+
+  #[test_case("rdev", InputSourceKind::Rdev)]
+  #[test_case("stdin", InputSourceKind::Stdin)]
+  #[test_case("pipe:/tmp/events", InputSourceKind::Pipe("/tmp/events".to_owned()))]
+  #[test_case("evdev", InputSourceKind::Evdev(vec![]))]
+  #[test_case(
+    "evdev:/dev/input/event3,/dev/input/event5",
+    InputSourceKind::Evdev(vec!["/dev/input/event3".to_owned(), "/dev/input/event5".to_owned()])
+  )]
+  fn test_parse_input_source(value: &str, expected: InputSourceKind) {
+    assert_eq!(value.parse::<InputSourceKind>().ok(), Some(expected));
+  }
+
+  #[test_case("x11")]
+  #[test_case("pipe"; "pipe without path")]
+  #[test_case("pipe:"; "pipe with empty path")]
+  #[test_case("stdin:/tmp/x")]
+  fn test_parse_input_source_error(value: &str) {
+    assert!(value.parse::<InputSourceKind>().is_err());
+  }
+}
@@ -0,0 +1,281 @@
+use anyhow::{bail, Context, Result};
+use rdev::Key;
+use std::fs::File;
+use std::io::Read;
+use std::sync::mpsc::{self, Sender};
+
+use super::{EventCallback, InputSource};
+use crate::{
+  input_event::{InputEvent, InputEventKind},
+  input_settings::InputButton,
+};
+
+/// Size of `struct input_event` on 64-bit Linux: a `timeval` (two 64-bit
+/// integers), then a 16-bit type, a 16-bit code and a 32-bit value.
+const EVENT_SIZE: usize = 24;
+
+const EV_KEY: u16 = 1;
+const EV_REL: u16 = 2;
+
+const REL_X: u16 = 0;
+const REL_Y: u16 = 1;
+const REL_WHEEL: u16 = 8;
+
+const BTN_LEFT: u16 = 0x110;
+const BTN_RIGHT: u16 = 0x111;
+const BTN_MIDDLE: u16 = 0x112;
+const BTN_MISC: u16 = 0x100;
+const BTN_MOUSE_LAST: u16 = 0x117;
+
+/// Codes from `linux/input-event-codes.h` for the keys that can be used as
+/// trigger keys. Other keys get the X11 key code (evdev code + 8), like rdev.
+static KEY_CODES: [(u16, Key); 30] = [
+  (29, Key::ControlLeft),
+  (42, Key::ShiftLeft),
+  (54, Key::ShiftRight),
+  (56, Key::Alt),
+  (58, Key::CapsLock),
+  (59, Key::F1),
+  (60, Key::F2),
+  (61, Key::F3),
+  (62, Key::F4),
+  (63, Key::F5),
+  (64, Key::F6),
+  (65, Key::F7),
+  (66, Key::F8),
+  (67, Key::F9),
+  (68, Key::F10),
+  (69, Key::NumLock),
+  (70, Key::ScrollLock),
+  (87, Key::F11),
+  (88, Key::F12),
+  (97, Key::ControlRight),
+  (99, Key::PrintScreen),
+  (100, Key::AltGr),
+  (102, Key::Home),
+  (104, Key::PageUp),
+  (107, Key::End),
+  (109, Key::PageDown),
+  (110, Key::Insert),
+  (119, Key::Pause),
+  (125, Key::MetaLeft),
+  (126, Key::MetaRight),
+];
+
+fn key_from_code(code: u16) -> Key {
+  KEY_CODES
+    .iter()
+    .find(|(c, _)| *c == code)
+    .map_or_else(|| Key::Unknown(u32::from(code) + 8), |(_, key)| *key)
+}
+
+const fn button_from_code(code: u16) -> Option<InputButton> {
+  match code {
+    BTN_LEFT => Some(InputButton::Left),
+    BTN_RIGHT => Some(InputButton::Right),
+    BTN_MIDDLE => Some(InputButton::Middle),
+    _ => None,
+  }
+}
+
+struct RawEvent {
+  time_ms: u128,
+  kind: u16,
+  code: u16,
+  value: i32,
+}
+
+impl RawEvent {
+  fn parse(bytes: &[u8; EVENT_SIZE]) -> Self {
+    let sec = i64::from_ne_bytes(bytes[0..8].try_into().unwrap());
+    let usec = i64::from_ne_bytes(bytes[8..16].try_into().unwrap());
+    let ms = sec.saturating_mul(1000) + usec.div_euclid(1000);
+
+    Self {
+      time_ms: u128::try_from(ms).unwrap_or_default(),
+      kind: u16::from_ne_bytes(bytes[16..18].try_into().unwrap()),
+      code: u16::from_ne_bytes(bytes[18..20].try_into().unwrap()),
+      value: i32::from_ne_bytes(bytes[20..24].try_into().unwrap()),
+    }
+  }
+}
+
+/// Turns raw device events into input events. Pointer devices only report
+/// relative movements, so the position is accumulated (it's only used for
+/// gesture directions).
+#[derive(Default)]
+struct EvdevDecoder {
+  position: (f64, f64),
+}
+
+impl EvdevDecoder {
+  /// Auto-repeated key presses (value 2) are ignored.
+  fn key_kind(code: u16, value: i32) -> Option<InputEventKind> {
+    let is_button = (BTN_MISC..=BTN_MOUSE_LAST).contains(&code);
+
+    match (is_button, value) {
+      (true, 1) => Some(InputEventKind::ButtonPress(button_from_code(code))),
+      (true, 0) => Some(InputEventKind::ButtonRelease(button_from_code(code))),
+      (false, 1) => Some(InputEventKind::KeyPress(key_from_code(code))),
+      (false, 0) => Some(InputEventKind::KeyRelease(key_from_code(code))),
+      _ => None,
+    }
+  }
+
+  fn rel_kind(&mut self, code: u16, value: i32) -> Option<InputEventKind> {
+    let (x, y) = &mut self.position;
+
+    match code {
+      REL_X => *x += f64::from(value),
+      REL_Y => *y += f64::from(value),
+      REL_WHEEL => return Some(InputEventKind::Wheel(i64::from(value))),
+      _ => return None,
+    }
+
+    Some(InputEventKind::MouseMove(self.position.0, self.position.1))
+  }
+
+  fn decode(&mut self, raw: &RawEvent) -> Option<InputEvent> {
+    let kind = match raw.kind {
+      EV_KEY => Self::key_kind(raw.code, raw.value),
+      EV_REL => self.rel_kind(raw.code, raw.value),
+      _ => None,
+    }?;
+
+    Some(InputEvent {
+      time_ms: raw.time_ms,
+      kind,
+    })
+  }
+}
+
+fn read_device(path: &str, sender: &Sender<InputEvent>) -> Result<()> {
+  let mut file = File::open(path).with_context(|| format!("Cannot open {path}"))?;
+  let mut decoder = EvdevDecoder::default();
+  let mut buf = [0; EVENT_SIZE];
+
+  loop {
+    file.read_exact(&mut buf)?;
+
+    if let Some(event) = decoder.decode(&RawEvent::parse(&buf)) {
+      sender.send(event)?;
+    }
+  }
+}
+
+/// Reads `/dev/input/event*` devices directly, which works without a display
+/// server (e.g. Wayland or a console). The user needs read permission on them
+/// (usually by being in the `input` group).
+pub struct EvdevSource {
+  paths: Vec<String>,
+}
+
+impl EvdevSource {
+  pub const fn new(paths: Vec<String>) -> Self {
+    Self { paths }
+  }
+}
+
+/// All the readable event devices.
+pub fn find_devices() -> Result<Vec<String>> {
+  let mut paths: Vec<String> = std::fs::read_dir("/dev/input")?
+    .filter_map(Result::ok)
+    .map(|entry| entry.path().to_string_lossy().into_owned())
+    .filter(|path| path.starts_with("/dev/input/event") && File::open(path).is_ok())
+    .collect();
+
+  if paths.is_empty() {
+    bail!("No readable devices in /dev/input (is the user in the `input` group?)");
+  }
+
+  paths.sort();
+  Ok(paths)
+}
+
+impl InputSource for EvdevSource {
+  fn listen(self: Box<Self>, mut on_event: EventCallback) -> Result<()> {
+    let (sender, receiver) = mpsc::channel();
+
+    for path in self.paths {
+      let device_sender = sender.clone();
+
+      std::thread::spawn(move || {
+        if let Err(err) = read_device(&path, &device_sender) {
+          eprintln!("Stopped reading {path}: {err}");
+        }
+      });
+    }
+
+    drop(sender);
+
+    for event in receiver {
+      on_event(event);
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test_case::test_case;
+
+  fn raw_bytes(sec: i64, usec: i64, kind: u16, code: u16, value: i32) -> [u8; EVENT_SIZE] {
+    let mut bytes = [0; EVENT_SIZE];
+    bytes[0..8].copy_from_slice(&sec.to_ne_bytes());
+    bytes[8..16].copy_from_slice(&usec.to_ne_bytes());
+    bytes[16..18].copy_from_slice(&kind.to_ne_bytes());
+    bytes[18..20].copy_from_slice(&code.to_ne_bytes());
+    bytes[20..24].copy_from_slice(&value.to_ne_bytes());
+    bytes
+  }
+
+  fn decode(
+    decoder: &mut EvdevDecoder,
+    kind: u16,
+    code: u16,
+    value: i32,
+  ) -> Option<InputEventKind> {
+    let raw = RawEvent::parse(&raw_bytes(1, 500_000, kind, code, value));
+    decoder.decode(&raw).map(|e| e.kind)
+  }
+
+  #[test]
+  fn test_parse_time() {
+    let raw = RawEvent::parse(&raw_bytes(12, 345_678, EV_KEY, BTN_LEFT, 1));
+    assert_eq!(raw.time_ms, 12_345);
+  }
+
+  #[test_case(
+    EV_KEY,
+    BTN_LEFT,
+    1,
+    Some(InputEventKind::ButtonPress(Some(InputButton::Left)))
+  )]
+  #[test_case(
+    EV_KEY,
+    BTN_RIGHT,
+    0,
+    Some(InputEventKind::ButtonRelease(Some(InputButton::Right)))
+  )]
+  #[test_case(EV_KEY, 0x113, 1, Some(InputEventKind::ButtonPress(None)))]
+  #[test_case(EV_KEY, 70, 1, Some(InputEventKind::KeyPress(Key::ScrollLock)))]
+  #[test_case(EV_KEY, 70, 2, None)]
+  #[test_case(EV_KEY, 30, 0, Some(InputEventKind::KeyRelease(Key::Unknown(38))))]
+  #[test_case(EV_REL, REL_WHEEL, -1, Some(InputEventKind::Wheel(-1)))]
+  #[test_case(0, 0, 0, None)]
+  fn test_decode(kind: u16, code: u16, value: i32, expected: Option<InputEventKind>) {
+    let mut decoder = EvdevDecoder::default();
+    assert_eq!(decode(&mut decoder, kind, code, value), expected);
+  }
+
+  #[test]
+  fn test_decode_accumulates_position() {
+    let mut decoder = EvdevDecoder::default();
+    decode(&mut decoder, EV_REL, REL_X, 10);
+    decode(&mut decoder, EV_REL, REL_Y, -4);
+    let kind = decode(&mut decoder, EV_REL, REL_X, 5);
+    assert_eq!(kind, Some(InputEventKind::MouseMove(15.0, -4.0)));
+  }
+}
@@ -0,0 +1,52 @@
+use anyhow::{bail, Result};
+use rdev::{listen, Button, Event, EventType};
+use std::time::UNIX_EPOCH;
+
+use super::{EventCallback, InputSource};
+use crate::{
+  input_event::{InputEvent, InputEventKind},
+  input_settings::InputButton,
+};
+
+/// Global input from the display server (X11 on Linux).
+pub struct RdevSource;
+
+const fn to_input_button(button: Button) -> Option<InputButton> {
+  match button {
+    Button::Left => Some(InputButton::Left),
+    Button::Right => Some(InputButton::Right),
+    Button::Middle => Some(InputButton::Middle),
+    Button::Unknown(_) => None,
+  }
+}
+
+fn from_rdev(event: &Event) -> Option<InputEvent> {
+  let kind = match event.event_type {
+    EventType::ButtonPress(b) => InputEventKind::ButtonPress(to_input_button(b)),
+    EventType::ButtonRelease(b) => InputEventKind::ButtonRelease(to_input_button(b)),
+    EventType::KeyPress(key) => InputEventKind::KeyPress(key),
+    EventType::KeyRelease(key) => InputEventKind::KeyRelease(key),
+    EventType::MouseMove { x, y } => InputEventKind::MouseMove(x, y),
+    EventType::Wheel { delta_y, .. } => InputEventKind::Wheel(delta_y),
+  };
+
+  let time_ms = event.time.duration_since(UNIX_EPOCH).ok()?.as_millis();
+
+  Some(InputEvent { time_ms, kind })
+}
+
+impl InputSource for RdevSource {
+  fn listen(self: Box<Self>, mut on_event: EventCallback) -> Result<()> {
+    let listen_result = listen(move |event| {
+      if let Some(input_event) = from_rdev(&event) {
+        on_event(input_event);
+      }
+    });
+
+    if let Err(error) = listen_result {
+      bail!("Device error: {error:?}");
+    }
+
+    Ok(())
+  }
+}
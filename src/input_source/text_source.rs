@@ -0,0 +1,78 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::os::unix::fs::FileTypeExt;
+
+use super::{EventCallback, InputSource};
+use crate::input_event::InputEvent;
+
+/// Events in the same text format as recordings (e.g. `120 press left`), one
+/// per line.
+pub enum TextSource {
+  Stdin,
+  Pipe(String),
+}
+
+/// Invalid lines are reported and skipped, so a typo doesn't stop the server.
+fn read_events<R: BufRead>(reader: R, on_event: &mut EventCallback) -> Result<()> {
+  for line_result in reader.lines() {
+    let line = line_result?;
+    let trimmed = line.trim();
+
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+      continue;
+    }
+
+    match trimmed.parse::<InputEvent>() {
+      Ok(event) => on_event(event),
+      Err(err) => eprintln!("Ignoring input event: {err}"),
+    }
+  }
+
+  Ok(())
+}
+
+fn is_named_pipe(path: &str) -> bool {
+  std::fs::metadata(path).is_ok_and(|m| m.file_type().is_fifo())
+}
+
+/// A named pipe reaches the end every time a writer closes it, so it's opened
+/// again to wait for the next one.
+fn read_pipe(path: &str, on_event: &mut EventCallback) -> Result<()> {
+  loop {
+    let file = File::open(path).with_context(|| format!("Cannot open {path}"))?;
+    read_events(BufReader::new(file), on_event)?;
+
+    if !is_named_pipe(path) {
+      return Ok(());
+    }
+  }
+}
+
+impl InputSource for TextSource {
+  fn listen(self: Box<Self>, mut on_event: EventCallback) -> Result<()> {
+    match *self {
+      Self::Stdin => read_events(std::io::stdin().lock(), &mut on_event),
+      Self::Pipe(path) => read_pipe(&path, &mut on_event),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::{Arc, Mutex};
+
+  #[test]
+  fn test_read_events_skips_invalid_lines() {
+    let text = "# comment\n0 press left\n\nnonsense\n150 release left\n";
+    let events = Arc::new(Mutex::new(vec![]));
+    let events_clone = Arc::clone(&events);
+    let mut on_event: EventCallback = Box::new(move |e| events_clone.lock().unwrap().push(e));
+
+    read_events(text.as_bytes(), &mut on_event).unwrap();
+
+    let times: Vec<u128> = events.lock().unwrap().iter().map(|e| e.time_ms).collect();
+    assert_eq!(times, [0, 150]);
+  }
+}
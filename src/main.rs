@@ -11,16 +11,30 @@
 #![deny(clippy::shadow_unrelated)]
 #![allow(clippy::significant_drop_tightening)]
 
+mod active_window;
 mod api_client;
+mod arming_settings;
 mod cli;
 mod click_sequence_detector;
 mod cmd;
+mod command_options;
 mod constants;
 mod event_observe;
+mod fuzzy_matcher;
+mod gesture_detector;
+mod input_event;
+mod input_settings;
+mod input_source;
 mod ipc_tcp;
 mod logger;
+mod match_settings;
+mod mode;
+mod morse;
 mod sequence_automata;
+mod sequence_pattern;
 mod server;
+mod timing_calibration;
+mod timing_settings;
 mod util;
 
 fn main() {
@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+
+use crate::timing_settings::parse_flag;
+
+/// How clicked sequences are matched against the configured ones. Only set
+/// from the configuration file.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MatchSettings {
+  /// Recognize a sequence even when it comes after other clicks in the same
+  /// burst.
+  pub match_anywhere: bool,
+  /// When a sequence ends without matching, match the closest configured
+  /// sequence if it's within this edit distance (0 disables it).
+  pub fuzzy_distance: usize,
+}
+
+impl MatchSettings {
+  pub fn from_settings(settings: &HashMap<String, String>) -> Self {
+    let defaults = Self::default();
+
+    Self {
+      match_anywhere: parse_flag(settings, "MATCH_ANYWHERE").unwrap_or(defaults.match_anywhere),
+      fuzzy_distance: settings
+        .get("FUZZY_DISTANCE")
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(defaults.fuzzy_distance),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_from_settings() {
+    let settings = HashMap::from([("MATCH_ANYWHERE".to_owned(), "1".to_owned())]);
+    assert!(MatchSettings::from_settings(&settings).match_anywhere);
+    assert!(!MatchSettings::from_settings(&HashMap::new()).match_anywhere);
+  }
+
+  #[test]
+  fn test_from_settings_fuzzy_distance() {
+    let settings = HashMap::from([("FUZZY_DISTANCE".to_owned(), "2".to_owned())]);
+    assert_eq!(MatchSettings::from_settings(&settings).fuzzy_distance, 2);
+    let invalid = HashMap::from([("FUZZY_DISTANCE".to_owned(), "-1".to_owned())]);
+    assert_eq!(MatchSettings::from_settings(&invalid).fuzzy_distance, 0);
+  }
+}
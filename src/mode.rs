@@ -0,0 +1,108 @@
+use std::{cell::OnceCell, fmt::Display, sync::Mutex, time::Duration};
+
+use regex::Regex;
+
+use crate::command_options::{format_timeout, parse_timeout};
+
+/// The mode of the commands before any `[mode NAME]` line, which is the one
+/// the server starts in.
+pub const DEFAULT_MODE: &str = "default";
+
+/// `[mode NAME]` on its own line: the commands after it (until the next one,
+/// or the end of the file) are only matched in that mode. With a timeout
+/// (e.g. `[mode browser timeout=30s]`), the mode goes back to the default
+/// one after that long without clicks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModeHeader {
+  pub name: String,
+  pub timeout: Option<Duration>,
+}
+
+static HEADER_REGEX: Mutex<OnceCell<Regex>> = Mutex::new(OnceCell::new());
+
+impl ModeHeader {
+  pub fn new(name: &str) -> Self {
+    Self {
+      name: name.to_owned(),
+      timeout: None,
+    }
+  }
+
+  pub fn parse(line: &str) -> Option<Self> {
+    let guard = HEADER_REGEX.lock().unwrap();
+    let re = guard
+      .get_or_init(|| Regex::new(r"^\s*\[mode\s+([\w.-]+)(?:\s+timeout=(\S+))?\s*\]\s*$").unwrap());
+    let captures = re.captures(line)?;
+    let timeout = match captures.get(2) {
+      Some(timeout) => Some(parse_timeout(timeout.as_str())?),
+      None => None,
+    };
+
+    Some(Self {
+      name: captures[1].to_owned(),
+      timeout,
+    })
+  }
+
+  pub fn is_default(&self) -> bool {
+    self.name == DEFAULT_MODE
+  }
+}
+
+impl Display for ModeHeader {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self.timeout {
+      Some(timeout) => write!(
+        f,
+        "[mode {} timeout={}]",
+        self.name,
+        format_timeout(timeout)
+      ),
+      None => write!(f, "[mode {}]", self.name),
+    }
+  }
+}
+
+/// Modes declared more than once are merged (a timeout is kept unless a
+/// later one replaces it).
+pub fn merge_modes(headers: impl IntoIterator<Item = ModeHeader>) -> Vec<ModeHeader> {
+  let mut modes: Vec<ModeHeader> = vec![];
+
+  for header in headers {
+    match modes.iter_mut().find(|m| m.name == header.name) {
+      Some(mode) => mode.timeout = header.timeout.or(mode.timeout),
+      None => modes.push(header),
+    }
+  }
+
+  modes
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test_case::test_case;
+
+  #[test_case("[mode browser]" => Some(ModeHeader::new("browser")); "name")]
+  #[test_case("  [mode  vim-2 ]  " => Some(ModeHeader::new("vim-2")); "spaces")]
+  #[test_case("[mode browser timeout=30s]" => Some(ModeHeader { name: "browser".to_owned(), timeout: Some(Duration::from_secs(30)) }); "timeout")]
+  #[test_case("[mode browser timeout=soon]" => None; "invalid timeout")]
+  #[test_case("[mode]" => None; "no name")]
+  #[test_case("[ mode browser ]" => None; "test command")]
+  fn test_parse(line: &str) -> Option<ModeHeader> {
+    ModeHeader::parse(line)
+  }
+
+  #[test_case("[mode browser]")]
+  #[test_case("[mode browser timeout=1500ms]")]
+  fn test_display_round_trip(line: &str) {
+    assert_eq!(ModeHeader::parse(line).unwrap().to_string(), line);
+  }
+
+  #[test]
+  fn test_merge_modes() {
+    let timed = ModeHeader::parse("[mode a timeout=5s]").unwrap();
+    let merged = merge_modes([timed.clone(), ModeHeader::new("b"), ModeHeader::new("a")]);
+    assert_eq!(merged, [timed, ModeHeader::new("b")]);
+  }
+}
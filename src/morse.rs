@@ -0,0 +1,150 @@
+/// International Morse code for digits. They all have five symbols, so a
+/// number doesn't need separators between digits.
+static DIGITS: [(char, &str); 10] = [
+  ('0', "-----"),
+  ('1', ".----"),
+  ('2', "..---"),
+  ('3', "...--"),
+  ('4', "....-"),
+  ('5', "....."),
+  ('6', "-...."),
+  ('7', "--..."),
+  ('8', "---.."),
+  ('9', "----."),
+];
+
+const DIGIT_LEN: usize = 5;
+
+/// International Morse code for letters.
+static LETTERS: [(char, &str); 26] = [
+  ('A', ".-"),
+  ('B', "-..."),
+  ('C', "-.-."),
+  ('D', "-.."),
+  ('E', "."),
+  ('F', "..-."),
+  ('G', "--."),
+  ('H', "...."),
+  ('I', ".."),
+  ('J', ".---"),
+  ('K', "-.-"),
+  ('L', ".-.."),
+  ('M', "--"),
+  ('N', "-."),
+  ('O', "---"),
+  ('P', ".--."),
+  ('Q', "--.-"),
+  ('R', ".-."),
+  ('S', "..."),
+  ('T', "-"),
+  ('U', "..-"),
+  ('V', "...-"),
+  ('W', ".--"),
+  ('X', "-..-"),
+  ('Y', "-.--"),
+  ('Z', "--.."),
+];
+
+/// Symbols that can separate letters in an alias (the others are letters
+/// themselves, or are used to write them). They are clicked too, so a sequence
+/// with them isn't always made of letters.
+pub const GAP_SYMBOLS: &str = ",_:=^<>";
+
+fn is_gap(c: char) -> bool {
+  GAP_SYMBOLS.contains(c)
+}
+
+fn code_of(c: char) -> Option<&'static str> {
+  let upper = c.to_ascii_uppercase();
+
+  LETTERS
+    .iter()
+    .chain(&DIGITS)
+    .find(|(letter, _)| *letter == upper)
+    .map(|(_, code)| *code)
+}
+
+fn decode_digit(symbols: &[char]) -> Option<char> {
+  let code: String = symbols.iter().collect();
+
+  DIGITS
+    .iter()
+    .find(|(_, digit_code)| *digit_code == code)
+    .map(|(digit, _)| *digit)
+}
+
+/// Decodes a number written as consecutive morse digits (e.g. `..---.----`
+/// is `21`).
+pub fn decode_number(symbols: &str) -> Option<String> {
+  let chars: Vec<char> = symbols.chars().collect();
+
+  if chars.is_empty() || !chars.len().is_multiple_of(DIGIT_LEN) {
+    return None;
+  }
+
+  chars.chunks(DIGIT_LEN).map(decode_digit).collect()
+}
+
+/// Writes letters and digits in morse, one after the other (e.g. `SOS` is
+/// `...---...`), or separated by the gap symbols between them (`S,O,S` is
+/// `...,---,...`).
+pub fn encode_text(text: &str) -> Option<String> {
+  text
+    .chars()
+    .map(|c| {
+      if is_gap(c) {
+        Some(c.to_string())
+      } else {
+        code_of(c).map(str::to_owned)
+      }
+    })
+    .collect()
+}
+
+/// The letter or digit that symbols are, if they are a single one. Letters
+/// written one after the other can't be told apart, and the gap symbols are
+/// also clicked as symbols of their own, so longer sequences aren't decoded
+/// (only aliases are written as letters).
+pub fn decode_letter(code: &str) -> Option<char> {
+  LETTERS
+    .iter()
+    .chain(&DIGITS)
+    .find(|(_, letter_code)| *letter_code == code)
+    .map(|(letter, _)| *letter)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test_case::test_case;
+
+  #[test_case(".----", Some("1"))]
+  #[test_case("..---.----", Some("21"))]
+  #[test_case("-----.....", Some("05"))]
+  #[test_case("", None; "empty")]
+  #[test_case("..--", None; "incomplete digit")]
+  #[test_case(".-.-.", None; "not a digit")]
+  #[test_case("....,", None; "other symbols")]
+  fn test_decode_number(symbols: &str, expected: Option<&str>) {
+    assert_eq!(decode_number(symbols).as_deref(), expected);
+  }
+
+  #[test_case("SOS" => Some("...---...".to_owned()); "concatenated")]
+  #[test_case("s,o,s" => Some("...,---,...".to_owned()); "gaps")]
+  #[test_case("K9" => Some("-.-----.".to_owned()); "digit")]
+  #[test_case("S!" => None; "unknown character")]
+  fn test_encode_text(text: &str) -> Option<String> {
+    encode_text(text)
+  }
+
+  #[test_case("..." => Some('S'); "letter")]
+  #[test_case(".----" => Some('1'); "digit")]
+  #[test_case("...,---,..." => None; "gaps")]
+  #[test_case(".,." => None; "other button")]
+  #[test_case("...---..." => None; "concatenated")]
+  #[test_case(".U." => None; "not a gap")]
+  #[test_case("" => None; "empty")]
+  fn test_decode_letter(symbols: &str) -> Option<char> {
+    decode_letter(symbols)
+  }
+}
@@ -1,54 +1,191 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashSet, VecDeque};
+
+use crate::{
+  input_settings::SEQUENCE_SYMBOLS,
+  sequence_pattern::{Pattern, PatternNfa},
+};
+
+/// Checking which sequences can be reached explores sets of pattern states,
+/// and there can be exponentially many of them (e.g. `.?{30}` when matching
+/// anywhere), so it gives up after this many.
+const MAX_EXPLORED_STATES: usize = 4096;
+
+/// `?` matches any of these.
+fn alphabet(patterns: &[Pattern]) -> BTreeSet<char> {
+  let written = patterns.iter().flat_map(Pattern::symbols);
+  SEQUENCE_SYMBOLS.chars().chain(written).collect()
+}
+
+fn parse_patterns(sequences: &[&str]) -> Vec<Pattern> {
+  sequences
+    .iter()
+    .map(|s| Pattern::parse(s).unwrap_or_else(|_| Pattern::nothing()))
+    .collect()
+}
 
 #[derive(Copy, Clone)]
 pub enum AutomataInstruction {
   Char(char),
   Reset,
+  /// The arming key was pressed (only used when arming is enabled).
+  Arm,
+  /// The arming key was released.
+  Disarm,
+  /// A sequence sent over HTTP starts. It's matched even when disarmed.
+  SentStart,
+  /// The sequence sent over HTTP ends.
+  SentEnd,
+  /// Nothing happened, but the timeouts have to be computed again (e.g. the
+  /// mode was switched over HTTP).
+  Wake,
+  /// A button or key was pressed, so a symbol is coming when it's released.
+  Press,
 }
 
+/// Follows the pattern automaton one symbol at a time, keeping the set of
+/// states reached so far (instead of building every set beforehand, which can
+/// take exponential time).
 pub struct SequenceAutomata {
-  curr_node: usize,
-  failed: bool,
-  graph: Vec<HashMap<char, usize>>,
-  results: HashMap<usize, Vec<usize>>,
+  nfa: PatternNfa,
+  alphabet: BTreeSet<char>,
+  start: BTreeSet<usize>,
+  states: BTreeSet<usize>,
+  sequence_count: usize,
+  match_anywhere: bool,
+  wait_for_longer: bool,
+  pending: Option<Vec<usize>>,
+  holding: bool,
 }
 
 impl SequenceAutomata {
+  /// Sequences can be patterns (see `Pattern`). Invalid patterns never match.
   pub fn new(sequences: &[&str]) -> Self {
-    let mut result = Self {
-      curr_node: 0,
-      failed: false,
-      graph: vec![],
-      results: HashMap::new(),
-    };
+    Self::from_sequences(sequences, false)
+  }
+
+  /// When matching anywhere, a symbol that doesn't continue any sequence
+  /// doesn't make the whole burst fail: sequences can start after it.
+  pub fn from_sequences(sequences: &[&str], match_anywhere: bool) -> Self {
+    let patterns = parse_patterns(sequences);
+    let nfa = PatternNfa::new(&patterns);
+    let start = nfa.start();
 
-    result.add_node();
+    Self {
+      alphabet: alphabet(&patterns),
+      states: start.clone(),
+      start,
+      nfa,
+      sequence_count: sequences.len(),
+      match_anywhere,
+      wait_for_longer: false,
+      pending: None,
+      holding: false,
+    }
+  }
 
-    for (i, seq) in sequences.iter().enumerate() {
-      result.add_sequence(seq, i);
+  /// Symbols outside the alphabet (which `?` doesn't match) lead nowhere.
+  fn step(&self, states: &BTreeSet<usize>, c: char) -> BTreeSet<usize> {
+    if self.alphabet.contains(&c) {
+      self.nfa.step(states, c)
+    } else {
+      BTreeSet::new()
     }
+  }
 
-    result
+  /// Whether the symbol continues the sequences started so far.
+  fn continues(&self, states: &BTreeSet<usize>, c: char) -> bool {
+    !self.step(states, c).is_empty()
   }
 
-  fn reset(&mut self) {
-    self.curr_node = 0;
-    self.failed = false;
+  /// When matching anywhere, the start states are always active, so any symbol
+  /// can also start a sequence, and a symbol that doesn't lead anywhere goes
+  /// back to the start. Otherwise it leads to a set without states, which
+  /// stays empty until a `Reset`.
+  fn next_states(&self, states: &BTreeSet<usize>, c: char) -> BTreeSet<usize> {
+    if !self.match_anywhere {
+      return self.step(states, c);
+    }
+
+    let active: BTreeSet<usize> = states.union(&self.start).copied().collect();
+    let next = self.step(&active, c);
+
+    if next.is_empty() {
+      self.start.clone()
+    } else {
+      next
+    }
+  }
+
+  fn results_at(&self, states: &BTreeSet<usize>) -> Option<Vec<usize>> {
+    Some(self.nfa.matched(states)).filter(|ids| !ids.is_empty())
+  }
+
+  fn unvisited_next(
+    &self,
+    states: &BTreeSet<usize>,
+    visited: &mut HashSet<BTreeSet<usize>>,
+  ) -> Vec<BTreeSet<usize>> {
+    (self.alphabet.iter())
+      .map(|c| self.next_states(states, *c))
+      .filter(|next| visited.insert(next.clone()))
+      .collect()
+  }
+
+  /// Ids of the sequences that some input can match. A sequence can't be
+  /// matched if a shorter one always matches first. When there are too many
+  /// sets of states to explore, every sequence is considered reachable.
+  pub fn reachable_results(&self) -> BTreeSet<usize> {
+    let mut visited = HashSet::from([self.start.clone()]);
+    let mut queue = VecDeque::from([self.start.clone()]);
+    let mut result = BTreeSet::new();
+
+    while visited.len() <= MAX_EXPLORED_STATES {
+      let Some(states) = queue.pop_front() else {
+        return result;
+      };
+      let ids = self.results_at(&states);
+
+      if ids.is_none() || self.waits_at(&states) {
+        queue.extend(self.unvisited_next(&states, &mut visited));
+      }
+
+      result.extend(ids.into_iter().flatten());
+    }
+
+    (0..self.sequence_count).collect()
+  }
+
+  fn waits_at(&self, states: &BTreeSet<usize>) -> bool {
+    self.wait_for_longer && self.alphabet.iter().any(|c| self.continues(states, *c))
   }
 
-  fn add_node(&mut self) -> usize {
-    let idx = self.graph.len();
-    self.graph.push(HashMap::new());
-    idx
+  /// Results of a sequence that is also the start of a longer one are kept
+  /// until the longer one can't be reached anymore, or until a `Reset`.
+  pub const fn with_wait_for_longer(mut self, enabled: bool) -> Self {
+    self.wait_for_longer = enabled;
+    self
+  }
+
+  /// Whether there are results to give if the sequence ends here (i.e. no
+  /// symbol is coming).
+  pub const fn is_waiting(&self) -> bool {
+    self.pending.is_some() && !self.holding
+  }
+
+  fn reset(&mut self) {
+    self.states.clone_from(&self.start);
+    self.pending = None;
+    self.holding = false;
   }
 
   fn get_current_results(&mut self) -> Option<Vec<usize>> {
-    if self.failed {
+    let result = self.results_at(&self.states);
+
+    if self.waits_at(&self.states) {
+      self.pending = result;
       return None;
     }
 
-    let result = self.results.get(&self.curr_node).map(Vec::clone);
-
     if result.is_some() {
       self.reset();
     }
@@ -56,37 +193,46 @@ impl SequenceAutomata {
     result
   }
 
-  pub fn put(&mut self, instruction: AutomataInstruction) -> Option<Vec<usize>> {
-    match instruction {
-      AutomataInstruction::Char(c) => {
-        if let Some(child) = self.graph[self.curr_node].get(&c) {
-          self.curr_node = *child;
-        } else {
-          self.failed = true;
-        }
-        self.get_current_results()
-      }
-      AutomataInstruction::Reset => {
-        self.reset();
-        None
-      }
+  /// A symbol that doesn't continue the longer sequence gives the pending
+  /// results, and starts a new sequence.
+  fn put_char(&mut self, c: char) -> Option<Vec<usize>> {
+    self.holding = false;
+
+    let continues = self.continues(&self.states, c);
+
+    if let Some(pending) = self.pending.take_if(|_| !continues) {
+      self.reset();
+      let next = self.put_char(c).unwrap_or_default();
+      return Some([pending, next].concat());
     }
+
+    self.states = self.next_states(&self.states, c);
+    self.pending = None;
+
+    self.get_current_results()
   }
 
-  fn add_sequence(&mut self, sequence: &str, id: usize) {
-    let mut curr = 0;
+  /// A sequence waiting for a longer one matches when the sequence ends.
+  fn end_sequence(&mut self) -> Option<Vec<usize>> {
+    let pending = self.pending.take();
+    self.reset();
+    pending
+  }
 
-    for c in sequence.chars() {
-      #[allow(clippy::map_entry)]
-      if !self.graph[curr].contains_key(&c) {
-        let v = self.add_node();
-        self.graph[curr].insert(c, v);
+  pub fn put(&mut self, instruction: AutomataInstruction) -> Option<Vec<usize>> {
+    match instruction {
+      AutomataInstruction::Char(c) => self.put_char(c),
+      AutomataInstruction::Reset => self.end_sequence(),
+      AutomataInstruction::Press => {
+        self.holding = true;
+        None
       }
-
-      curr = *self.graph[curr].get(&c).unwrap();
+      AutomataInstruction::Arm
+      | AutomataInstruction::Disarm
+      | AutomataInstruction::SentStart
+      | AutomataInstruction::SentEnd
+      | AutomataInstruction::Wake => None,
     }
-
-    self.results.entry(curr).or_default().push(id);
   }
 }
 
@@ -181,4 +327,155 @@ mod tests {
     let mut automata = build_automata(&["0111", "011"]);
     check_results(&mut automata, "0111", &[None, None, Some(vec![1]), None]);
   }
+
+  fn build_waiting_automata(binary_strings: &[&str]) -> SequenceAutomata {
+    SequenceAutomata::new(binary_strings).with_wait_for_longer(true)
+  }
+
+  #[test]
+  fn test_wait_for_longer_reset_gives_shorter() {
+    let mut automata = build_waiting_automata(&["011", "0111"]);
+    check_results(&mut automata, "011R", &[None, None, None, Some(vec![0])]);
+    check_results(&mut automata, "0111", &[None, None, None, Some(vec![1])]);
+  }
+
+  #[test]
+  fn test_wait_for_longer_leaf_fires_immediately() {
+    let mut automata = build_waiting_automata(&["01", "011"]);
+    check_results(&mut automata, "011", &[None, None, Some(vec![1])]);
+    check_results(&mut automata, "01", &[None, None]);
+    assert!(automata.is_waiting());
+  }
+
+  #[test]
+  fn test_wait_for_longer_other_symbol_gives_shorter() {
+    let mut automata = build_waiting_automata(&["01", "011", "0"]);
+    check_results(&mut automata, "010", &[None, None, Some(vec![0])]);
+    check_results(&mut automata, "R", &[Some(vec![2])]);
+  }
+
+  #[test]
+  fn test_wait_for_longer_other_symbol_completes_another() {
+    let mut automata = build_waiting_automata(&["01", "011", "2"]);
+    check_results(&mut automata, "012", &[None, None, Some(vec![0, 2])]);
+  }
+
+  #[test]
+  fn test_wait_for_longer_not_waiting_while_pressing() {
+    let mut automata = build_waiting_automata(&["01", "011"]);
+    check_results(&mut automata, "01", &[None, None]);
+    automata.put(AutomataInstruction::Press);
+    assert!(!automata.is_waiting());
+    assert_eq!(automata.put(AutomataInstruction::Char('1')), Some(vec![1]));
+    assert!(!automata.is_waiting());
+  }
+
+  #[test]
+  fn test_patterns() {
+    let mut automata = build_automata(&["0?1", "(00|11){2}"]);
+    check_results(&mut automata, "011", &[None, None, Some(vec![0])]);
+    check_results(&mut automata, "1100", &[None, None, None, Some(vec![1])]);
+  }
+
+  #[test]
+  fn test_pattern_and_literal_match_together() {
+    let mut automata = build_automata(&["0{2,3}", "00"]);
+    check_results(&mut automata, "00", &[None, Some(vec![0, 1])]);
+  }
+
+  #[test]
+  fn test_invalid_pattern_never_matches() {
+    let mut automata = build_automata(&["(01", "01"]);
+    check_results(&mut automata, "01", &[None, Some(vec![1])]);
+  }
+
+  fn build_anywhere_automata(binary_strings: &[&str]) -> SequenceAutomata {
+    SequenceAutomata::from_sequences(binary_strings, true)
+  }
+
+  #[test]
+  fn test_anywhere_after_stray_symbols() {
+    let mut automata = build_anywhere_automata(&["0101"]);
+    check_results(
+      &mut automata,
+      "110101",
+      &[None, None, None, None, None, Some(vec![0])],
+    );
+  }
+
+  #[test]
+  fn test_anywhere_failure_inside_sequence() {
+    let mut automata = build_anywhere_automata(&["0011", "1101"]);
+    check_results(
+      &mut automata,
+      "00011",
+      &[None, None, None, None, Some(vec![0])],
+    );
+    check_results(
+      &mut automata,
+      "R011101",
+      &[None, None, None, None, None, None, Some(vec![1])],
+    );
+    check_results(&mut automata, "R0101", &[None, None, None, None, None]);
+  }
+
+  #[test]
+  fn test_anywhere_shorter_inside_longer() {
+    let mut automata = build_anywhere_automata(&["0110", "11"]);
+    check_results(&mut automata, "011", &[None, None, Some(vec![1])]);
+  }
+
+  #[test]
+  fn test_anywhere_unknown_symbol() {
+    let mut automata = build_anywhere_automata(&["01"]);
+    check_results(&mut automata, "0x01", &[None, None, None, Some(vec![0])]);
+  }
+
+  #[test]
+  fn test_anywhere_matches_again_after_match() {
+    let mut automata = build_anywhere_automata(&["01"]);
+    check_results(
+      &mut automata,
+      "01101",
+      &[None, Some(vec![0]), None, None, Some(vec![0])],
+    );
+  }
+
+  #[test]
+  fn test_anywhere_wait_for_longer() {
+    let mut automata = build_anywhere_automata(&["01", "011"]).with_wait_for_longer(true);
+    check_results(&mut automata, "1010", &[None, None, None, Some(vec![0])]);
+    check_results(&mut automata, "1R", &[None, Some(vec![0])]);
+    check_results(&mut automata, "1011", &[None, None, None, Some(vec![1])]);
+  }
+
+  #[test]
+  fn test_reachable_results() {
+    let automata = build_automata(&["01", "011", "1?"]);
+    assert_eq!(automata.reachable_results(), BTreeSet::from([0, 2]));
+    let waiting = build_waiting_automata(&["01", "011", "1?"]);
+    assert_eq!(waiting.reachable_results(), BTreeSet::from([0, 1, 2]));
+  }
+
+  #[test]
+  fn test_reachable_results_anywhere() {
+    let automata = build_anywhere_automata(&["11", "0110", "0101"]);
+    assert_eq!(automata.reachable_results(), BTreeSet::from([0, 2]));
+    assert_eq!(
+      build_automata(&["11", "0110", "0101"]).reachable_results(),
+      BTreeSet::from([0, 1, 2])
+    );
+  }
+
+  #[test]
+  fn test_anywhere_long_patterns() {
+    let mut automata = build_anywhere_automata(&[".?{40}"]);
+    let results: Vec<Option<Vec<usize>>> = format!("-{}", ".".repeat(41))
+      .chars()
+      .map(|c| automata.put(char_to_instruction(c)))
+      .collect();
+    assert!(results[..41].iter().all(Option::is_none));
+    assert_eq!(results[41], Some(vec![0]));
+    assert_eq!(automata.reachable_results(), BTreeSet::from([0]));
+  }
 }
@@ -0,0 +1,445 @@
+use anyhow::{bail, Result};
+use std::collections::{BTreeSet, HashMap};
+
+const MAX_REPEAT: usize = 50;
+
+/// Nested repetitions multiply the size of the automaton, so the whole
+/// pattern is limited too.
+const MAX_SIZE: usize = 1000;
+
+/// A sequence that can contain `?` (any symbol), repetitions (`-{3}`,
+/// `.{1,3}`) and alternatives (`(..|--)`). Any other character is a symbol.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Pattern {
+  Symbol(char),
+  Any,
+  Concat(Vec<Self>),
+  Alternation(Vec<Self>),
+  Repeat(Box<Self>, usize, usize),
+}
+
+fn is_syntax(c: char) -> bool {
+  "?(|){}".contains(c)
+}
+
+struct Parser {
+  chars: Vec<char>,
+  pos: usize,
+}
+
+impl Parser {
+  fn peek(&self) -> Option<char> {
+    self.chars.get(self.pos).copied()
+  }
+
+  fn eat(&mut self, c: char) -> bool {
+    let found = self.peek() == Some(c);
+    self.pos += usize::from(found);
+    found
+  }
+
+  fn number(&mut self) -> Result<usize> {
+    let start = self.pos;
+
+    while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+      self.pos += 1;
+    }
+
+    let digits: String = self.chars[start..self.pos].iter().collect();
+
+    match digits.parse() {
+      Ok(n) if n <= MAX_REPEAT => Ok(n),
+      Ok(_) => bail!("Repetitions can't be more than {MAX_REPEAT}"),
+      Err(_) => bail!("Expected a number at position {}", start + 1),
+    }
+  }
+
+  /// `{n}` or `{n,m}`, after the opening brace.
+  fn repetition(&mut self, pattern: Pattern) -> Result<Pattern> {
+    let min = self.number()?;
+    let max = if self.eat(',') { self.number()? } else { min };
+
+    if !self.eat('}') {
+      bail!("Expected `}}` at position {}", self.pos + 1);
+    }
+
+    if min > max {
+      bail!("Incorrect repetition {{{min},{max}}}");
+    }
+
+    Ok(Pattern::Repeat(Box::new(pattern), min, max))
+  }
+
+  /// `(a|b)`, after the opening parenthesis.
+  fn group(&mut self) -> Result<Pattern> {
+    let position = self.pos;
+    let inner = self.alternation()?;
+
+    if !self.eat(')') {
+      bail!("Unclosed `(` at position {position}");
+    }
+
+    Ok(inner)
+  }
+
+  fn atom(&mut self) -> Result<Pattern> {
+    let Some(c) = self.peek() else {
+      bail!("Unexpected end of pattern");
+    };
+
+    self.pos += 1;
+
+    match c {
+      '?' => Ok(Pattern::Any),
+      '(' => self.group(),
+      _ if !is_syntax(c) => Ok(Pattern::Symbol(c)),
+      _ => bail!("Unexpected `{c}` at position {}", self.pos),
+    }
+  }
+
+  fn repeat(&mut self) -> Result<Pattern> {
+    let atom = self.atom()?;
+
+    if self.eat('{') {
+      self.repetition(atom)
+    } else {
+      Ok(atom)
+    }
+  }
+
+  fn concat(&mut self) -> Result<Pattern> {
+    let mut items = vec![];
+
+    while self.peek().is_some_and(|c| c != '|' && c != ')') {
+      items.push(self.repeat()?);
+    }
+
+    if items.is_empty() {
+      bail!("Empty pattern at position {}", self.pos + 1);
+    }
+
+    Ok(Pattern::Concat(items))
+  }
+
+  fn alternation(&mut self) -> Result<Pattern> {
+    let mut options = vec![self.concat()?];
+
+    while self.eat('|') {
+      options.push(self.concat()?);
+    }
+
+    Ok(if options.len() == 1 {
+      options.remove(0)
+    } else {
+      Pattern::Alternation(options)
+    })
+  }
+}
+
+impl Pattern {
+  /// Matches no sequence.
+  pub const fn nothing() -> Self {
+    Self::Alternation(vec![])
+  }
+
+  pub fn parse(text: &str) -> Result<Self> {
+    let mut parser = Parser {
+      chars: text.chars().collect(),
+      pos: 0,
+    };
+
+    let pattern = parser.alternation()?;
+
+    if let Some(c) = parser.peek() {
+      bail!("Unexpected `{c}` at position {}", parser.pos + 1);
+    }
+
+    if pattern.size() > MAX_SIZE {
+      bail!("The pattern is too large (its repetitions expand past {MAX_SIZE} symbols)");
+    }
+
+    Ok(pattern)
+  }
+
+  /// About how many states the automaton matching it has (the symbols it
+  /// expands to, and the ends of alternatives and repetitions).
+  fn size(&self) -> usize {
+    match self {
+      Self::Symbol(_) | Self::Any => 1,
+      Self::Concat(items) => items.iter().map(Self::size).fold(0, usize::saturating_add),
+      Self::Alternation(options) => (options.iter().map(Self::size)).fold(1, usize::saturating_add),
+      Self::Repeat(pattern, _, max) => pattern.size().saturating_mul(*max).saturating_add(1),
+    }
+  }
+
+  /// Length of the shortest sequence it matches.
+  pub fn min_len(&self) -> usize {
+    match self {
+      Self::Symbol(_) | Self::Any => 1,
+      Self::Concat(items) => items.iter().map(Self::min_len).sum(),
+      Self::Alternation(options) => options.iter().map(Self::min_len).min().unwrap_or(0),
+      Self::Repeat(pattern, min, _) => pattern.min_len() * min,
+    }
+  }
+
+  /// Symbols written literally (i.e. not matched by `?`).
+  pub fn symbols(&self) -> BTreeSet<char> {
+    match self {
+      Self::Symbol(c) => BTreeSet::from([*c]),
+      Self::Any => BTreeSet::new(),
+      Self::Concat(items) | Self::Alternation(items) => {
+        items.iter().flat_map(Self::symbols).collect()
+      }
+      Self::Repeat(pattern, _, _) => pattern.symbols(),
+    }
+  }
+}
+
+#[derive(Clone, Copy)]
+enum Label {
+  Symbol(char),
+  Any,
+  Empty,
+}
+
+/// Nondeterministic automaton matching several patterns, where each pattern
+/// has its own final state. Patterns have no loops, so neither does this.
+pub struct PatternNfa {
+  edges: Vec<Vec<(Label, usize)>>,
+  finals: Vec<Option<usize>>,
+}
+
+impl PatternNfa {
+  pub fn new(patterns: &[Pattern]) -> Self {
+    let mut result = Self {
+      edges: vec![vec![]],
+      finals: vec![None],
+    };
+
+    for (id, pattern) in patterns.iter().enumerate() {
+      let end = result.compile(pattern, 0);
+      let accept = result.add_state();
+      result.edges[end].push((Label::Empty, accept));
+      result.finals[accept] = Some(id);
+    }
+
+    result
+  }
+
+  fn add_state(&mut self) -> usize {
+    self.edges.push(vec![]);
+    self.finals.push(None);
+    self.edges.len() - 1
+  }
+
+  fn edge(&mut self, from: usize, label: Label) -> usize {
+    let to = self.add_state();
+    self.edges[from].push((label, to));
+    to
+  }
+
+  fn compile_repeat(&mut self, pattern: &Pattern, start: usize, min: usize, max: usize) -> usize {
+    let mut curr = (0..min).fold(start, |s, _| self.compile(pattern, s));
+    let end = self.add_state();
+    self.edges[curr].push((Label::Empty, end));
+
+    for _ in min..max {
+      curr = self.compile(pattern, curr);
+      self.edges[curr].push((Label::Empty, end));
+    }
+
+    end
+  }
+
+  /// Adds the states matching the pattern after `start`, and returns the last
+  /// one.
+  fn compile(&mut self, pattern: &Pattern, start: usize) -> usize {
+    match pattern {
+      Pattern::Symbol(c) => self.edge(start, Label::Symbol(*c)),
+      Pattern::Any => self.edge(start, Label::Any),
+      Pattern::Concat(items) => items.iter().fold(start, |s, p| self.compile(p, s)),
+      Pattern::Alternation(options) => {
+        let end = self.add_state();
+
+        for option in options {
+          let last = self.compile(option, start);
+          self.edges[last].push((Label::Empty, end));
+        }
+
+        end
+      }
+      Pattern::Repeat(inner, min, max) => self.compile_repeat(inner, start, *min, *max),
+    }
+  }
+
+  fn closure(&self, states: impl IntoIterator<Item = usize>) -> BTreeSet<usize> {
+    let mut result = BTreeSet::new();
+    let mut stack: Vec<usize> = states.into_iter().collect();
+
+    while let Some(state) = stack.pop() {
+      if result.insert(state) {
+        let empty = self.edges[state]
+          .iter()
+          .filter(|(l, _)| matches!(l, Label::Empty));
+        stack.extend(empty.map(|(_, to)| *to));
+      }
+    }
+
+    result
+  }
+
+  pub fn start(&self) -> BTreeSet<usize> {
+    self.closure([0])
+  }
+
+  pub fn step(&self, states: &BTreeSet<usize>, c: char) -> BTreeSet<usize> {
+    let next = states
+      .iter()
+      .flat_map(|s| &self.edges[*s])
+      .filter_map(|(label, to)| match label {
+        Label::Symbol(symbol) if *symbol == c => Some(*to),
+        Label::Any => Some(*to),
+        _ => None,
+      });
+
+    self.closure(next)
+  }
+
+  /// Ids of the patterns matched when reaching these states.
+  pub fn matched(&self, states: &BTreeSet<usize>) -> Vec<usize> {
+    states.iter().filter_map(|s| self.finals[*s]).collect()
+  }
+
+  /// Lowers the costs to also count skipping symbols of the patterns (i.e.
+  /// symbols missing from the input).
+  fn relax(&self, costs: &mut [usize]) {
+    let mut changed = true;
+
+    while changed {
+      changed = false;
+
+      for (from, edges) in self.edges.iter().enumerate() {
+        for (label, to) in edges {
+          let cost = costs[from].saturating_add(usize::from(!matches!(label, Label::Empty)));
+          changed |= cost < costs[*to];
+          costs[*to] = costs[*to].min(cost);
+        }
+      }
+    }
+  }
+
+  /// Costs after one more input symbol, which can be extra (cost 1), match, or
+  /// replace a symbol of the pattern (cost 1).
+  fn step_costs(&self, costs: &[usize], c: char) -> Vec<usize> {
+    let mut next: Vec<usize> = costs.iter().map(|cost| cost.saturating_add(1)).collect();
+
+    for (from, edges) in self.edges.iter().enumerate() {
+      for (label, to) in edges {
+        let extra = match label {
+          Label::Symbol(symbol) => usize::from(*symbol != c),
+          Label::Any => 0,
+          Label::Empty => continue,
+        };
+        next[*to] = next[*to].min(costs[from].saturating_add(extra));
+      }
+    }
+
+    self.relax(&mut next);
+    next
+  }
+
+  /// Edit distance between the input and the closest sequence matched by each
+  /// pattern (by id). Patterns that match nothing are left out.
+  pub fn distances(&self, input: &str) -> HashMap<usize, usize> {
+    let mut start = vec![usize::MAX; self.edges.len()];
+    start[0] = 0;
+    self.relax(&mut start);
+
+    let costs = input
+      .chars()
+      .fold(start, |costs, c| self.step_costs(&costs, c));
+
+    self
+      .finals
+      .iter()
+      .zip(costs)
+      .filter(|(_, cost)| *cost != usize::MAX)
+      .filter_map(|(id, cost)| Some(((*id)?, cost)))
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test_case::test_case;
+
+  fn matches(pattern: &str, sequence: &str) -> bool {
+    let nfa = PatternNfa::new(&[Pattern::parse(pattern).unwrap()]);
+    let end = sequence.chars().fold(nfa.start(), |s, c| nfa.step(&s, c));
+    !nfa.matched(&end).is_empty()
+  }
+
+  #[test_case(".-", ".-", true; "literal")]
+  #[test_case(".-", ".--", false; "literal too long")]
+  #[test_case(".?", ".-", true; "any symbol")]
+  #[test_case(".?", ".", false; "any symbol missing")]
+  #[test_case("-{3}", "---", true; "repeat")]
+  #[test_case("-{3}", "--", false; "repeat too short")]
+  #[test_case(".{1,3}-", "..-", true; "repeat range")]
+  #[test_case(".{1,3}-", "....-", false; "repeat range too long")]
+  #[test_case("(..|--)-", "---", true; "alternation")]
+  #[test_case("(..|--)-", ".--", false; "alternation mixed")]
+  #[test_case("(.-){2}", ".-.-", true; "repeat group")]
+  #[test_case(".-{0,1}", ".", true; "optional")]
+  fn test_matches(pattern: &str, sequence: &str, expected: bool) {
+    assert_eq!(matches(pattern, sequence), expected);
+  }
+
+  #[test_case("(.."; "unclosed group")]
+  #[test_case("..)"; "unopened group")]
+  #[test_case("(..|)"; "empty alternative")]
+  #[test_case("-{x}"; "repeat not a number")]
+  #[test_case("-{3"; "repeat unclosed")]
+  #[test_case("-{3,1}"; "repeat reversed")]
+  #[test_case("-{500}"; "repeat too many")]
+  #[test_case("((.{50}){50}){50}"; "nested repeats too large")]
+  #[test_case("((((.{50}){50}){50}){50}){50}"; "nested repeats overflowing")]
+  #[test_case("{3}"; "repeat nothing")]
+  #[test_case(""; "empty")]
+  fn test_parse_error(pattern: &str) {
+    assert!(Pattern::parse(pattern).is_err());
+  }
+
+  #[test_case(".-", 2; "min literal")]
+  #[test_case("?{3}", 3; "min repeat")]
+  #[test_case("(.|---)-", 2; "min alternation")]
+  #[test_case(".{0,2}", 0; "min optional")]
+  fn test_min_len(pattern: &str, expected: usize) {
+    assert_eq!(Pattern::parse(pattern).unwrap().min_len(), expected);
+  }
+
+  #[test_case(".-", ".-", 0; "distance same")]
+  #[test_case(".-", ".--", 1; "distance extra symbol")]
+  #[test_case(".--", ".-", 1; "distance missing symbol")]
+  #[test_case(".-.", "..." , 1; "distance replaced symbol")]
+  #[test_case("...", "---", 3; "distance all different")]
+  #[test_case("-{3}.?", "--..", 1; "distance pattern")]
+  #[test_case("(..|--)-", "-.-", 1; "distance alternation")]
+  fn test_distance(pattern: &str, input: &str, expected: usize) {
+    let nfa = PatternNfa::new(&[Pattern::parse(pattern).unwrap()]);
+    assert_eq!(nfa.distances(input), HashMap::from([(0, expected)]));
+  }
+
+  #[test]
+  fn test_distance_nothing() {
+    let nfa = PatternNfa::new(&[Pattern::nothing(), Pattern::parse("..").unwrap()]);
+    assert_eq!(nfa.distances("."), HashMap::from([(1, 1)]));
+  }
+
+  #[test]
+  fn test_symbols() {
+    let pattern = Pattern::parse("(.U|--)?{2}").unwrap();
+    assert_eq!(pattern.symbols(), BTreeSet::from(['-', '.', 'U']));
+  }
+}
@@ -1,9 +1,16 @@
+mod arming_gate;
 mod automata_manager;
-mod config_file_parser;
+pub mod config_diagnostics;
+pub mod config_file_parser;
+pub mod config_includes;
+pub mod config_toml;
 mod global_context;
 mod global_context_installer;
 mod http;
+mod input_events;
 pub mod main;
-mod mouse_events;
+mod parameter_capture;
 mod process_manager;
+pub mod replay;
 mod results_command_exec;
+mod shell_blocks;
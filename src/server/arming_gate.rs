@@ -0,0 +1,182 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+  arming_settings::ArmingSettings,
+  sequence_automata::{AutomataInstruction, SequenceAutomata},
+};
+
+pub struct ArmingGate {
+  settings: ArmingSettings,
+  key_held: bool,
+  /// Sequences sent over HTTP aren't gated.
+  sending: bool,
+  armed_until: Option<Instant>,
+  arm_automata: SequenceAutomata,
+}
+
+fn build_arm_automata(settings: &ArmingSettings) -> SequenceAutomata {
+  let sequences: Vec<&str> = settings.sequence.iter().map(String::as_ref).collect();
+  SequenceAutomata::new(&sequences)
+}
+
+impl ArmingGate {
+  pub fn new(settings: ArmingSettings) -> Self {
+    Self {
+      arm_automata: build_arm_automata(&settings),
+      settings,
+      key_held: false,
+      sending: false,
+      armed_until: None,
+    }
+  }
+
+  pub fn set_settings(&mut self, settings: ArmingSettings) {
+    if settings != self.settings {
+      *self = Self::new(settings);
+    }
+  }
+
+  pub const fn settings(&self) -> &ArmingSettings {
+    &self.settings
+  }
+
+  pub fn is_armed(&self, now: Instant) -> bool {
+    !self.settings.is_enabled() || self.key_held || self.armed_until.is_some_and(|t| now < t)
+  }
+
+  /// Time until the gate disarms by itself, if it's armed by a sequence (and
+  /// hasn't disarmed yet).
+  pub fn time_left(&self, now: Instant) -> Option<Duration> {
+    self
+      .armed_until
+      .filter(|t| !self.key_held && now < *t)
+      .map(|t| t - now)
+  }
+
+  fn arm_for_a_while(&mut self, now: Instant) {
+    let timeout = u64::try_from(self.settings.timeout_ms).unwrap_or(u64::MAX);
+    self.armed_until = Some(now + Duration::from_millis(timeout));
+  }
+
+  fn feed_arm_sequence(&mut self, instruction: AutomataInstruction, now: Instant) {
+    if self.arm_automata.put(instruction).is_some() {
+      self.arm_automata.put(AutomataInstruction::Reset);
+      self.arm_for_a_while(now);
+    }
+  }
+
+  /// Returns whether the instruction must be given to the command automata.
+  pub fn filter(&mut self, instruction: AutomataInstruction, now: Instant) -> bool {
+    match instruction {
+      AutomataInstruction::Arm => self.key_held = true,
+      AutomataInstruction::Disarm => self.key_held = false,
+      AutomataInstruction::SentStart => self.sending = true,
+      AutomataInstruction::SentEnd => self.sending = false,
+      _ if self.sending => return true,
+      _ if self.is_armed(now) => {
+        if self.armed_until.is_some_and(|t| now < t) {
+          self.arm_for_a_while(now);
+        }
+        return true;
+      }
+      _ => self.feed_arm_sequence(instruction, now),
+    }
+
+    false
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rdev::Key;
+
+  fn char_instruction(c: char) -> AutomataInstruction {
+    match c {
+      'R' => AutomataInstruction::Reset,
+      'A' => AutomataInstruction::Arm,
+      'D' => AutomataInstruction::Disarm,
+      'S' => AutomataInstruction::SentStart,
+      'E' => AutomataInstruction::SentEnd,
+      _ => AutomataInstruction::Char(c),
+    }
+  }
+
+  fn run(gate: &mut ArmingGate, instructions: &str, now: Instant) -> String {
+    instructions
+      .chars()
+      .filter(|c| gate.filter(char_instruction(*c), now))
+      .collect()
+  }
+
+  fn sequence_gate() -> ArmingGate {
+    ArmingGate::new(ArmingSettings {
+      sequence: Some("..--".to_owned()),
+      timeout_ms: 1000,
+      ..ArmingSettings::default()
+    })
+  }
+
+  #[test]
+  fn test_disabled_lets_everything_pass() {
+    let mut gate = ArmingGate::new(ArmingSettings::default());
+    let now = Instant::now();
+    assert_eq!(run(&mut gate, "R.-R", now), "R.-R");
+    assert!(gate.is_armed(now));
+    assert_eq!(gate.time_left(now), None);
+  }
+
+  #[test]
+  fn test_key() {
+    let mut gate = ArmingGate::new(ArmingSettings {
+      key: Some(Key::ControlRight),
+      ..ArmingSettings::default()
+    });
+    let now = Instant::now();
+    assert_eq!(run(&mut gate, "R.-A..-D-.R", now), "..-");
+    assert!(!gate.is_armed(now));
+  }
+
+  #[test]
+  fn test_sent_sequences_bypass_the_gate() {
+    let mut gate = ArmingGate::new(ArmingSettings {
+      key: Some(Key::ControlRight),
+      ..ArmingSettings::default()
+    });
+    let now = Instant::now();
+    assert_eq!(run(&mut gate, "S.-E.-", now), ".-");
+    assert_eq!(run(&mut gate, "A.S-E.", now), ".-.");
+    assert!(gate.is_armed(now));
+  }
+
+  #[test]
+  fn test_sequence() {
+    let mut gate = sequence_gate();
+    let now = Instant::now();
+    assert_eq!(run(&mut gate, "R.-R..--R.-.", now), "R.-.");
+    assert!(gate.is_armed(now));
+    assert_eq!(gate.time_left(now), Some(Duration::from_secs(1)));
+  }
+
+  #[test]
+  fn test_sequence_timeout() {
+    let mut gate = sequence_gate();
+    let now = Instant::now();
+    let later = now + Duration::from_millis(1500);
+    assert_eq!(run(&mut gate, "..--", now), "");
+    assert!(gate.is_armed(now));
+    assert!(!gate.is_armed(later));
+    assert_eq!(gate.time_left(later), None);
+    assert_eq!(run(&mut gate, "R.-", later), "");
+  }
+
+  #[test]
+  fn test_sequence_activity_extends_timeout() {
+    let mut gate = sequence_gate();
+    let now = Instant::now();
+    let later = now + Duration::from_millis(800);
+    run(&mut gate, "..--", now);
+    assert_eq!(run(&mut gate, ".", later), ".");
+    assert!(gate.is_armed(later + Duration::from_millis(800)));
+  }
+}
@@ -1,8 +1,16 @@
-use super::global_context::MainProcessState;
+use super::{
+  global_context::MainProcessState,
+  parameter_capture::CommandMatch,
+  results_command_exec::{pick_matches, with_commands, MatchedCommand},
+};
+use crate::cmd::Cmd;
 use crate::event_observe::EventType;
+use crate::mode::DEFAULT_MODE;
 use crate::sequence_automata::AutomataInstruction;
-use std::sync::mpsc::{Receiver, Sender};
-use std::sync::Mutex;
+use anyhow::Result;
+use std::sync::mpsc::{Receiver, RecvError, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 static SEND_ERR: &str = "Should send event";
 
@@ -10,30 +18,210 @@ fn notify_instruction(events_sender: &Sender<EventType>, instruction: AutomataIn
   let event = match instruction {
     AutomataInstruction::Char(c) => EventType::SequenceItem(c),
     AutomataInstruction::Reset => EventType::SequenceReset,
+    AutomataInstruction::Arm
+    | AutomataInstruction::Disarm
+    | AutomataInstruction::SentStart
+    | AutomataInstruction::SentEnd
+    | AutomataInstruction::Wake
+    | AutomataInstruction::Press => return,
   };
 
   events_sender.send(event).expect(SEND_ERR);
 }
 
-pub fn manage_automata(
-  results_sender: &Sender<usize>,
-  sequence_rec: Receiver<AutomataInstruction>,
+/// How long to wait for an instruction before the sequence ends by itself
+/// (e.g. the arming expires, a shorter sequence has been waiting for a
+/// longer one, or the mode goes back to the default one).
+fn idle_timeout(state: &MainProcessState) -> Option<Duration> {
+  let now = Instant::now();
+  let arming = state.arming_gate.time_left(now);
+  let reset_ms = u64::try_from(state.effective_timing().sequence_reset_ms).unwrap_or(u64::MAX);
+  let waiting = state.is_waiting().then(|| Duration::from_millis(reset_ms));
+
+  [arming, waiting, state.mode_time_left(now)]
+    .into_iter()
+    .flatten()
+    .min()
+}
+
+/// Waits for the next instruction. Returns `None` when the idle timeout
+/// elapsed. Waking up only computes the idle timeout again.
+fn next_instruction(
+  sequence_rec: &Receiver<AutomataInstruction>,
+  state: &Mutex<MainProcessState>,
+) -> Result<Option<AutomataInstruction>, RecvError> {
+  let time_left = idle_timeout(&state.lock().unwrap());
+
+  let received = time_left.map_or_else(
+    || {
+      sequence_rec
+        .recv()
+        .map_err(|_| RecvTimeoutError::Disconnected)
+    },
+    |timeout| sequence_rec.recv_timeout(timeout),
+  );
+
+  match received {
+    Ok(AutomataInstruction::Wake) => next_instruction(sequence_rec, state),
+    Ok(instruction) => Ok(Some(instruction)),
+    Err(RecvTimeoutError::Timeout) => Ok(None),
+    Err(RecvTimeoutError::Disconnected) => Err(RecvError),
+  }
+}
+
+fn notify_arming_change(
+  state: &mut MainProcessState,
+  events_sender: &Sender<EventType>,
+  was_armed: bool,
+  now: Instant,
+) {
+  let is_armed = state.arming_gate.is_armed(now);
+
+  if was_armed != is_armed {
+    state.reset_sequence();
+
+    let event = if is_armed {
+      EventType::Armed
+    } else {
+      EventType::Disarmed
+    };
+
+    events_sender.send(event).expect(SEND_ERR);
+  }
+}
+
+fn notify_near_miss(state: &mut MainProcessState, events_sender: &Sender<EventType>) {
+  if let Some(sequences) = state.near_miss.take() {
+    events_sender
+      .send(EventType::NearMiss(sequences))
+      .expect(SEND_ERR);
+  }
+}
+
+pub fn change_mode(
+  state: &mut MainProcessState,
+  events_sender: &Sender<EventType>,
+  mode: &str,
+  now: Instant,
+) -> Result<()> {
+  state.switch_mode(mode, now)?;
+
+  events_sender
+    .send(EventType::ModeChanged(mode.to_owned()))
+    .expect(SEND_ERR);
+  Ok(())
+}
+
+/// A mode with a timeout goes back to the default one after that long without
+/// instructions.
+fn track_mode_activity(
+  instruction: Option<AutomataInstruction>,
+  state: &mut MainProcessState,
+  events_sender: &Sender<EventType>,
+  now: Instant,
+) {
+  if state.mode_time_left(now) == Some(Duration::ZERO) {
+    change_mode(state, events_sender, DEFAULT_MODE, now).expect("The default mode should exist");
+  }
+
+  if instruction.is_some() {
+    state.mode_activity = now;
+  }
+}
+
+/// When several matched commands switch modes, the last one wins.
+pub fn switch_mode_after(
+  commands: &[Cmd],
+  state: &mut MainProcessState,
+  events_sender: &Sender<EventType>,
+  now: Instant,
+) {
+  let switch = commands
+    .iter()
+    .rev()
+    .find_map(|cmd| cmd.options.switch.clone());
+
+  if let Err(err) = switch.map_or(Ok(()), |mode| change_mode(state, events_sender, &mode, now)) {
+    eprintln!("{err}");
+  }
+}
+
+/// Without an instruction (the idle timeout elapsed), the sequence ends.
+/// Timeouts are measured with `now` (the time of the instruction, which is
+/// the recorded one when replaying).
+pub fn process_instruction(
+  instruction: Option<AutomataInstruction>,
+  now: Instant,
+  state: &mut MainProcessState,
+  events_sender: &Sender<EventType>,
+) -> Option<Vec<CommandMatch>> {
+  track_mode_activity(instruction, state, events_sender, now);
+  let was_armed = state.arming_gate.is_armed(now);
+  let pass = instruction.filter(|i| state.arming_gate.filter(*i, now));
+
+  instruction.inspect(|i| notify_instruction(events_sender, *i));
+  notify_arming_change(state, events_sender, was_armed, now);
+
+  let results = match (instruction, pass) {
+    (None, _) => state.put_instruction(AutomataInstruction::Reset),
+    (_, Some(i)) => state.put_instruction(i),
+    (Some(_), None) => None,
+  };
+
+  notify_near_miss(state, events_sender);
+  results
+}
+
+fn found_event(commands: &[Cmd]) -> EventType {
+  let names: Vec<String> = commands
+    .iter()
+    .filter_map(Cmd::name)
+    .map(str::to_owned)
+    .collect();
+
+  if names.is_empty() {
+    EventType::FoundResults
+  } else {
+    EventType::FoundNamedResults(names)
+  }
+}
+
+/// Only the commands that run are announced, and can switch modes.
+fn send_picked(
+  picked: Vec<MatchedCommand>,
+  results_sender: &Sender<Vec<MatchedCommand>>,
   events_sender: &Sender<EventType>,
   state: &Mutex<MainProcessState>,
 ) {
-  for instruction in sequence_rec {
-    notify_instruction(events_sender, instruction);
+  if picked.is_empty() {
+    return;
+  }
+
+  let commands: Vec<Cmd> = picked.iter().map(|(_, cmd)| cmd.clone()).collect();
+  let mut guard = state.lock().unwrap();
+  events_sender.send(found_event(&commands)).expect(SEND_ERR);
+  switch_mode_after(&commands, &mut guard, events_sender, Instant::now());
+  drop(guard);
 
-    let put_result = state.lock().unwrap().automata.put(instruction);
+  results_sender.send(picked).expect("Result should be sent");
+}
 
-    if let Some(results) = put_result {
-      events_sender.send(EventType::FoundResults).expect(SEND_ERR);
+pub fn manage_automata(
+  results_sender: &Sender<Vec<MatchedCommand>>,
+  sequence_rec: &Receiver<AutomataInstruction>,
+  events_sender: &Sender<EventType>,
+  state: &Mutex<MainProcessState>,
+) {
+  while let Ok(instruction) = next_instruction(sequence_rec, state) {
+    let mut guard = state.lock().unwrap();
+    let results = process_instruction(instruction, Instant::now(), &mut guard, events_sender);
+    let matched = results.map(|matches| with_commands(&guard, matches));
+    let provider = Arc::clone(&guard.window_provider);
+    drop(guard);
 
-      for result_id in results {
-        results_sender
-          .send(result_id)
-          .expect("Result should be sent");
-      }
+    if let Some(commands) = matched {
+      let picked = pick_matches(commands, provider.as_ref());
+      send_picked(picked, results_sender, events_sender, state);
     }
   }
 }
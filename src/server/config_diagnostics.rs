@@ -0,0 +1,606 @@
+use std::{collections::HashMap, fmt::Display};
+
+use super::{
+  config_file_parser::parse_setting,
+  shell_blocks::{unclosed_text, Block, Placement},
+};
+use crate::{
+  cmd::{encode_alias, Cmd},
+  input_settings::SEQUENCE_SYMBOLS,
+  sequence_pattern::{Pattern, PatternNfa},
+  timing_settings::{parse_flag_value, parse_ms_value},
+};
+
+/// Sequences shorter than this can be clicked by accident (e.g. a double
+/// click, or a click followed by a drag).
+const MIN_SAFE_LEN: usize = 3;
+
+/// A triple click is common enough to be clicked by accident too.
+const TRIPLE_CLICK: &str = "...";
+
+/// Sequences this many clicks apart can be confused by a single mistake.
+const TOO_CLOSE_DISTANCE: usize = 1;
+
+/// Symbols that aren't commands in the shell on their own, so a line starting
+/// with one of them was probably meant to be a sequence (`:`, `<` and `>` are
+/// valid shell code, `.` is checked separately).
+const SINGLE_SYMBOL_TYPOS: &str = "-,_=^";
+
+/// Characters a sequence can have besides symbols (for patterns).
+const PATTERN_CHARS: &str = "?(|){}0123456789";
+
+/// The settings (without `HORSETAB_`) that are durations in milliseconds.
+const MS_SETTINGS: [&str; 3] = ["LONG_PRESS_MS", "SEQUENCE_RESET_MS", "ARM_TIMEOUT_MS"];
+
+/// The settings that are turned on or off.
+const FLAG_SETTINGS: [&str; 6] = [
+  "ADAPTIVE",
+  "WAIT_FOR_LONGER",
+  "BUTTON_SYMBOLS",
+  "WHEEL",
+  "GESTURES",
+  "MATCH_ANYWHERE",
+];
+
+/// The other settings, whose values aren't checked here.
+const OTHER_SETTINGS: [&str; 5] = ["BUTTON", "KEY", "ARM_KEY", "ARM_SEQUENCE", "FUZZY_DISTANCE"];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+  /// The same sequence is on another line.
+  Duplicate(Location),
+  /// A shorter sequence always matches first.
+  Unreachable,
+  /// A single wrong, missing or extra click (or none) turns one into the
+  /// other.
+  TooClose(String),
+  /// Ordinary clicking can match it.
+  TooShort,
+  /// The line looks like a command, but isn't one, so it runs as script.
+  NotACommand(NearMiss),
+  /// The command is written inside a shell block.
+  InsideBlock(Block),
+  /// An included file is already being included (by itself, or by a file it
+  /// includes).
+  IncludeCycle,
+  /// An included file can't be read (with the error).
+  Unreadable(String),
+  /// The command switches to a mode that has no section.
+  UnknownMode(String),
+  /// A heredoc or quoted string starts here, and the file ends before it's
+  /// closed.
+  Unclosed,
+  /// A `HORSETAB_` line that doesn't change any setting.
+  IgnoredSetting(SettingProblem),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NearMiss {
+  /// These characters aren't symbols.
+  OtherCharacters(String),
+  SingleSymbol,
+  InvalidPattern,
+  InvalidAlias,
+  MissingCommand,
+}
+
+impl Display for NearMiss {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::OtherCharacters(chars) => write!(f, "not symbols: {chars}"),
+      Self::SingleSymbol => write!(f, "a sequence needs at least two symbols"),
+      Self::InvalidPattern => write!(f, "not a valid pattern"),
+      Self::InvalidAlias => write!(f, "not a valid alias"),
+      Self::MissingCommand => write!(f, "no command after it"),
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SettingProblem {
+  Unknown,
+  NotMilliseconds,
+  NotAFlag,
+}
+
+impl Display for SettingProblem {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Unknown => write!(f, "not a setting"),
+      Self::NotMilliseconds => write!(f, "not a number of milliseconds above 0"),
+      Self::NotAFlag => write!(f, "not on or off"),
+    }
+  }
+}
+
+/// Where a line is: its number (starting at 1), and the file it's in, unless
+/// it's the main file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Location {
+  pub file: Option<String>,
+  pub line: usize,
+}
+
+impl Location {
+  pub const fn new(line: usize) -> Self {
+    Self { file: None, line }
+  }
+}
+
+impl Display for Location {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match &self.file {
+      Some(file) => write!(f, "line {} of {file}", self.line),
+      None => write!(f, "line {}", self.line),
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+  pub location: Location,
+  pub sequence: String,
+  pub problem: Problem,
+}
+
+const UNREACHABLE: &str = "is unreachable (a shorter sequence always matches first)";
+const UNCLOSED: &str =
+  "isn't closed, so the rest of the file is text (its commands aren't defined)";
+
+impl Display for Problem {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Duplicate(other) => write!(f, "is also on {other}"),
+      Self::Unreachable => write!(f, "{UNREACHABLE}"),
+      Self::TooClose(other) => write!(f, "can be confused with {other}"),
+      Self::TooShort => write!(f, "can be clicked by accident"),
+      Self::NotACommand(reason) => write!(f, "isn't a command ({reason}), so it runs as script"),
+      Self::InsideBlock(block) => write!(f, "is inside {block}, but commands are always defined"),
+      Self::IncludeCycle => write!(f, "is already being included, so it's skipped"),
+      Self::Unreadable(err) => write!(f, "can't be read ({err}), so it's skipped"),
+      Self::UnknownMode(mode) => write!(f, "switches to {mode}, but there's no [mode {mode}]"),
+      Self::Unclosed => write!(f, "{UNCLOSED}"),
+      Self::IgnoredSetting(reason) => write!(f, "is ignored ({reason})"),
+    }
+  }
+}
+
+impl Display for Diagnostic {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let Self {
+      location,
+      sequence,
+      problem,
+    } = self;
+    write!(f, "{location}: {sequence} {problem}")
+  }
+}
+
+/// The only sequence a pattern matches, if it has no `?`, repetitions or
+/// alternatives.
+fn literal(pattern: &Pattern) -> Option<String> {
+  match pattern {
+    Pattern::Symbol(c) => Some(c.to_string()),
+    Pattern::Concat(items) => items.iter().map(literal).collect(),
+    _ => None,
+  }
+}
+
+fn parse_or_nothing(sequence: &str) -> Pattern {
+  Pattern::parse(sequence).unwrap_or_else(|_| Pattern::nothing())
+}
+
+struct Analysis<'a> {
+  sequences: &'a [&'a str],
+  lines: &'a [usize],
+  unreachable: &'a [String],
+  patterns: Vec<Pattern>,
+  /// Distances from a triple click to every pattern.
+  triple_click: HashMap<usize, usize>,
+  /// Distances from each literal sequence to every pattern.
+  distances: Vec<Option<HashMap<usize, usize>>>,
+}
+
+impl<'a> Analysis<'a> {
+  fn new(sequences: &'a [&'a str], lines: &'a [usize], unreachable: &'a [String]) -> Self {
+    let patterns: Vec<Pattern> = sequences.iter().map(|s| parse_or_nothing(s)).collect();
+    let nfa = PatternNfa::new(&patterns);
+
+    Self {
+      sequences,
+      lines,
+      unreachable,
+      triple_click: nfa.distances(TRIPLE_CLICK),
+      distances: patterns
+        .iter()
+        .map(|p| literal(p).map(|l| nfa.distances(&l)))
+        .collect(),
+      patterns,
+    }
+  }
+
+  fn duplicate(&self, i: usize) -> Option<Problem> {
+    let first = self
+      .sequences
+      .iter()
+      .position(|s| *s == self.sequences[i])?;
+    (first < i).then(|| Problem::Duplicate(Location::new(self.lines[first])))
+  }
+
+  fn is_unreachable(&self, i: usize) -> bool {
+    self.unreachable.iter().any(|s| s == self.sequences[i])
+  }
+
+  fn too_short(&self, i: usize) -> Option<Problem> {
+    let triple_click = self.triple_click.get(&i) == Some(&0);
+    (self.patterns[i].min_len() < MIN_SAFE_LEN || triple_click).then_some(Problem::TooShort)
+  }
+
+  /// Distance between two sequences, when one of them is literal (patterns
+  /// aren't compared with each other).
+  fn distance(&self, i: usize, j: usize) -> Option<usize> {
+    let from_i = self.distances[i].as_ref().and_then(|d| d.get(&j));
+    let from_j = self.distances[j].as_ref().and_then(|d| d.get(&i));
+    from_i.or(from_j).copied()
+  }
+
+  /// Only reported on the later of the two sequences.
+  fn too_close(&self, i: usize) -> Option<Problem> {
+    let other = (0..i).find(|j| {
+      self.sequences[*j] != self.sequences[i]
+        && self
+          .distance(i, *j)
+          .is_some_and(|d| d <= TOO_CLOSE_DISTANCE)
+    })?;
+
+    Some(Problem::TooClose(self.sequences[other].to_owned()))
+  }
+
+  /// An unreachable or duplicate sequence isn't checked any further.
+  fn problems(&self, i: usize) -> Vec<Problem> {
+    if let Some(duplicate) = self.duplicate(i) {
+      return vec![duplicate];
+    }
+
+    if self.is_unreachable(i) {
+      return vec![Problem::Unreachable];
+    }
+
+    [self.too_short(i), self.too_close(i)]
+      .into_iter()
+      .flatten()
+      .collect()
+  }
+}
+
+/// Problems with the sequences configured on these lines, given the ones that
+/// are unreachable.
+pub fn diagnose(sequences: &[&str], lines: &[usize], unreachable: &[String]) -> Vec<Diagnostic> {
+  let analysis = Analysis::new(sequences, lines, unreachable);
+
+  (0..sequences.len())
+    .flat_map(|i| {
+      analysis
+        .problems(i)
+        .into_iter()
+        .map(move |problem| Diagnostic {
+          location: Location::new(lines[i]),
+          sequence: sequences[i].to_owned(),
+          problem,
+        })
+    })
+    .collect()
+}
+
+fn is_symbol(c: char) -> bool {
+  SEQUENCE_SYMBOLS.contains(c)
+}
+
+/// `. file` sources a file, but `. word` looks more like a sequence that is
+/// too short.
+fn is_single_symbol_typo(symbol: char, argument: Option<&str>) -> bool {
+  let plain_word = |word: &str| word.chars().all(|c| c.is_alphanumeric() || c == '_');
+
+  match symbol {
+    '.' => argument.is_some_and(plain_word),
+    _ => argument.is_some() && SINGLE_SYMBOL_TYPOS.contains(symbol),
+  }
+}
+
+/// Mostly dots and dashes (e.g. `.-.x-`), but not a path.
+fn is_morse_like(word: &str) -> bool {
+  let morse = word.chars().filter(|c| ".-".contains(*c)).count();
+  word.starts_with(['.', '-']) && !word.contains('/') && morse >= 2 && morse * 2 >= word.len()
+}
+
+fn other_characters(word: &str) -> String {
+  let mut chars: Vec<char> = vec![];
+
+  for c in word.chars() {
+    if !is_symbol(c) && !PATTERN_CHARS.contains(c) && !chars.contains(&c) {
+      chars.push(c);
+    }
+  }
+
+  chars.into_iter().collect()
+}
+
+/// Why a sequence made only of symbols and pattern characters isn't a command.
+fn sequence_near_miss(word: &str, argument: Option<&str>) -> NearMiss {
+  match Pattern::parse(word).map(|pattern| pattern.min_len()) {
+    Ok(len) if len < 2 => NearMiss::SingleSymbol,
+    Ok(_) if argument.is_none() => NearMiss::MissingCommand,
+    _ => NearMiss::InvalidPattern,
+  }
+}
+
+fn alias_near_miss(alias: &str, argument: Option<&str>) -> NearMiss {
+  match encode_alias(alias) {
+    Some(_) if argument.is_none() => NearMiss::MissingCommand,
+    _ => NearMiss::InvalidAlias,
+  }
+}
+
+/// What is wrong with the first word of a line that isn't a command, if it
+/// looks like a sequence.
+fn near_miss(line: &str) -> Option<(&str, NearMiss)> {
+  let mut words = line.split_whitespace();
+  let word = words.next().filter(|w| !w.starts_with('#'))?;
+  let argument = words.next();
+
+  word
+    .strip_prefix('@')
+    .filter(|a| !a.is_empty())
+    .map_or_else(
+      || symbols_near_miss(word, argument),
+      |alias| Some((word, alias_near_miss(alias, argument))),
+    )
+}
+
+fn symbols_near_miss<'a>(word: &'a str, argument: Option<&str>) -> Option<(&'a str, NearMiss)> {
+  let mut chars = word.chars();
+
+  if let (Some(symbol), None) = (chars.next(), chars.next()) {
+    return is_single_symbol_typo(symbol, argument).then_some((word, NearMiss::SingleSymbol));
+  }
+
+  let other = other_characters(word);
+
+  if other.is_empty() && word.contains(is_symbol) {
+    Some((word, sequence_near_miss(word, argument)))
+  } else {
+    is_morse_like(word).then_some((word, NearMiss::OtherCharacters(other)))
+  }
+}
+
+fn line_problem(line: &str, placement: Placement) -> Option<(String, Problem)> {
+  match placement {
+    Placement::Script => {
+      near_miss(line).map(|(word, reason)| (word.to_owned(), Problem::NotACommand(reason)))
+    }
+    Placement::Command(Some(block)) => {
+      Some((Cmd::parse(line).ok()?.sequence, Problem::InsideBlock(block)))
+    }
+    Placement::Command(None) | Placement::Continuation | Placement::Text => None,
+  }
+}
+
+/// A heredoc or quoted string that the file ends in, on the line it starts.
+pub fn diagnose_unclosed(lines: &[String]) -> Option<Diagnostic> {
+  let (line, opening) = unclosed_text(lines)?;
+
+  Some(Diagnostic {
+    location: Location::new(line),
+    sequence: opening,
+    problem: Problem::Unclosed,
+  })
+}
+
+fn setting_problem(key: &str, value: &str) -> Option<SettingProblem> {
+  if MS_SETTINGS.contains(&key) {
+    parse_ms_value(value)
+      .is_none()
+      .then_some(SettingProblem::NotMilliseconds)
+  } else if FLAG_SETTINGS.contains(&key) {
+    parse_flag_value(value)
+      .is_none()
+      .then_some(SettingProblem::NotAFlag)
+  } else {
+    (!OTHER_SETTINGS.contains(&key)).then_some(SettingProblem::Unknown)
+  }
+}
+
+/// Settings that are misspelled, or whose values can't be used, so the
+/// default is kept.
+pub fn diagnose_settings(lines: &[String]) -> Vec<Diagnostic> {
+  (1..)
+    .zip(lines)
+    .filter_map(|(number, line)| {
+      let (key, value) = parse_setting(line)?;
+      setting_problem(&key, &value).map(|problem| Diagnostic {
+        location: Location::new(number),
+        sequence: format!("HORSETAB_{key}={value}"),
+        problem: Problem::IgnoredSetting(problem),
+      })
+    })
+    .collect()
+}
+
+/// Problems with the lines themselves: commands inside shell blocks, and lines
+/// that aren't commands but look like one (e.g. because of a typo in the
+/// sequence).
+pub fn diagnose_lines(lines: &[String], placements: &[Placement]) -> Vec<Diagnostic> {
+  (1..)
+    .zip(lines.iter().zip(placements))
+    .filter_map(|(number, (line, placement))| {
+      line_problem(line, *placement).map(|(sequence, problem)| Diagnostic {
+        location: Location::new(number),
+        sequence,
+        problem,
+      })
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test_case::test_case;
+  use Placement::{Command, Script};
+
+  fn problems(sequences: &[&str], unreachable: &[&str]) -> Vec<(usize, Problem)> {
+    let lines: Vec<usize> = (1..=sequences.len()).collect();
+    let unreachable_owned: Vec<String> = unreachable.iter().map(|s| (*s).to_owned()).collect();
+
+    diagnose(sequences, &lines, &unreachable_owned)
+      .into_iter()
+      .map(|d| (d.location.line, d.problem))
+      .collect()
+  }
+
+  #[test]
+  fn test_no_problems() {
+    assert!(problems(&["..--", "----", "(.-|-.){2}"], &[]).is_empty());
+  }
+
+  #[test]
+  fn test_duplicate() {
+    assert_eq!(
+      problems(&["..--", "-..-", "..--"], &[]),
+      vec![(3, Problem::Duplicate(Location::new(1)))]
+    );
+  }
+
+  #[test]
+  fn test_unreachable() {
+    assert_eq!(
+      problems(&["..--", "..--.", "..---"], &["..--.", "..---"]),
+      vec![(2, Problem::Unreachable), (3, Problem::Unreachable)]
+    );
+  }
+
+  #[test]
+  fn test_too_close() {
+    assert_eq!(
+      problems(&["..--", "-..-", "..-.-"], &[]),
+      vec![(3, Problem::TooClose("..--".to_owned()))]
+    );
+  }
+
+  #[test]
+  fn test_too_close_pattern() {
+    assert_eq!(
+      problems(&["-{3}.?", "--.."], &[]),
+      vec![(2, Problem::TooClose("-{3}.?".to_owned()))]
+    );
+  }
+
+  #[test]
+  fn test_too_short() {
+    assert_eq!(
+      problems(&[".-", "...", "-..-"], &[]),
+      vec![(1, Problem::TooShort), (2, Problem::TooShort)]
+    );
+  }
+
+  #[test_case(".-.x- echo a" => Some((".-.x-", NearMiss::OtherCharacters("x".to_owned()))); "other characters")]
+  #[test_case("..--# echo a" => Some(("..--#", NearMiss::OtherCharacters("#".to_owned()))); "comment without space")]
+  #[test_case("- echo a" => Some(("-", NearMiss::SingleSymbol)); "single symbol")]
+  #[test_case(". cmd" => Some((".", NearMiss::SingleSymbol)); "single dot")]
+  #[test_case("(.|-) echo a" => Some(("(.|-)", NearMiss::SingleSymbol)); "short pattern")]
+  #[test_case("(..|-- echo a" => Some(("(..|--", NearMiss::InvalidPattern)); "invalid pattern")]
+  #[test_case("((.{50}){50}){50} echo a" => Some(("((.{50}){50}){50}", NearMiss::InvalidPattern)); "pattern too large")]
+  #[test_case("  ..-- " => Some(("..--", NearMiss::MissingCommand)); "missing command")]
+  #[test_case("@S!S echo a" => Some(("@S!S", NearMiss::InvalidAlias)); "invalid alias")]
+  #[test_case("@SOS" => Some(("@SOS", NearMiss::MissingCommand)); "alias without command")]
+  #[test_case(". /home/user/file" => None; "source")]
+  #[test_case(". \"$HOME/.profile\"" => None; "source variable")]
+  #[test_case(": ${A:=1}" => None; "colon")]
+  #[test_case("> file" => None; "redirection")]
+  #[test_case("./script.sh" => None; "script")]
+  #[test_case("../bin/run -v" => None; "parent directory")]
+  #[test_case("((i++))" => None; "arithmetic")]
+  #[test_case("# ..-- comment" => None; "comment")]
+  #[test_case("echo -.-." => None; "shell")]
+  fn test_near_miss(line: &str) -> Option<(&str, NearMiss)> {
+    near_miss(line)
+  }
+
+  #[test]
+  fn test_diagnose_lines() {
+    let lines = ["if true; then", ".-.- b", "fi", "-.x. c", "..-- d"].map(str::to_owned);
+    let placements = [
+      Script,
+      Command(Some(Block::If)),
+      Script,
+      Script,
+      Command(None),
+    ];
+    let problems: Vec<(usize, Problem)> = diagnose_lines(&lines, &placements)
+      .into_iter()
+      .map(|d| (d.location.line, d.problem))
+      .collect();
+    let other_characters = NearMiss::OtherCharacters("x".to_owned());
+    assert_eq!(problems[0], (2, Problem::InsideBlock(Block::If)));
+    assert_eq!(problems[1..], [(4, Problem::NotACommand(other_characters))]);
+  }
+
+  #[test_case("LONG_PRESS_MS", "250" => None; "milliseconds")]
+  #[test_case("SEQUENCE_RESET_MS", "0" => Some(SettingProblem::NotMilliseconds); "zero")]
+  #[test_case("ARM_TIMEOUT_MS", "-5" => Some(SettingProblem::NotMilliseconds); "negative")]
+  #[test_case("LONG_PRESS_MS", "abc" => Some(SettingProblem::NotMilliseconds); "not a number")]
+  #[test_case("WAIT_FOR_LONGER", "Yes" => None; "flag")]
+  #[test_case("ADAPTIVE", "maybe" => Some(SettingProblem::NotAFlag); "not a flag")]
+  #[test_case("BUTTON", "middle" => None; "other setting")]
+  #[test_case("LONG_PRES_MS", "250" => Some(SettingProblem::Unknown); "misspelled")]
+  fn test_setting_problem(key: &str, value: &str) -> Option<SettingProblem> {
+    setting_problem(key, value)
+  }
+
+  #[test]
+  fn test_diagnose_settings() {
+    let lines = ["HORSETAB_WHEEL=on", "echo", "HORSETAB_SEQUENCE_RESET_MS=0"].map(str::to_owned);
+    let messages: Vec<String> = diagnose_settings(&lines)
+      .iter()
+      .map(ToString::to_string)
+      .collect();
+    assert_eq!(
+      messages,
+      ["line 3: HORSETAB_SEQUENCE_RESET_MS=0 is ignored (not a number of milliseconds above 0)"]
+    );
+  }
+
+  #[test_case(&Problem::NotACommand(NearMiss::SingleSymbol) => "isn't a command (a sequence needs at least two symbols), so it runs as script"; "not a command")]
+  #[test_case(&Problem::InsideBlock(Block::Function) => "is inside a function, but commands are always defined"; "inside block")]
+  #[test_case(&Problem::Unclosed => "isn't closed, so the rest of the file is text (its commands aren't defined)"; "unclosed")]
+  #[test_case(&Problem::IgnoredSetting(SettingProblem::Unknown) => "is ignored (not a setting)"; "ignored setting")]
+  fn test_display_problem(problem: &Problem) -> String {
+    problem.to_string()
+  }
+
+  #[test]
+  fn test_display() {
+    let diagnostic = Diagnostic {
+      location: Location::new(4),
+      sequence: "..-".to_owned(),
+      problem: Problem::Duplicate(Location::new(2)),
+    };
+    assert_eq!(diagnostic.to_string(), "line 4: ..- is also on line 2");
+  }
+
+  #[test]
+  fn test_display_included() {
+    let diagnostic = Diagnostic {
+      location: Location {
+        file: Some("conf.d/b.conf".to_owned()),
+        line: 3,
+      },
+      sequence: "a.conf".to_owned(),
+      problem: Problem::IncludeCycle,
+    };
+    assert_eq!(
+      diagnostic.to_string(),
+      "line 3 of conf.d/b.conf: a.conf is already being included, so it's skipped"
+    );
+  }
+}
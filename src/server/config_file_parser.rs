@@ -1,123 +1,387 @@
-use std::collections::BTreeSet;
+use std::{cell::OnceCell, collections::HashMap, sync::Mutex};
 
+use regex::Regex;
+
+use super::{
+  config_diagnostics::{
+    diagnose, diagnose_lines, diagnose_settings, diagnose_unclosed, Diagnostic, Location, Problem,
+  },
+  config_includes::ConfigSource,
+  shell_blocks::{placements, Placement},
+};
 use crate::{
-  cmd::Cmd,
-  sequence_automata::{AutomataInstruction, SequenceAutomata},
+  cmd::{Annotation, Cmd},
+  match_settings::MatchSettings,
+  mode::{merge_modes, ModeHeader, DEFAULT_MODE},
+  sequence_automata::SequenceAutomata,
+  timing_settings::TimingSettings,
 };
 
 pub struct Configuration {
   pub commands: Vec<Cmd>,
-  pub unreachable_sequences: Vec<String>,
   pub pre_script: String,
+  pub settings: HashMap<String, String>,
+  /// The default mode and the ones with a section.
+  pub modes: Vec<ModeHeader>,
+  /// Numbers (starting at 1) of the lines with commands.
+  pub command_lines: Vec<usize>,
+  pub diagnostics: Vec<Diagnostic>,
 }
 
-fn parse_lines(lines: &[String]) -> (Vec<Cmd>, String) {
-  let mut commands = vec![];
-  let mut other = vec![];
+static SETTING_REGEX: Mutex<OnceCell<Regex>> = Mutex::new(OnceCell::new());
 
-  for line in lines {
-    Cmd::parse(line).map_or_else(
-      |_| {
-        other.push(line.clone());
-      },
-      |cmd| {
-        commands.push(cmd);
-      },
-    );
-  }
+pub fn parse_setting(line: &str) -> Option<(String, String)> {
+  let guard = SETTING_REGEX.lock().unwrap();
+  let re =
+    guard.get_or_init(|| Regex::new(r"^\s*HORSETAB_([A-Z0-9_]+)=(\S+)\s*(?:#.*)?$").unwrap());
 
-  (commands, other.join("\n"))
+  re.captures(line)
+    .map(|c| (c[1].to_owned(), c[2].trim_matches(['"', '\'']).to_owned()))
 }
 
-fn pluck_sequence(commands: &[Cmd]) -> Vec<&str> {
-  commands
+fn parse_settings(lines: &[String]) -> HashMap<String, String> {
+  lines
     .iter()
-    .map(|c| c.sequence.as_ref())
-    .collect::<Vec<&str>>()
+    .filter_map(|line| parse_setting(line))
+    .collect()
 }
 
-fn sequence_is_reachable(sequence: &str, automata: &mut SequenceAutomata, id: usize) -> bool {
-  let mut latest_result = None;
-  for c in sequence.chars() {
-    latest_result = automata.put(AutomataInstruction::Char(c));
+/// An annotation is kept until the next line that isn't blank.
+fn next_annotation(line: &str, annotation: Option<Annotation>) -> Option<Annotation> {
+  if line.trim().is_empty() {
+    annotation
+  } else {
+    Annotation::parse(line)
   }
+}
 
-  latest_result.map_or(false, |res| res.contains(&id))
+/// A `[mode NAME]` line, which isn't part of the script.
+pub fn section(line: &str, placement: Placement) -> Option<ModeHeader> {
+  ModeHeader::parse(line).filter(|_| placement == Placement::Script)
 }
 
-fn get_unreachable_sequences(sequences: &[&str]) -> Vec<String> {
-  let mut automata = SequenceAutomata::new(sequences);
+fn parse_modes(lines: &[String], placements: &[Placement]) -> Vec<ModeHeader> {
+  let headers = lines
+    .iter()
+    .zip(placements)
+    .filter_map(|(line, placement)| section(line, *placement));
 
-  let mut ids = (0..sequences.len()).collect::<BTreeSet<usize>>();
+  merge_modes(std::iter::once(ModeHeader::new(DEFAULT_MODE)).chain(headers))
+}
 
-  for (i, seq) in sequences.iter().enumerate() {
-    automata.put(AutomataInstruction::Reset);
+/// The lines other than the `[mode NAME]` ones, each with the mode of the
+/// section it's in.
+fn lines_in_modes<'a>(
+  lines: &'a [String],
+  placements: &[Placement],
+) -> Vec<(&'a String, Placement, String)> {
+  let mut in_modes = vec![];
+  let mut mode = DEFAULT_MODE.to_owned();
 
-    if sequence_is_reachable(seq, &mut automata, i) {
-      ids.remove(&i);
+  for (line, placement) in lines.iter().zip(placements) {
+    match section(line, *placement) {
+      Some(header) => mode = header.name,
+      None => in_modes.push((line, *placement, mode.clone())),
     }
   }
 
-  ids
-    .iter()
-    .map(|i| sequences[*i])
-    .map(std::borrow::ToOwned::to_owned)
+  in_modes
+}
+
+/// A command's annotation, its text and its mode.
+type CommandText = (Option<Annotation>, String, String);
+
+/// Lines that look like commands inside heredocs or quoted strings are part
+/// of the script, and the lines that continue a command are part of it.
+fn parse_lines(lines: &[String], placements: &[Placement]) -> (Vec<Cmd>, String) {
+  let mut command_texts: Vec<CommandText> = vec![];
+  let mut annotation = None;
+  let mut other = vec![];
+
+  for (line, placement, mode) in lines_in_modes(lines, placements) {
+    match (placement, command_texts.last_mut()) {
+      (Placement::Command(_), _) => command_texts.push((annotation.take(), line.clone(), mode)),
+      (Placement::Continuation, Some((_, text, _))) => *text = format!("{text}\n{line}"),
+      _ => {
+        annotation = next_annotation(line, annotation);
+        other.push(line.clone());
+      }
+    }
+  }
+
+  (parse_commands(command_texts), other.join("\n"))
+}
+
+fn parse_commands(command_texts: Vec<CommandText>) -> Vec<Cmd> {
+  command_texts
+    .into_iter()
+    .filter_map(|(annotation, text, mode)| {
+      Some(Cmd::parse(&text).ok()?.annotated(annotation).in_mode(&mode))
+    })
+    .collect()
+}
+
+fn get_unreachable_sequences(
+  sequences: &[&str],
+  wait_for_longer: bool,
+  match_anywhere: bool,
+) -> Vec<String> {
+  let automata = SequenceAutomata::from_sequences(sequences, match_anywhere)
+    .with_wait_for_longer(wait_for_longer);
+  let reachable = automata.reachable_results();
+
+  (0..sequences.len())
+    .filter(|i| !reachable.contains(i))
+    .map(|i| sequences[i].to_owned())
     .collect::<Vec<String>>()
 }
 
+fn command_line_numbers(placements: &[Placement]) -> Vec<usize> {
+  (1..)
+    .zip(placements)
+    .filter(|(_, placement)| matches!(placement, Placement::Command(_)))
+    .map(|(number, _)| number)
+    .collect()
+}
+
+/// The indexes of the commands in each mode.
+fn mode_groups(commands: &[Cmd]) -> Vec<Vec<usize>> {
+  let mut groups: Vec<(&str, Vec<usize>)> = vec![];
+
+  for (i, cmd) in commands.iter().enumerate() {
+    match groups.iter_mut().find(|(mode, _)| *mode == cmd.mode) {
+      Some((_, ids)) => ids.push(i),
+      None => groups.push((&cmd.mode, vec![i])),
+    }
+  }
+
+  groups.into_iter().map(|(_, ids)| ids).collect()
+}
+
+/// Sequences are only compared with the ones in the same mode, since the
+/// others aren't matched at the same time.
+fn diagnose_modes(
+  commands: &[Cmd],
+  lines: &[usize],
+  timing: TimingSettings,
+  matching: &MatchSettings,
+) -> Vec<Diagnostic> {
+  mode_groups(commands)
+    .into_iter()
+    .flat_map(|ids| {
+      let mode_commands: Vec<&Cmd> = ids.iter().map(|i| &commands[*i]).collect();
+      let mode_lines: Vec<usize> = ids.iter().map(|i| lines[*i]).collect();
+      diagnose_mode(&mode_commands, &mode_lines, timing, matching)
+    })
+    .collect()
+}
+
+/// A command can still run after others with the same sequence when all of
+/// them depend on the window, since the first one whose condition matches
+/// is picked.
+fn is_alternative(diagnostic: &Diagnostic, commands: &[&Cmd], lines: &[usize]) -> bool {
+  let is_duplicate = matches!(diagnostic.problem, Problem::Duplicate(_));
+  let position = lines.iter().position(|l| *l == diagnostic.location.line);
+
+  position.filter(|_| is_duplicate).is_some_and(|i| {
+    (commands[..i].iter())
+      .filter(|c| c.sequence == commands[i].sequence)
+      .all(|c| c.options.has_window_condition())
+  })
+}
+
+fn diagnose_mode(
+  commands: &[&Cmd],
+  lines: &[usize],
+  timing: TimingSettings,
+  matching: &MatchSettings,
+) -> Vec<Diagnostic> {
+  let sequences: Vec<&str> = commands.iter().map(|c| c.sequence.as_str()).collect();
+  let unreachable =
+    get_unreachable_sequences(&sequences, timing.wait_for_longer, matching.match_anywhere);
+
+  (diagnose(&sequences, lines, &unreachable).into_iter())
+    .filter(|d| !is_alternative(d, commands, lines))
+    .collect()
+}
+
+/// Commands that switch to a mode without a section.
+fn diagnose_switches(commands: &[Cmd], lines: &[usize], modes: &[ModeHeader]) -> Vec<Diagnostic> {
+  (commands.iter().zip(lines))
+    .filter_map(|(cmd, line)| {
+      let mode = cmd.options.switch.as_ref()?;
+      (!modes.iter().any(|m| m.name == *mode)).then(|| Diagnostic {
+        location: Location::new(*line),
+        sequence: cmd.sequence.clone(),
+        problem: Problem::UnknownMode(mode.clone()),
+      })
+    })
+    .collect()
+}
+
+/// Problems with the commands and with the lines themselves, in line order.
+fn find_diagnostics(
+  lines: &[String],
+  placements: &[Placement],
+  commands: &[Cmd],
+  settings: &HashMap<String, String>,
+  default_timing: TimingSettings,
+) -> Vec<Diagnostic> {
+  let timing = default_timing.with_overrides(settings);
+  let matching = MatchSettings::from_settings(settings);
+  let command_lines = command_line_numbers(placements);
+
+  let mut diagnostics = diagnose_modes(commands, &command_lines, timing, &matching);
+  let modes = parse_modes(lines, placements);
+  diagnostics.extend(diagnose_switches(commands, &command_lines, &modes));
+  diagnostics.extend(diagnose_lines(lines, placements));
+  diagnostics.extend(diagnose_unclosed(lines));
+  diagnostics.extend(diagnose_settings(lines));
+  diagnostics.sort_by_key(|d| d.location.line);
+  diagnostics
+}
+
+/// Diagnostics are found on all the lines together, so the lines they are on
+/// are translated to the files they come from.
+fn locate(source: &ConfigSource, diagnostic: Diagnostic) -> (usize, Diagnostic) {
+  let line = diagnostic.location.line;
+  let problem = match diagnostic.problem {
+    Problem::Duplicate(other) => Problem::Duplicate(source.location(other.line)),
+    problem => problem,
+  };
+
+  let located = Diagnostic {
+    location: source.location(line),
+    problem,
+    ..diagnostic
+  };
+  (line, located)
+}
+
+/// Skipped includes go in between the lines around them.
+fn merge_skipped(source: &ConfigSource, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+  let mut all: Vec<(usize, Diagnostic)> = diagnostics
+    .into_iter()
+    .map(|d| locate(source, d))
+    .chain(source.skipped.iter().cloned())
+    .collect();
+  all.sort_by_key(|(line, _)| *line);
+  all.into_iter().map(|(_, d)| d).collect()
+}
+
 impl Configuration {
   pub fn from_lines(lines: &[String]) -> Self {
-    let (commands, pre_script) = parse_lines(lines);
+    Self::from_lines_with(lines, TimingSettings::default())
+  }
 
-    let sequences: Vec<&str> = pluck_sequence(&commands);
-    let unreachable_sequences = get_unreachable_sequences(&sequences);
+  /// The default timing (e.g. from the command line) is needed to tell which
+  /// sequences are unreachable.
+  pub fn from_lines_with(lines: &[String], default_timing: TimingSettings) -> Self {
+    Self::from_source(&ConfigSource::from_lines(lines), default_timing)
+  }
+
+  /// Line numbers (e.g. of the commands) count all the lines together.
+  pub fn from_source(source: &ConfigSource, default_timing: TimingSettings) -> Self {
+    let lines = &source.lines;
+    let placements = placements(lines);
+    let (commands, pre_script) = parse_lines(lines, &placements);
+    let settings = parse_settings(lines);
+    let found = find_diagnostics(lines, &placements, &commands, &settings, default_timing);
 
     Self {
       commands,
-      unreachable_sequences,
       pre_script,
+      settings,
+      modes: parse_modes(lines, &placements),
+      command_lines: command_line_numbers(&placements),
+      diagnostics: merge_skipped(source, found),
     }
   }
-
-  pub fn get_sequences(&self) -> Vec<&str> {
-    self
-      .commands
-      .iter()
-      .map(|c| c.sequence.as_ref())
-      .collect::<Vec<&str>>()
-  }
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::constants::DEFAULT_COMMAND_CONFIG_FILE_CONTENT;
   use test_case::test_case;
 
   #[test]
   fn test_get_unreachable_sequences_all_ok() {
-    assert!(get_unreachable_sequences(&["abc", "bca"]).is_empty());
-    assert!(get_unreachable_sequences(&["abc", "abb"]).is_empty());
-    assert!(get_unreachable_sequences(&["abc", "aaa"]).is_empty());
-    assert!(get_unreachable_sequences(&["abc", "abd"]).is_empty());
+    assert!(get_unreachable_sequences(&["abc", "bca"], false, false).is_empty());
+    assert!(get_unreachable_sequences(&["abc", "abb"], false, false).is_empty());
+    assert!(get_unreachable_sequences(&["abc", "aaa"], false, false).is_empty());
+    assert!(get_unreachable_sequences(&["abc", "abd"], false, false).is_empty());
   }
 
   #[test]
   fn test_get_unreachable_sequences_some_fail() {
-    assert_eq!(get_unreachable_sequences(&["abc", "abcc"]), vec!["abcc"]);
     assert_eq!(
-      get_unreachable_sequences(&["abc", "abcc", "abccc"]),
+      get_unreachable_sequences(&["abc", "abcc"], false, false),
+      vec!["abcc"]
+    );
+    assert_eq!(
+      get_unreachable_sequences(&["abc", "abcc", "abccc"], false, false),
       vec!["abcc", "abccc"]
     );
     assert_eq!(
-      get_unreachable_sequences(&["abccc", "abcc", "abcx"]),
+      get_unreachable_sequences(&["abccc", "abcc", "abcx"], false, false),
       vec!["abccc"]
     );
   }
 
+  #[test]
+  fn test_get_unreachable_sequences_wait_for_longer() {
+    assert!(get_unreachable_sequences(&["abc", "abcc", "abccc"], true, false).is_empty());
+    assert!(get_unreachable_sequences(&["abcc", "abc", "a"], true, false).is_empty());
+  }
+
+  #[test]
+  fn test_get_unreachable_sequences_patterns() {
+    assert!(get_unreachable_sequences(&[".?", "--"], false, false).is_empty());
+    assert_eq!(
+      get_unreachable_sequences(&[".?", ".-."], false, false),
+      vec![".-."]
+    );
+    assert!(get_unreachable_sequences(&["(..|--)", "-{2}"], false, false).is_empty());
+    assert_eq!(
+      get_unreachable_sequences(&["(..|--)", "--."], false, false),
+      vec!["--."]
+    );
+    assert_eq!(
+      get_unreachable_sequences(&["-{2,3}", "---"], false, false),
+      vec!["---"]
+    );
+    assert!(get_unreachable_sequences(&["-{2,3}", "---"], true, false).is_empty());
+  }
+
+  #[test]
+  fn test_get_unreachable_sequences_match_anywhere() {
+    assert!(get_unreachable_sequences(&["bc", "abcd"], false, false).is_empty());
+    assert_eq!(
+      get_unreachable_sequences(&["bc", "abcd"], false, true),
+      vec!["abcd"]
+    );
+  }
+
   #[test]
   fn test_get_unreachable_sequences_same() {
-    assert!(get_unreachable_sequences(&["abc", "abc"]).is_empty());
-    assert!(get_unreachable_sequences(&["a", "a"]).is_empty());
+    assert!(get_unreachable_sequences(&["abc", "abc"], false, false).is_empty());
+    assert!(get_unreachable_sequences(&["a", "a"], false, false).is_empty());
+  }
+
+  #[test_case("HORSETAB_LONG_PRESS_MS=300", Some(("LONG_PRESS_MS", "300")))]
+  #[test_case("  HORSETAB_SEQUENCE_RESET_MS=\"800\" ", Some(("SEQUENCE_RESET_MS", "800")))]
+  #[test_case("HORSETAB_LONG_PRESS_MS = 300", None; "spaces around equals")]
+  #[test_case("# HORSETAB_LONG_PRESS_MS=300", None; "commented out")]
+  #[test_case("HORSETAB_LONG_PRESS_MS=300  # comment", Some(("LONG_PRESS_MS", "300")))]
+  #[test_case("MY_VAR=300", None)]
+  fn test_parse_setting(line: &str, expected: Option<(&str, &str)>) {
+    let expected_owned = expected.map(|(k, v)| (k.to_owned(), v.to_owned()));
+    assert_eq!(parse_setting(line), expected_owned);
+  }
+
+  fn parse(lines: &[String]) -> (Vec<Cmd>, String) {
+    parse_lines(lines, &placements(lines))
   }
 
   fn string_vec<const N: usize>(strs: [&str; N]) -> Vec<String> {
@@ -126,21 +390,21 @@ mod tests {
 
   #[test]
   fn test_parse_lines_multiple_empty_lines() {
-    let (cmds, other) = parse_lines(&string_vec([" ", "   ", " "]));
+    let (cmds, other) = parse(&string_vec([" ", "   ", " "]));
     assert!(cmds.is_empty());
     assert_eq!(other, " \n   \n ");
   }
 
   #[test]
   fn test_parse_lines_with_comments() {
-    let (cmds, other) = parse_lines(&string_vec([" #", "  # .-.- aa ", "#", " #.-."]));
+    let (cmds, other) = parse(&string_vec([" #", "  # .-.- aa ", "#", " #.-."]));
     assert!(cmds.is_empty());
     assert_eq!(other, " #\n  # .-.- aa \n#\n #.-.");
   }
 
   #[test]
   fn test_parse_lines_with_comments_2() {
-    let (cmds, other) = parse_lines(&string_vec([".-.-  #"]));
+    let (cmds, other) = parse(&string_vec([".-.-  #"]));
 
     assert!(other.is_empty());
     assert_eq!(cmds.len(), 1);
@@ -155,7 +419,7 @@ mod tests {
   #[test_case(" . source_something")]
   #[test_case("  something ")]
   fn test_parse_lines_with_non_morse_command(line: &str) {
-    let (cmds, other) = parse_lines(&string_vec([line]));
+    let (cmds, other) = parse(&string_vec([line]));
     assert!(cmds.is_empty());
     assert!(!other.is_empty());
   }
@@ -169,7 +433,7 @@ mod tests {
     expected_cmd: &[&str],
     expected_other: &str,
   ) {
-    let (cmds, other) = parse_lines(&string_vec(lines));
+    let (cmds, other) = parse(&string_vec(lines));
 
     let result_seq = cmds
       .iter()
@@ -185,4 +449,214 @@ mod tests {
     assert_eq!(result_cmd, expected_cmd);
     assert_eq!(other, expected_other);
   }
+
+  #[test]
+  fn test_from_lines_diagnostics() {
+    let lines = string_vec(["A=1", "..-- echo a", "", "-..- echo b", "..-- echo c"]);
+    let config = Configuration::from_lines(&lines);
+    let lines_and_sequences: Vec<(usize, &str)> = config
+      .diagnostics
+      .iter()
+      .map(|d| (d.location.line, d.sequence.as_ref()))
+      .collect();
+    assert_eq!(lines_and_sequences, vec![(5, "..--")]);
+  }
+
+  #[test]
+  fn test_from_lines_near_misses() {
+    let lines = string_vec([
+      ". cmd",
+      "..-- echo a",
+      "..-x echo b",
+      "-..- echo c",
+      "..-- echo d",
+    ]);
+    let config = Configuration::from_lines(&lines);
+    let lines_with_problems: Vec<usize> =
+      config.diagnostics.iter().map(|d| d.location.line).collect();
+    assert_eq!(lines_with_problems, vec![1, 3, 5]);
+    assert_eq!(config.pre_script, ". cmd\n..-x echo b");
+  }
+
+  #[test]
+  fn test_parse_lines_heredoc() {
+    let (cmds, other) = parse(&string_vec([
+      "cat <<EOF",
+      "..-- not a command",
+      "EOF",
+      ".-.- cmd",
+    ]));
+    assert_eq!(cmds.len(), 1);
+    assert_eq!(other, "cat <<EOF\n..-- not a command\nEOF");
+  }
+
+  #[test]
+  fn test_parse_lines_multiple_lines() {
+    let lines = string_vec([
+      ".-.- echo a \\",
+      "  && echo b",
+      "echo c",
+      "..-- #n \\",
+      "  echo $1",
+    ]);
+    let (cmds, other) = parse(&lines);
+    let texts: Vec<String> = cmds.iter().map(ToString::to_string).collect();
+    assert_eq!(
+      texts,
+      [".-.- echo a \\\n  && echo b", "..-- #n \\\n  echo $1"]
+    );
+    assert_eq!(other, "echo c");
+  }
+
+  #[test]
+  fn test_parse_lines_annotations() {
+    let lines = string_vec([
+      "# @name a: Say a",
+      "",
+      ".-.- echo a",
+      "..-- echo b",
+      "# @name c",
+      "echo",
+      "-..- echo c",
+    ]);
+    let (cmds, _) = parse(&lines);
+    let names: Vec<Option<&str>> = cmds.iter().map(Cmd::name).collect();
+    assert_eq!(names, [Some("a"), None, None]);
+    assert_eq!(cmds[0].to_string(), "# @name a: Say a\n.-.- echo a");
+  }
+
+  #[test]
+  fn test_from_lines_inside_block() {
+    let lines = string_vec(["if true; then", "  ..-- echo a", "fi"]);
+    let config = Configuration::from_lines(&lines);
+    assert_eq!(config.commands.len(), 1);
+    assert!(matches!(
+      config.diagnostics.as_slice(),
+      [Diagnostic {
+        location: Location { line: 2, .. },
+        problem: Problem::InsideBlock(_),
+        ..
+      }]
+    ));
+  }
+
+  #[test]
+  fn test_from_lines_unclosed_quote() {
+    let lines = string_vec([".-.- echo a", "echo \"it's", "..-- echo b"]);
+    let config = Configuration::from_lines(&lines);
+    assert_eq!(config.commands.len(), 1);
+    let messages: Vec<String> = config.diagnostics.iter().map(ToString::to_string).collect();
+    assert_eq!(
+      messages,
+      ["line 2: \" isn't closed, so the rest of the file is text (its commands aren't defined)"]
+    );
+  }
+
+  #[test]
+  fn test_from_lines_diagnostics_default_timing() {
+    let lines = string_vec([".-.- echo a", ".-.-- echo b"]);
+    let timing = TimingSettings {
+      wait_for_longer: true,
+      ..TimingSettings::default()
+    };
+    assert_eq!(Configuration::from_lines(&lines).diagnostics.len(), 1);
+    // Still too close, but reachable.
+    let diagnostics = Configuration::from_lines_with(&lines, timing).diagnostics;
+    assert!(matches!(
+      diagnostics.as_slice(),
+      [Diagnostic {
+        problem: Problem::TooClose(_),
+        ..
+      }]
+    ));
+  }
+
+  fn source(lines: &[&str], locations: Vec<Location>) -> ConfigSource {
+    ConfigSource {
+      lines: lines.iter().map(|l| (*l).to_owned()).collect(),
+      locations,
+      skipped: vec![],
+    }
+  }
+
+  fn diagnostic_texts(source: &ConfigSource) -> Vec<String> {
+    let config = Configuration::from_source(source, TimingSettings::default());
+    config.diagnostics.iter().map(ToString::to_string).collect()
+  }
+
+  #[test]
+  fn test_from_source_locations() {
+    let included = Location {
+      file: Some("a.conf".to_owned()),
+      line: 3,
+    };
+    let source = source(&["..-- a", "..-- b"], vec![Location::new(1), included]);
+    assert_eq!(
+      diagnostic_texts(&source),
+      ["line 3 of a.conf: ..-- is also on line 1"]
+    );
+  }
+
+  #[test]
+  fn test_from_source_skipped() {
+    let locations = vec![Location::new(1), Location::new(3)];
+    let mut source = source(&["..-- a", "..-- b"], locations);
+    let cycle = Diagnostic {
+      location: Location::new(2),
+      sequence: "b.conf".to_owned(),
+      problem: Problem::IncludeCycle,
+    };
+    source.skipped.push((1, cycle));
+    let texts = diagnostic_texts(&source);
+    assert!(texts[0].starts_with("line 2: b.conf is already being included"));
+    assert_eq!(texts[1], "line 3: ..-- is also on line 1");
+  }
+
+  #[test]
+  fn test_from_lines_modes() {
+    let lines = string_vec([
+      "[mode browser timeout=10s]",
+      "..-- [switch=default] echo a",
+      "[mode default]",
+      "..-- [switch=browser] echo b",
+    ]);
+    let config = Configuration::from_lines(&lines);
+    let modes: Vec<&str> = config.commands.iter().map(|c| c.mode.as_str()).collect();
+    assert_eq!(modes, ["browser", "default"]);
+    assert_eq!(config.modes.len(), 2);
+    assert!(config.pre_script.is_empty());
+    // The same sequence in another mode isn't a duplicate.
+    assert_eq!(config.diagnostics, vec![]);
+  }
+
+  #[test]
+  fn test_from_lines_window_alternatives() {
+    let lines = string_vec([
+      "..-- [class=firefox] echo a",
+      "..-- [title=vim] echo b",
+      "..-- echo c",
+      "..-- [class=xterm] echo d",
+    ]);
+    let texts = diagnostic_texts(&ConfigSource::from_lines(&lines));
+    assert_eq!(texts, ["line 4: ..-- is also on line 1"]);
+  }
+
+  #[test]
+  fn test_from_lines_unknown_mode() {
+    let lines = string_vec(["..-- [switch=browser] echo a"]);
+    let texts = diagnostic_texts(&ConfigSource::from_lines(&lines));
+    assert_eq!(
+      texts,
+      ["line 1: ..-- switches to browser, but there's no [mode browser]"]
+    );
+  }
+
+  #[test]
+  fn test_default_config_has_no_diagnostics() {
+    let lines: Vec<String> = DEFAULT_COMMAND_CONFIG_FILE_CONTENT
+      .lines()
+      .map(str::to_owned)
+      .collect();
+    assert_eq!(Configuration::from_lines(&lines).diagnostics, vec![]);
+  }
 }
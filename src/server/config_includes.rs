@@ -0,0 +1,429 @@
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+use super::{
+  config_diagnostics::{Diagnostic, Location, Problem},
+  config_toml::{is_toml, to_lines},
+  shell_blocks::{placements, Placement},
+};
+use crate::{
+  constants::{get_default_conf_d_path, get_default_config_path},
+  mode::{ModeHeader, DEFAULT_MODE},
+  util::{expand_home, read_lines_or_create},
+};
+
+/// Files in the `conf.d` directory are loaded after the main file.
+const CONF_D_PATTERN: &str = "*.conf";
+
+/// The lines of a file and of the files it includes (in place of the
+/// `include` lines), with the location each one comes from.
+#[derive(Default)]
+pub struct ConfigSource {
+  pub lines: Vec<String>,
+  pub locations: Vec<Location>,
+  /// Includes that were skipped, after the number of lines before them.
+  pub skipped: Vec<(usize, Diagnostic)>,
+}
+
+impl ConfigSource {
+  pub fn from_lines(lines: &[String]) -> Self {
+    Self {
+      lines: lines.to_vec(),
+      locations: (1..=lines.len()).map(Location::new).collect(),
+      skipped: vec![],
+    }
+  }
+
+  /// Where a line is, by its number among all the lines.
+  pub fn location(&self, line: usize) -> Location {
+    self.locations[line - 1].clone()
+  }
+}
+
+/// `include path`, where the path can be a pattern (e.g. `dir/*.conf`).
+pub fn directive(line: &str) -> Option<&str> {
+  let (word, path) = line.trim().split_once(char::is_whitespace)?;
+  (word == "include").then(|| path.trim_start())
+}
+
+fn is_pattern(path: &str) -> bool {
+  path.contains(['*', '?', '['])
+}
+
+/// The files an include refers to, relative to the directory of the file it's
+/// in. Patterns match files in alphabetical order.
+fn expand(dir: &Path, include: &str) -> Result<Vec<PathBuf>, String> {
+  let path = expand_home(include);
+
+  if !is_pattern(include) {
+    return Ok(vec![dir.join(path)]);
+  }
+
+  let escaped_dir = PathBuf::from(glob::Pattern::escape(&dir.to_string_lossy()));
+  let paths = glob::glob(&escaped_dir.join(path).to_string_lossy()).map_err(|e| e.to_string())?;
+
+  Ok(paths.filter_map(Result::ok).collect())
+}
+
+fn canonical(path: &Path) -> PathBuf {
+  fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn parent(path: &Path) -> PathBuf {
+  path.parent().map(Path::to_path_buf).unwrap_or_default()
+}
+
+type NumberedLines = Vec<(usize, String)>;
+
+/// The lines in the line format (`.toml` files are translated), with their
+/// numbers in the file.
+fn file_lines(path: &Path, content: &str) -> Result<NumberedLines, String> {
+  if is_toml(path) {
+    to_lines(content)
+  } else {
+    Ok((1..).zip(content.lines().map(str::to_owned)).collect())
+  }
+}
+
+fn read_file(path: &Path) -> Result<NumberedLines, String> {
+  let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+  file_lines(path, &content)
+}
+
+struct Resolver {
+  main: PathBuf,
+  /// Files being included (each one by the one before it).
+  stack: Vec<PathBuf>,
+  /// The mode of the section the lines are in.
+  mode: String,
+  source: ConfigSource,
+}
+
+impl Resolver {
+  /// Relative to the main file's directory, when it's in it.
+  fn display(&self, path: &Path) -> String {
+    let main_dir = canonical(&parent(&self.main));
+    let full = canonical(path);
+    full
+      .strip_prefix(main_dir)
+      .unwrap_or(&full)
+      .display()
+      .to_string()
+  }
+
+  fn skip(&mut self, location: &Location, path: String, problem: Problem) {
+    let diagnostic = Diagnostic {
+      location: location.clone(),
+      sequence: path,
+      problem,
+    };
+    self
+      .source
+      .skipped
+      .push((self.source.lines.len(), diagnostic));
+  }
+
+  fn add_file(&mut self, path: &Path, numbered: &[(usize, String)]) {
+    let file = (canonical(path) != canonical(&self.main)).then(|| self.display(path));
+    let lines: Vec<String> = numbered.iter().map(|(_, line)| line.clone()).collect();
+    self.stack.push(canonical(path));
+
+    for ((number, line), placement) in numbered.iter().zip(placements(&lines)) {
+      let location = Location {
+        file: file.clone(),
+        line: *number,
+      };
+      self.add_line(&parent(path), line, placement, location);
+    }
+
+    self.stack.pop();
+  }
+
+  fn add_line(&mut self, dir: &Path, line: &str, placement: Placement, location: Location) {
+    if let Some(include) = directive(line).filter(|_| placement == Placement::Script) {
+      return self.include(dir, include, &location);
+    }
+
+    if let Some(header) = ModeHeader::parse(line).filter(|_| placement == Placement::Script) {
+      self.mode = header.name;
+    }
+
+    self.source.lines.push(line.to_owned());
+    self.source.locations.push(location);
+  }
+
+  /// Sections end with the file they are in, so the lines after an include
+  /// are in the same mode as before it.
+  fn restore_mode(&mut self, mode: &str, location: &Location) {
+    if self.mode != mode {
+      self.add_line(
+        Path::new(""),
+        &ModeHeader::new(mode).to_string(),
+        Placement::Script,
+        location.clone(),
+      );
+    }
+  }
+
+  fn include(&mut self, dir: &Path, include: &str, location: &Location) {
+    match expand(dir, include) {
+      Ok(paths) => {
+        for path in paths {
+          self.include_file(&path, location);
+        }
+      }
+      Err(err) => self.skip(location, include.to_owned(), Problem::Unreadable(err)),
+    }
+  }
+
+  fn include_file(&mut self, path: &Path, location: &Location) {
+    if self.stack.contains(&canonical(path)) {
+      return self.skip(location, self.display(path), Problem::IncludeCycle);
+    }
+
+    match read_file(path) {
+      Ok(lines) => {
+        let mode = self.mode.clone();
+        self.add_file(path, &lines);
+        self.restore_mode(&mode, location);
+      }
+      Err(err) => self.skip(location, self.display(path), Problem::Unreadable(err)),
+    }
+  }
+
+  /// As if the main file included them at the end (outside of any section),
+  /// but problems are shown on the files themselves.
+  fn include_conf_d(&mut self, dir: &Path) {
+    self.stack.push(canonical(&self.main));
+
+    for path in expand(dir, CONF_D_PATTERN).unwrap_or_default() {
+      let location = Location {
+        file: Some(self.display(&path)),
+        line: 1,
+      };
+      self.restore_mode(DEFAULT_MODE, &location);
+      self.include_file(&path, &location);
+    }
+
+    self.stack.pop();
+  }
+}
+
+/// Replaces the `include` lines of the main file (and of the files it
+/// includes) with the lines of the files they refer to, and adds the files in
+/// the `conf.d` directory (if any) at the end. Included files that can't be
+/// read, or would include themselves, are skipped, but the main file has to
+/// be valid.
+pub fn resolve(main: &Path, content: &str, conf_d: Option<&Path>) -> Result<ConfigSource, String> {
+  let mut resolver = Resolver {
+    main: main.to_path_buf(),
+    stack: vec![],
+    mode: DEFAULT_MODE.to_owned(),
+    source: ConfigSource::default(),
+  };
+
+  resolver.add_file(main, &file_lines(main, content)?);
+
+  if let Some(dir) = conf_d {
+    resolver.include_conf_d(dir);
+  }
+
+  Ok(resolver.source)
+}
+
+fn is_default_config(config_path: &str) -> bool {
+  let default = get_default_config_path();
+  let canonical = |path: &str| fs::canonicalize(path).ok();
+
+  config_path == default || canonical(config_path).is_some_and(|p| Some(p) == canonical(&default))
+}
+
+/// `~/.config/horsetab/conf.d` goes with the default configuration file, so
+/// another file (e.g. one being tried out) is loaded without it.
+pub fn conf_d_for(config_path: &str) -> Option<PathBuf> {
+  let conf_d = get_default_conf_d_path();
+
+  (is_default_config(config_path) && !conf_d.is_empty()).then(|| PathBuf::from(conf_d))
+}
+
+/// The main file (created if it doesn't exist), the files it includes, and
+/// the ones in `~/.config/horsetab/conf.d` (see `conf_d_for`).
+pub fn read_config(config_path: &str) -> std::io::Result<ConfigSource> {
+  let content = read_lines_or_create(config_path)?.join("\n");
+
+  resolve(
+    Path::new(config_path),
+    &content,
+    conf_d_for(config_path).as_deref(),
+  )
+  .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::TempDir;
+  use test_case::test_case;
+
+  fn write_files(files: &[(&str, &str)]) -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+
+    for (name, content) in files {
+      let path = dir.path().join(name);
+      fs::create_dir_all(parent(&path)).unwrap();
+      fs::write(path, content).unwrap();
+    }
+
+    dir
+  }
+
+  fn resolve_main(dir: &TempDir, conf_d: Option<&str>) -> ConfigSource {
+    let main = dir.path().join("main.conf");
+    let content = fs::read_to_string(&main).unwrap();
+    resolve(
+      &main,
+      &content,
+      conf_d.map(|d| dir.path().join(d)).as_deref(),
+    )
+    .unwrap()
+  }
+
+  fn locations(source: &ConfigSource) -> Vec<String> {
+    source.locations.iter().map(ToString::to_string).collect()
+  }
+
+  #[test_case("include a.conf" => Some("a.conf"); "file")]
+  #[test_case("  include   dir/*.conf" => Some("dir/*.conf"); "pattern")]
+  #[test_case("include" => None; "no path")]
+  #[test_case("includes a.conf" => None; "other word")]
+  fn test_directive(line: &str) -> Option<&str> {
+    directive(line)
+  }
+
+  #[test]
+  fn test_resolve() {
+    let dir = write_files(&[
+      ("main.conf", "A=1\ninclude sub/*.conf\n..-- echo main"),
+      ("sub/b.conf", "-..- echo b"),
+      ("sub/a.conf", "include ../c.conf\n.-.- echo a"),
+      ("c.conf", "---- echo c"),
+    ]);
+    let source = resolve_main(&dir, None);
+
+    assert_eq!(
+      source.lines,
+      [
+        "A=1",
+        "---- echo c",
+        ".-.- echo a",
+        "-..- echo b",
+        "..-- echo main"
+      ]
+    );
+    assert_eq!(
+      locations(&source),
+      [
+        "line 1",
+        "line 1 of c.conf",
+        "line 2 of sub/a.conf",
+        "line 1 of sub/b.conf",
+        "line 3"
+      ]
+    );
+    assert!(source.skipped.is_empty());
+  }
+
+  #[test]
+  fn test_resolve_cycle() {
+    let dir = write_files(&[
+      ("main.conf", "include a.conf\necho main"),
+      ("a.conf", "echo a\ninclude main.conf"),
+    ]);
+    let source = resolve_main(&dir, None);
+
+    assert_eq!(source.lines, ["echo a", "echo main"]);
+    assert_eq!(
+      source.skipped[0].1.to_string(),
+      "line 2 of a.conf: main.conf is already being included, so it's skipped"
+    );
+  }
+
+  #[test]
+  fn test_resolve_unreadable() {
+    let dir = write_files(&[("main.conf", "include missing.conf\ninclude none/*.conf")]);
+    let source = resolve_main(&dir, None);
+
+    assert!(source.lines.is_empty());
+    assert_eq!(source.skipped.len(), 1);
+    assert!(matches!(
+      &source.skipped[0].1,
+      Diagnostic {
+        location: Location { line: 1, .. },
+        problem: Problem::Unreadable(_),
+        ..
+      }
+    ));
+  }
+
+  #[test]
+  fn test_resolve_not_a_directive() {
+    let dir = write_files(&[("main.conf", "cat <<EOF\ninclude a.conf\nEOF")]);
+    assert_eq!(resolve_main(&dir, None).lines.len(), 3);
+  }
+
+  #[test]
+  fn test_resolve_modes() {
+    let dir = write_files(&[
+      ("main.conf", "[mode a]\ninclude b.conf\n..-- echo a"),
+      ("b.conf", "-..- echo b\n[mode b]\n.-.- echo b"),
+    ]);
+    let source = resolve_main(&dir, None);
+
+    let expected = "[mode a]\n-..- echo b\n[mode b]\n.-.- echo b\n[mode a]\n..-- echo a";
+    assert_eq!(source.lines.join("\n"), expected);
+    assert_eq!(locations(&source)[4], "line 2");
+  }
+
+  #[test]
+  fn test_resolve_toml() {
+    let dir = write_files(&[
+      ("main.conf", "include b.toml"),
+      (
+        "b.toml",
+        "script = \"B=1\"\n\n[[commands]]\nsequence = \"..--\"\ncommand = \"echo b\"",
+      ),
+    ]);
+    let source = resolve_main(&dir, None);
+
+    assert_eq!(source.lines, ["B=1", "..-- echo b"]);
+    assert_eq!(locations(&source), ["line 1 of b.toml", "line 3 of b.toml"]);
+  }
+
+  #[test]
+  fn test_conf_d_only_with_the_default_config() {
+    assert_eq!(
+      conf_d_for(&get_default_config_path()).map(|p| p.to_string_lossy().into_owned()),
+      Some(get_default_conf_d_path())
+    );
+    assert_eq!(conf_d_for("/tmp/other.conf"), None);
+  }
+
+  #[test]
+  fn test_resolve_conf_d() {
+    let dir = write_files(&[
+      ("main.conf", "..-- echo main"),
+      ("conf.d/b.conf", "-..- echo b"),
+      ("conf.d/a.conf", ".-.- echo a"),
+      ("conf.d/notes.txt", "not loaded"),
+    ]);
+    let source = resolve_main(&dir, Some("conf.d"));
+
+    assert_eq!(
+      source.lines,
+      ["..-- echo main", ".-.- echo a", "-..- echo b"]
+    );
+    assert_eq!(locations(&source)[1], "line 1 of conf.d/a.conf");
+  }
+}
@@ -0,0 +1,769 @@
+use std::{
+  collections::{BTreeMap, HashMap},
+  path::Path,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use serde::{Deserialize, Serialize};
+use toml::{Spanned, Value};
+
+use super::{
+  config_file_parser::{parse_setting, section, Configuration},
+  config_includes::directive,
+  shell_blocks::{placements, Placement},
+};
+use crate::{
+  cmd::{encode_alias, parse_commands, Annotation, Cmd, Parameter},
+  command_options::{format_timeout, is_window_pattern, parse_timeout, CommandOptions},
+  mode::{ModeHeader, DEFAULT_MODE},
+};
+
+/// A command in the structured format, with the same parts as a command line.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct TomlCommand {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  name: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  description: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  sequence: Option<String>,
+  /// Letters and digits, instead of a sequence.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  alias: Option<String>,
+  /// `number` or `symbols`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  parameter: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  cwd: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  timeout: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  interpreter: Option<String>,
+  /// Switches to this mode when the sequence matches.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  switch: Option<String>,
+  /// Only runs in windows whose class matches this pattern.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  class: Option<String>,
+  /// Only runs in windows whose title matches this pattern.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  title: Option<String>,
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  env: BTreeMap<String, String>,
+  /// The mode it's matched in (the default one if there's none).
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  mode: Option<String>,
+  command: String,
+}
+
+/// A mode, which only needs to be declared for a timeout, or when no command
+/// is in it.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct TomlMode {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  timeout: Option<String>,
+  /// Files whose commands are in this mode.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  include: Vec<Spanned<String>>,
+}
+
+/// The structured format (a `.toml` file).
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct TomlConfig {
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  include: Vec<Spanned<String>>,
+  /// Everything that isn't a command (variables, functions, etc).
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  script: Option<Spanned<String>>,
+  /// The `HORSETAB_` settings, without the prefix (in any case).
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  settings: BTreeMap<String, Spanned<Value>>,
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  modes: BTreeMap<String, Spanned<TomlMode>>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  commands: Vec<Spanned<TomlCommand>>,
+}
+
+/// Whether a file is in the structured format (by its extension).
+pub fn is_toml(path: &Path) -> bool {
+  path
+    .extension()
+    .is_some_and(|extension| extension == "toml")
+}
+
+const fn parameter_name(parameter: Parameter) -> &'static str {
+  match parameter {
+    Parameter::Number => "number",
+    Parameter::Symbols => "symbols",
+  }
+}
+
+fn parse_parameter(name: &str) -> Result<Parameter, String> {
+  match name {
+    "number" => Ok(Parameter::Number),
+    "symbols" => Ok(Parameter::Symbols),
+    _ => Err(format!(
+      "unknown parameter `{name}` (use number or symbols)"
+    )),
+  }
+}
+
+fn line_of(content: &str, offset: usize) -> usize {
+  content[..offset].matches('\n').count() + 1
+}
+
+/// Lines are written as they are parsed, so anything that wouldn't be parsed
+/// back the same way (e.g. a line break without `\`) is an error.
+fn check_written(written: String, parsed: Option<&str>) -> Result<String, String> {
+  if parsed == Some(written.as_str()) {
+    Ok(written)
+  } else {
+    Err(format!("can't be written as a line: {written}"))
+  }
+}
+
+fn check_pattern(pattern: Option<&String>) -> Result<Option<String>, String> {
+  match pattern {
+    Some(p) if !is_window_pattern(p) => Err(format!("invalid pattern `{p}`")),
+    _ => Ok(pattern.cloned()),
+  }
+}
+
+impl TomlCommand {
+  #[allow(clippy::too_many_lines)]
+  fn from_cmd(cmd: &Cmd) -> Self {
+    let options = &cmd.options;
+
+    Self {
+      name: cmd.annotation.as_ref().map(|a| a.name.clone()),
+      description: cmd.annotation.as_ref().and_then(|a| a.description.clone()),
+      sequence: cmd.alias.is_none().then(|| cmd.sequence.clone()),
+      alias: cmd.alias.clone(),
+      parameter: cmd.parameter.map(|p| parameter_name(p).to_owned()),
+      cwd: options.cwd.clone(),
+      timeout: options.timeout.map(format_timeout),
+      interpreter: options.interpreter.clone(),
+      switch: options.switch.clone(),
+      class: options.class.clone(),
+      title: options.title.clone(),
+      env: options.env.iter().cloned().collect(),
+      mode: (cmd.mode != DEFAULT_MODE).then(|| cmd.mode.clone()),
+      command: cmd.command.clone(),
+    }
+  }
+
+  fn annotation(&self) -> Result<Option<Annotation>, String> {
+    match (&self.name, &self.description) {
+      (None, Some(_)) => Err("a description needs a name".to_owned()),
+      (name, description) => Ok(name.as_ref().map(|n| Annotation {
+        name: n.clone(),
+        description: description.clone(),
+      })),
+    }
+  }
+
+  fn sequence(&self) -> Result<String, String> {
+    match (&self.sequence, &self.alias) {
+      (Some(sequence), None) => Ok(sequence.clone()),
+      (None, Some(alias)) => encode_alias(alias).ok_or_else(|| format!("invalid alias `{alias}`")),
+      _ => Err("needs either a sequence or an alias".to_owned()),
+    }
+  }
+
+  fn options(&self) -> Result<CommandOptions, String> {
+    let timeout = self
+      .timeout
+      .as_deref()
+      .map(|t| parse_timeout(t).ok_or_else(|| format!("invalid timeout `{t}`")))
+      .transpose()?;
+
+    Ok(CommandOptions {
+      cwd: self.cwd.clone(),
+      timeout,
+      interpreter: self.interpreter.clone(),
+      switch: self.switch.clone(),
+      class: check_pattern(self.class.as_ref())?,
+      title: check_pattern(self.title.as_ref())?,
+      env: self.env.clone().into_iter().collect(),
+    })
+  }
+
+  fn to_cmd(&self) -> Result<Cmd, String> {
+    Ok(Cmd {
+      annotation: self.annotation()?,
+      alias: self.alias.clone(),
+      sequence: self.sequence()?,
+      options: self.options()?,
+      parameter: self.parameter.as_deref().map(parse_parameter).transpose()?,
+      command: self.command.clone(),
+      mode: self.mode().to_owned(),
+    })
+  }
+
+  fn mode(&self) -> &str {
+    self.mode.as_deref().unwrap_or(DEFAULT_MODE)
+  }
+
+  /// The command line (with the annotation above it, if any). It's compared
+  /// as a command, since options that can't be read back (e.g. a quote in a
+  /// value with spaces) would still be read as part of the command text.
+  fn to_line(&self) -> Result<String, String> {
+    let cmd = self.to_cmd()?;
+    let written = cmd.to_string();
+    let parsed = parse_commands(&written);
+    let reparsed = (parsed.len() == 1)
+      .then(|| parsed[0].clone().in_mode(&cmd.mode))
+      .filter(|same| *same == cmd)
+      .map(|same| same.to_string());
+
+    check_written(written, reparsed.as_deref())
+  }
+}
+
+fn setting_value(value: &Value) -> Result<String, String> {
+  match value {
+    Value::String(text) => Ok(text.clone()),
+    Value::Integer(_) | Value::Float(_) | Value::Boolean(_) => Ok(value.to_string()),
+    _ => Err("must be a string, a number or a boolean".to_owned()),
+  }
+}
+
+fn setting_line(key: &str, value: &Value) -> Result<String, String> {
+  let written = format!("HORSETAB_{}={}", key.to_uppercase(), setting_value(value)?);
+  let parsed = parse_setting(&written).map(|(k, v)| format!("HORSETAB_{k}={v}"));
+
+  check_written(written, parsed.as_deref())
+}
+
+/// Values are kept as strings, unless they are plain numbers.
+fn toml_setting(value: &str) -> Value {
+  value
+    .parse::<i64>()
+    .ok()
+    .filter(|number| number.to_string() == value)
+    .map_or_else(|| Value::String(value.to_owned()), Value::Integer)
+}
+
+fn toml_settings(settings: &HashMap<String, String>) -> BTreeMap<String, Spanned<Value>> {
+  settings
+    .iter()
+    .map(|(key, value)| (key.to_lowercase(), Spanned::new(0..0, toml_setting(value))))
+    .collect()
+}
+
+type NumberedLines = Vec<(usize, String)>;
+
+fn include_line(path: &Spanned<String>, content: &str) -> (usize, String) {
+  let number = line_of(content, path.span().start);
+  (number, format!("include {}", path.get_ref()))
+}
+
+impl TomlConfig {
+  fn setting_lines(&self, content: &str) -> Result<NumberedLines, String> {
+    self
+      .settings
+      .iter()
+      .map(|(key, value)| {
+        let line = setting_line(key, value.get_ref()).map_err(|e| format!("settings.{key} {e}"))?;
+        Ok((line_of(content, value.span().start), line))
+      })
+      .collect()
+  }
+
+  /// The script's first line is the one after the quotes when they are
+  /// followed by a line break.
+  fn script_lines(&self, content: &str) -> NumberedLines {
+    let Some(script) = &self.script else {
+      return vec![];
+    };
+    let start = script.span().start;
+    let line_break_after_quotes = ["\"\"\"\n", "\"\"\"\r\n", "'''\n", "'''\r\n"]
+      .iter()
+      .any(|quotes| content[start..].starts_with(quotes));
+    let first = line_of(content, start) + usize::from(line_break_after_quotes);
+
+    (first..)
+      .zip(script.get_ref().lines().map(str::to_owned))
+      .collect()
+  }
+
+  /// The default mode, then the ones with commands (in order), and then the
+  /// declared ones without any.
+  fn mode_names(&self) -> Vec<&str> {
+    let mut names = vec![DEFAULT_MODE];
+    let used = self.commands.iter().map(|command| command.get_ref().mode());
+
+    for name in used.chain(self.modes.keys().map(String::as_str)) {
+      if !names.contains(&name) {
+        names.push(name);
+      }
+    }
+
+    names
+  }
+
+  fn mode_header(&self, name: &str) -> Result<String, String> {
+    let timeout = (self.modes.get(name))
+      .and_then(|mode| mode.get_ref().timeout.as_deref())
+      .map(|t| parse_timeout(t).ok_or_else(|| format!("invalid timeout `{t}`")))
+      .transpose()?;
+    let written = ModeHeader {
+      name: name.to_owned(),
+      timeout,
+    }
+    .to_string();
+    let parsed = ModeHeader::parse(&written).map(|header| header.to_string());
+
+    check_written(written, parsed.as_deref())
+  }
+
+  /// On the line the mode is declared on, or else on its first command.
+  fn header_line(&self, name: &str, content: &str) -> Result<(usize, String), String> {
+    let declared = self.modes.get(name).map(Spanned::span);
+    let first_command = || {
+      (self.commands.iter())
+        .find(|command| command.get_ref().mode() == name)
+        .map(Spanned::span)
+    };
+    let number = (declared.or_else(first_command)).map_or(1, |span| line_of(content, span.start));
+    let header = self
+      .mode_header(name)
+      .map_err(|e| format!("mode {name}: {e}"))?;
+
+    Ok((number, header))
+  }
+
+  fn mode_command_lines(&self, name: &str, content: &str) -> Result<NumberedLines, String> {
+    let mut lines = vec![];
+
+    for command in self.commands.iter().filter(|c| c.get_ref().mode() == name) {
+      let number = line_of(content, command.span().start);
+      let text = command
+        .get_ref()
+        .to_line()
+        .map_err(|e| format!("command on line {number}: {e}"))?;
+      lines.extend(text.lines().map(|line| (number, line.to_owned())));
+    }
+
+    Ok(lines)
+  }
+
+  /// The commands of each mode go after its header.
+  fn command_lines(&self, content: &str) -> Result<NumberedLines, String> {
+    let mut lines = vec![];
+
+    for name in self.mode_names() {
+      if name != DEFAULT_MODE {
+        lines.push(self.header_line(name, content)?);
+      }
+      lines.extend(self.mode_command_lines(name, content)?);
+    }
+
+    Ok(lines)
+  }
+
+  fn includes_in(&self, name: &str) -> &[Spanned<String>] {
+    if name == DEFAULT_MODE {
+      &self.include
+    } else {
+      self
+        .modes
+        .get(name)
+        .map_or(&[], |mode| &mode.get_ref().include)
+    }
+  }
+
+  fn mode_include_lines(
+    &self,
+    name: &str,
+    content: &str,
+    with_header: bool,
+  ) -> Result<NumberedLines, String> {
+    let includes = self.includes_in(name);
+    let mut lines: NumberedLines = (includes.iter())
+      .map(|path| include_line(path, content))
+      .collect();
+
+    if let Some((number, _)) = lines.first().filter(|_| with_header) {
+      let header = self
+        .mode_header(name)
+        .map_err(|e| format!("mode {name}: {e}"))?;
+      lines.insert(0, (*number, header));
+    }
+
+    Ok(lines)
+  }
+
+  /// Included files go after the commands, in the section of their mode (the
+  /// default one only needs a header after other modes).
+  fn include_lines(&self, content: &str) -> Result<NumberedLines, String> {
+    let names = self.mode_names();
+    let lines = (names.iter())
+      .map(|name| self.mode_include_lines(name, content, names.len() > 1))
+      .collect::<Result<Vec<NumberedLines>, String>>()?;
+
+    Ok(lines.concat())
+  }
+}
+
+/// The structured format written as lines (settings, script, commands by mode
+/// and then includes by mode), each with the number of the line it comes from.
+pub fn to_lines(content: &str) -> Result<NumberedLines, String> {
+  let config: TomlConfig = toml::from_str(content).map_err(|e| e.to_string())?;
+
+  Ok(
+    [
+      config.setting_lines(content)?,
+      config.script_lines(content),
+      config.command_lines(content)?,
+      config.include_lines(content)?,
+    ]
+    .concat(),
+  )
+}
+
+/// Settings, includes, annotations and modes have their own fields, so they
+/// aren't part of the script.
+fn is_script_line(line: &str, placement: Placement) -> bool {
+  match placement {
+    Placement::Script => {
+      parse_setting(line).is_none()
+        && directive(line).is_none()
+        && Annotation::parse(line).is_none()
+        && ModeHeader::parse(line).is_none()
+    }
+    Placement::Text => true,
+    Placement::Command(_) | Placement::Continuation => false,
+  }
+}
+
+fn script_text(lines: &[String], placements: &[Placement]) -> Option<String> {
+  let script: Vec<&str> = lines
+    .iter()
+    .zip(placements)
+    .filter(|(line, placement)| is_script_line(line, **placement))
+    .map(|(line, _)| line.as_str())
+    .collect();
+
+  (!script.is_empty()).then(|| format!("{}\n", script.join("\n")))
+}
+
+fn toml_commands(commands: &[Cmd]) -> Vec<Spanned<TomlCommand>> {
+  (commands.iter())
+    .map(|cmd| Spanned::new(0..0, TomlCommand::from_cmd(cmd)))
+    .collect()
+}
+
+/// What a line of the line format is, for the position of includes.
+enum Entry<'a> {
+  Include(&'a str),
+  Command,
+  /// Anything that runs, including settings.
+  Script,
+  Other,
+}
+
+fn entry(line: &str, placement: Placement) -> Entry<'_> {
+  let trimmed = line.trim();
+
+  match placement {
+    Placement::Command(_) => Entry::Command,
+    Placement::Continuation => Entry::Other,
+    Placement::Text => Entry::Script,
+    Placement::Script if trimmed.is_empty() || trimmed.starts_with('#') => Entry::Other,
+    Placement::Script => directive(line).map_or(Entry::Script, Entry::Include),
+  }
+}
+
+/// The lines other than the `[mode NAME]` ones, with their numbers and the
+/// mode of their section.
+fn entries<'a>(lines: &'a [String], placements: &[Placement]) -> Vec<(usize, Entry<'a>, String)> {
+  let mut result = vec![];
+  let mut mode = DEFAULT_MODE.to_owned();
+
+  for (number, (line, placement)) in (1..).zip(lines.iter().zip(placements)) {
+    match section(line, *placement) {
+      Some(header) => mode = header.name,
+      None => result.push((number, entry(line, *placement), mode.clone())),
+    }
+  }
+
+  result
+}
+
+/// Includes are written after the script and the commands, and grouped by
+/// mode (in the order of `names`), so a line after an include can't be kept
+/// after it when it's one of these.
+fn moves_include(entry: &Entry, mode: &str, include_mode: &str, names: &[&str]) -> bool {
+  let rank = |name: &str| names.iter().position(|n| *n == name);
+
+  match entry {
+    Entry::Script => true,
+    Entry::Command => mode == include_mode,
+    Entry::Include(_) => rank(mode) < rank(include_mode),
+    Entry::Other => false,
+  }
+}
+
+/// The conversion has to keep the mode of the included commands, and where
+/// their script runs, so includes that can't stay in place are an error.
+fn check_includes(
+  lines: &[String],
+  placements: &[Placement],
+  names: &[&str],
+) -> Result<(), String> {
+  let mut includes: Vec<(usize, &str, String)> = vec![];
+
+  for (number, entry, mode) in entries(lines, placements) {
+    let moved = (includes.iter()).find(|(_, _, m)| moves_include(&entry, &mode, m, names));
+
+    if let Some((line, path, _)) = moved {
+      return Err(format!(
+        "line {line}: include {path} can't be converted, since it would be moved after line \
+         {number} (includes go after the script and the commands of their mode)"
+      ));
+    }
+
+    if let Entry::Include(path) = entry {
+      includes.push((number, path, mode));
+    }
+  }
+
+  Ok(())
+}
+
+/// The included paths of each mode.
+fn includes(lines: &[String], placements: &[Placement]) -> Vec<(String, String)> {
+  entries(lines, placements)
+    .into_iter()
+    .filter_map(|(_, entry, mode)| match entry {
+      Entry::Include(path) => Some((path.to_owned(), mode)),
+      _ => None,
+    })
+    .collect()
+}
+
+fn included_in(includes: &[(String, String)], name: &str) -> Vec<Spanned<String>> {
+  (includes.iter())
+    .filter(|(_, mode)| mode == name)
+    .map(|(path, _)| Spanned::new(0..0, path.clone()))
+    .collect()
+}
+
+/// The modes with a section, other than the default one.
+fn toml_modes(
+  modes: &[ModeHeader],
+  includes: &[(String, String)],
+) -> BTreeMap<String, Spanned<TomlMode>> {
+  (modes.iter())
+    .filter(|mode| !mode.is_default())
+    .map(|mode| {
+      let toml_mode = TomlMode {
+        timeout: mode.timeout.map(format_timeout),
+        include: included_in(includes, &mode.name),
+      };
+      (mode.name.clone(), Spanned::new(0..0, toml_mode))
+    })
+    .collect()
+}
+
+/// Translates a file in the line format into the structured one. The
+/// commands, settings and includes get their own fields, and the remaining
+/// lines are the script.
+pub fn convert(lines: &[String]) -> Result<String, String> {
+  let placements = placements(lines);
+  let config = Configuration::from_lines(lines);
+  let includes = includes(lines, &placements);
+
+  let toml_config = TomlConfig {
+    include: included_in(&includes, DEFAULT_MODE),
+    script: script_text(lines, &placements).map(|script| Spanned::new(0..0, script)),
+    settings: toml_settings(&config.settings),
+    modes: toml_modes(&config.modes, &includes),
+    commands: toml_commands(&config.commands),
+  };
+  check_includes(lines, &placements, &toml_config.mode_names())?;
+
+  toml::to_string(&toml_config).map_err(|e| e.to_string())
+}
+
+/// Prints a file in the line format in the structured one.
+pub fn convert_file(config_path: &str) -> Result<String> {
+  if is_toml(Path::new(config_path)) {
+    bail!("{config_path} is already a TOML file");
+  }
+
+  let content =
+    std::fs::read_to_string(config_path).with_context(|| format!("Cannot read {config_path}"))?;
+  let lines: Vec<String> = content.lines().map(str::to_owned).collect();
+
+  convert(&lines).map_err(|err| anyhow!(err))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test_case::test_case;
+
+  fn string_vec(text: &str) -> Vec<String> {
+    text.lines().map(str::to_owned).collect()
+  }
+
+  fn config_from_toml(content: &str) -> Configuration {
+    let lines: Vec<String> = to_lines(content)
+      .unwrap()
+      .into_iter()
+      .map(|(_, l)| l)
+      .collect();
+    Configuration::from_lines(&lines)
+  }
+
+  #[test]
+  fn test_to_lines() {
+    let content = "include = [\"a.conf\"]\nscript = \"\"\"\nA=1\nB=2\n\"\"\"\n\n\
+                   [settings]\nlong_press_ms = 250\n\n\
+                   [[commands]]\nalias = \"SOS\"\nparameter = \"number\"\ncommand = \"echo $1\"\n";
+    let expected = [
+      (8, "HORSETAB_LONG_PRESS_MS=250"),
+      (3, "A=1"),
+      (4, "B=2"),
+      (10, "@SOS #n echo $1"),
+      (1, "include a.conf"),
+    ]
+    .map(|(number, line)| (number, line.to_owned()));
+    assert_eq!(to_lines(content).unwrap(), expected);
+  }
+
+  #[test_case("[[commands]]\nsequence = \"..\"\ntitle = \"a \\\" b\"\ncommand = \"a\"" => "command on line 1: can't be written as a line: .. [title=\"a \" b\"] a"; "quote in a value with spaces")]
+  #[test_case("[[commands]]\ncommand = \"a\"" => "command on line 1: needs either a sequence or an alias"; "no sequence")]
+  #[test_case("[[commands]]\nsequence = \".x\"\ncommand = \"a\"" => "command on line 1: can't be written as a line: .x a"; "invalid sequence")]
+  #[test_case("[[commands]]\nsequence = \"..\"\ntimeout = \"soon\"\ncommand = \"a\"" => "command on line 1: invalid timeout `soon`"; "invalid timeout")]
+  #[test_case("[[commands]]\nsequence = \"..\"\nclass = \"(x\"\ncommand = \"a\"" => "command on line 1: invalid pattern `(x`"; "invalid pattern")]
+  #[test_case("[[commands]]\nsequence = \"..\"\ndescription = \"x\"\ncommand = \"a\"" => "command on line 1: a description needs a name"; "description without name")]
+  #[test_case("[settings]\nbutton = [1]" => "settings.button must be a string, a number or a boolean"; "invalid setting")]
+  fn test_to_lines_errors(content: &str) -> String {
+    to_lines(content).unwrap_err()
+  }
+
+  fn command_texts(config: &Configuration) -> Vec<String> {
+    config.commands.iter().map(ToString::to_string).collect()
+  }
+
+  /// The script lines, without the settings and annotations that `convert` moves.
+  fn unmoved_script(config: &Configuration) -> Vec<String> {
+    let lines = string_vec(&config.pre_script).into_iter();
+    lines
+      .filter(|l| parse_setting(l).is_none() && Annotation::parse(l).is_none())
+      .collect()
+  }
+
+  /// The legacy configuration used by the round-trip tests, and its conversion.
+  fn round_trip() -> (Configuration, Configuration) {
+    let legacy = string_vec(
+      "A=1\nHORSETAB_LONG_PRESS_MS=250\n# first\nHORSETAB_BUTTON=middle\n\n# @name lock: Lock it\n\
+       .-.- [cwd=~/x timeout=2s env.B=2] #n lock $1\n@S,O,S echo a \\\n  && echo b\n\
+       cat <<X\n..-- text\nX\n-..- #s echo $1\n-..- [class=^firefox$ title=Git] echo b\n\
+       --.. [cwd=\"~/My Projects\" title=\"GitHub - Firefox\"] echo c",
+    );
+    let original = Configuration::from_lines(&legacy);
+    (original, config_from_toml(&convert(&legacy).unwrap()))
+  }
+
+  #[test]
+  fn test_convert_round_trip() {
+    let (original, converted) = round_trip();
+    assert_eq!(command_texts(&converted), command_texts(&original));
+    assert_eq!(converted.settings, original.settings);
+  }
+
+  #[test]
+  fn test_convert_script_order() {
+    let (original, converted) = round_trip();
+    // The settings go first, sorted, and the annotations go with their commands.
+    let script = string_vec(&converted.pre_script);
+    assert_eq!(
+      script[..2],
+      ["HORSETAB_BUTTON=middle", "HORSETAB_LONG_PRESS_MS=250"]
+    );
+    assert_eq!(script.last().unwrap(), "# @name lock: Lock it");
+    // The comments, blank lines and the rest of the script keep their order.
+    assert_eq!(unmoved_script(&converted), unmoved_script(&original));
+    assert_eq!(
+      unmoved_script(&original),
+      ["A=1", "# first", "", "cat <<X", "..-- text", "X"]
+    );
+  }
+
+  fn converted_lines(legacy: &str) -> Result<Vec<String>, String> {
+    let toml = convert(&string_vec(legacy))?;
+    Ok(to_lines(&toml)?.into_iter().map(|(_, line)| line).collect())
+  }
+
+  #[test_case("A=1\n.-.- echo a\ninclude a.conf\ninclude b.conf" => vec!["A=1", ".-.- echo a", "include a.conf", "include b.conf"]; "default mode")]
+  #[test_case("..-- echo a\n[mode vim]\n.-.- echo b\ninclude vim.conf" => vec!["..-- echo a", "[mode vim]", ".-.- echo b", "[mode vim]", "include vim.conf"]; "inside a mode")]
+  #[test_case("include a.conf\n[mode vim]\n.-.- echo b\ninclude vim.conf" => vec!["[mode vim]", ".-.- echo b", "[mode default]", "include a.conf", "[mode vim]", "include vim.conf"]; "in both modes")]
+  fn test_convert_includes(legacy: &str) -> Vec<String> {
+    converted_lines(legacy).unwrap()
+  }
+
+  #[test]
+  fn test_convert_includes_keeps_modes() {
+    let legacy = string_vec("..-- echo a\n[mode vim]\ninclude vim.conf\n# end");
+    let toml = convert(&legacy).unwrap();
+    assert!(
+      toml.contains("[modes.vim]\ninclude = [\"vim.conf\"]"),
+      "{toml}"
+    );
+  }
+
+  #[test_case("include a.conf\n.-.- echo a" => 2; "command after it")]
+  #[test_case("include a.conf\nA=1" => 2; "script after it")]
+  #[test_case("[mode vim]\ninclude vim.conf\n[mode default]\ninclude a.conf" => 4; "mode order")]
+  fn test_convert_includes_out_of_place(legacy: &str) -> usize {
+    let err = converted_lines(legacy).unwrap_err();
+    let after = err.split("after line ").nth(1).unwrap();
+    after.split_whitespace().next().unwrap().parse().unwrap()
+  }
+
+  #[test]
+  fn test_to_lines_modes() {
+    let content = "include = [\"a.conf\"]\n[modes.browser]\ntimeout = \"30s\"\n\n\
+                   [[commands]]\nsequence = \"..--\"\nmode = \"browser\"\ncommand = \"a\"\n\n\
+                   [[commands]]\nsequence = \"-..-\"\nswitch = \"browser\"\ncommand = \":\"\n";
+    let expected = [
+      (10, "-..- [switch=browser] :"),
+      (2, "[mode browser timeout=30s]"),
+      (5, "..-- a"),
+      (1, "[mode default]"),
+      (1, "include a.conf"),
+    ]
+    .map(|(number, line)| (number, line.to_owned()));
+    assert_eq!(to_lines(content).unwrap(), expected);
+  }
+
+  #[test]
+  fn test_convert_modes() {
+    let legacy =
+      string_vec("[mode idle timeout=5s]\n[mode vim]\n..-- echo a\n[mode default]\n-..- echo b");
+    let converted = config_from_toml(&convert(&legacy).unwrap());
+    let modes: Vec<&str> = converted.commands.iter().map(|c| c.mode.as_str()).collect();
+    assert_eq!(modes, ["default", "vim"]);
+    // Modes without commands go last.
+    let names: Vec<String> = converted.modes.iter().map(ToString::to_string).collect();
+    assert_eq!(
+      names,
+      ["[mode default]", "[mode vim]", "[mode idle timeout=5s]"]
+    );
+  }
+
+  #[test_case("a/horsetab.toml" => true; "toml")]
+  #[test_case(".horsetab.conf" => false; "conf")]
+  #[test_case("toml" => false; "no extension")]
+  fn test_is_toml(path: &str) -> bool {
+    is_toml(Path::new(path))
+  }
+}
@@ -1,22 +1,248 @@
-use super::process_manager::ProcessManager;
-use crate::{cmd::Cmd, sequence_automata::SequenceAutomata};
+use std::{
+  sync::Arc,
+  time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+
+use super::{
+  arming_gate::ArmingGate,
+  parameter_capture::{CommandMatch, ParameterCapture},
+  process_manager::ProcessManager,
+};
+use crate::{
+  active_window::{CommandWindowProvider, WindowProvider},
+  arming_settings::ArmingSettings,
+  cmd::Cmd,
+  constants::DEFAULT_WINDOW_COMMAND,
+  fuzzy_matcher::{Burst, FuzzyMatcher, FuzzyResult},
+  input_settings::InputSettings,
+  match_settings::MatchSettings,
+  mode::{ModeHeader, DEFAULT_MODE},
+  sequence_automata::{AutomataInstruction, SequenceAutomata},
+  timing_calibration::{TimingCalibration, TimingSample},
+  timing_settings::TimingSettings,
+};
 
 pub struct MainProcessState {
+  /// The commands of every mode.
   pub commands: Vec<Cmd>,
   pub pre_script: String,
+  pub modes: Vec<ModeHeader>,
+  pub mode: String,
+  /// When the last instruction came in the current mode.
+  pub mode_activity: Instant,
+  /// The ids of the commands in the current mode, which are the only ones the
+  /// automata (and the fuzzy matcher) know, by their position.
+  pub active: Vec<usize>,
   pub automata: SequenceAutomata,
+  pub matching: MatchSettings,
+  pub fuzzy: Option<FuzzyMatcher>,
+  pub burst: Burst,
+  /// Sequences that were just as close to a sequence that didn't match.
+  pub near_miss: Option<Vec<String>>,
+  pub capture: Option<ParameterCapture>,
   pub process_manager: ProcessManager,
   pub interpreter: String,
+  pub default_timing: TimingSettings,
+  pub timing: TimingSettings,
+  pub default_input: InputSettings,
+  pub input: InputSettings,
+  pub default_arming: ArmingSettings,
+  pub arming_gate: ArmingGate,
+  pub calibration: TimingCalibration,
+  pub calibration_path: Option<String>,
+  /// Picks between commands of the same sequence that depend on the window.
+  pub window_provider: Arc<dyn WindowProvider>,
+}
+
+/// Without a path (e.g. when replaying) nothing is loaded or saved.
+fn load_calibration(path: Option<&str>) -> TimingCalibration {
+  path.map_or_else(TimingCalibration::new, TimingCalibration::load_or_new)
 }
 
 impl MainProcessState {
-  pub fn new(interpreter: &str) -> Self {
+  #[allow(clippy::too_many_lines)]
+  pub fn new(
+    interpreter: &str,
+    timing: TimingSettings,
+    input: InputSettings,
+    arming: ArmingSettings,
+    calibration_path: Option<&str>,
+  ) -> Self {
     Self {
       commands: vec![],
       pre_script: String::new(),
+      modes: vec![ModeHeader::new(DEFAULT_MODE)],
+      mode: DEFAULT_MODE.to_owned(),
+      mode_activity: Instant::now(),
+      active: vec![],
       automata: SequenceAutomata::new(&[]),
+      matching: MatchSettings::default(),
+      fuzzy: None,
+      burst: Burst::default(),
+      near_miss: None,
+      capture: None,
       process_manager: ProcessManager::new(),
       interpreter: interpreter.to_owned(),
+      default_timing: timing,
+      timing,
+      default_input: input,
+      input,
+      arming_gate: ArmingGate::new(arming.clone()),
+      default_arming: arming,
+      calibration: load_calibration(calibration_path),
+      calibration_path: calibration_path.map(str::to_owned),
+      window_provider: Arc::new(CommandWindowProvider::new(DEFAULT_WINDOW_COMMAND)),
+    }
+  }
+
+  pub fn effective_timing(&self) -> TimingSettings {
+    if self.timing.adaptive {
+      self.calibration.learned_timing(self.timing)
+    } else {
+      self.timing
+    }
+  }
+
+  fn save_calibration_if_needed(&mut self) {
+    let Some(path) = &self.calibration_path else {
+      return;
+    };
+
+    if self.calibration.should_save() {
+      if let Err(e) = self.calibration.save(path) {
+        eprintln!("Cannot save timing calibration: {e}");
+      }
+    }
+  }
+
+  pub fn observe_timing(&mut self, sample: TimingSample) -> TimingSettings {
+    if self.timing.adaptive && !sample.is_idle(&self.effective_timing()) {
+      self.calibration.record(sample);
+
+      self.save_calibration_if_needed();
+    }
+
+    self.effective_timing()
+  }
+
+  fn build_matchers(&mut self) {
+    let sequences: Vec<&str> = (self.active.iter())
+      .map(|id| self.commands[*id].sequence.as_str())
+      .collect();
+
+    self.automata = SequenceAutomata::from_sequences(&sequences, self.matching.match_anywhere)
+      .with_wait_for_longer(self.timing.wait_for_longer);
+    self.fuzzy = (self.matching.fuzzy_distance > 0)
+      .then(|| FuzzyMatcher::new(&sequences, self.matching.fuzzy_distance));
+  }
+
+  /// Only the commands in the mode are matched from now on. The mode has to
+  /// be one of `modes`.
+  pub fn enter_mode(&mut self, name: &str, now: Instant) {
+    name.clone_into(&mut self.mode);
+    self.mode_activity = now;
+    self.active = (0..self.commands.len())
+      .filter(|id| self.commands[*id].mode == name)
+      .collect();
+
+    self.build_matchers();
+    self.reset_sequence();
+  }
+
+  pub fn switch_mode(&mut self, name: &str, now: Instant) -> Result<()> {
+    if !self.modes.iter().any(|mode| mode.name == name) {
+      bail!("There's no mode named {name}");
+    }
+
+    self.enter_mode(name, now);
+    Ok(())
+  }
+
+  /// How long until the current mode goes back to the default one, when it
+  /// has a timeout.
+  pub fn mode_time_left(&self, now: Instant) -> Option<Duration> {
+    let mode = self.modes.iter().find(|mode| mode.name == self.mode)?;
+    let idle = now.saturating_duration_since(self.mode_activity);
+
+    mode
+      .timeout
+      .filter(|_| !mode.is_default())
+      .map(|timeout| timeout.saturating_sub(idle))
+  }
+
+  /// Whether a sequence is complete, and only needs to end (i.e. a pause).
+  pub fn is_waiting(&self) -> bool {
+    self
+      .capture
+      .as_ref()
+      .map_or_else(|| self.automata.is_waiting(), ParameterCapture::is_waiting)
+  }
+
+  pub fn reset_sequence(&mut self) {
+    self.automata.put(AutomataInstruction::Reset);
+    self.capture = None;
+    self.burst = Burst::default();
+  }
+
+  fn start_capture(&mut self, ids: &[usize]) {
+    let commands: Vec<_> = ids
+      .iter()
+      .filter_map(|id| Some((*id, self.commands[*id].parameter?)))
+      .collect();
+
+    if !commands.is_empty() {
+      self.capture = Some(ParameterCapture::new(commands));
+    }
+  }
+
+  /// Commands with a parameter are never matched approximately.
+  fn fuzzy_match(&mut self, symbols: &str) -> Option<Vec<CommandMatch>> {
+    match self.fuzzy.as_ref()?.closest(symbols)? {
+      FuzzyResult::Match(ids) => {
+        let matches: Vec<CommandMatch> = ids
+          .into_iter()
+          .map(|id| self.active[id])
+          .filter(|id| self.commands[*id].parameter.is_none())
+          .map(CommandMatch::new)
+          .collect();
+        (!matches.is_empty()).then_some(matches)
+      }
+      FuzzyResult::Ambiguous(sequences) => {
+        self.near_miss = Some(sequences);
+        None
+      }
+    }
+  }
+
+  /// A sequence that ends without matching can still match approximately.
+  pub fn put_instruction(&mut self, instruction: AutomataInstruction) -> Option<Vec<CommandMatch>> {
+    let matches = self.match_instruction(instruction);
+    let unmatched = self.burst.put(instruction, matches.is_some());
+    matches.or_else(|| self.fuzzy_match(&unmatched?))
+  }
+
+  /// Commands with a parameter are matched after capturing the symbols that
+  /// follow the sequence.
+  fn match_instruction(&mut self, instruction: AutomataInstruction) -> Option<Vec<CommandMatch>> {
+    if let Some(capture) = self.capture.as_mut() {
+      let ended = capture.put(instruction);
+      let matches = self.capture.take_if(|_| ended)?.finish();
+      return (!matches.is_empty()).then_some(matches);
     }
+
+    let ids: Vec<usize> = (self.automata.put(instruction)?.into_iter())
+      .map(|id| self.active[id])
+      .collect();
+    self.start_capture(&ids);
+
+    let matches: Vec<CommandMatch> = ids
+      .into_iter()
+      .filter(|id| self.commands[*id].parameter.is_none())
+      .map(CommandMatch::new)
+      .collect();
+
+    (!matches.is_empty()).then_some(matches)
   }
 }
@@ -1,11 +1,14 @@
+use super::config_diagnostics::Diagnostic;
 use super::config_file_parser::Configuration;
+use super::config_includes::read_config;
 use super::global_context::MainProcessState;
-use crate::sequence_automata::SequenceAutomata;
-use crate::util::read_lines_or_create;
+use crate::match_settings::MatchSettings;
+use crate::mode::DEFAULT_MODE;
+use std::time::Instant;
 
 pub enum InstallResult {
   Ok(usize),
-  Unreachable((usize, Vec<String>)),
+  Diagnostics((usize, Vec<Diagnostic>)),
   FileError(std::io::Error),
 }
 
@@ -13,12 +16,12 @@ impl ToString for InstallResult {
   fn to_string(&self) -> String {
     match self {
       Self::Ok(count) => format!("Installed {count} commands"),
-      Self::Unreachable((count, sequences)) => {
-        let mut text = format!("Installed {count} commands, with some unreachable sequence(s):");
+      Self::Diagnostics((count, diagnostics)) => {
+        let mut text = format!("Installed {count} commands, with some problem(s):");
 
-        for seq in sequences {
+        for diagnostic in diagnostics {
           text += "\n";
-          text += seq;
+          text += &diagnostic.to_string();
         }
 
         text
@@ -28,26 +31,40 @@ impl ToString for InstallResult {
   }
 }
 
-fn assign_global_state(config: Configuration, state: &mut MainProcessState) {
-  state.automata = SequenceAutomata::new(&config.get_sequences());
+/// The current mode is kept if it's still there.
+pub fn assign_global_state(config: Configuration, state: &mut MainProcessState) {
+  let kept = config.modes.iter().any(|mode| mode.name == state.mode);
+  let mode = if kept {
+    state.mode.clone()
+  } else {
+    DEFAULT_MODE.to_owned()
+  };
+
+  state.timing = state.default_timing.with_overrides(&config.settings);
+  state.matching = MatchSettings::from_settings(&config.settings);
   state.commands = config.commands;
+  state.modes = config.modes;
+  state.enter_mode(&mode, Instant::now());
   state.pre_script = config.pre_script;
+  state.input = state.default_input.with_overrides(&config.settings);
+  let arming = state.default_arming.with_overrides(&config.settings);
+  state.arming_gate.set_settings(arming);
 }
 
 pub fn install_state_from_file(config_path: &str, state: &mut MainProcessState) -> InstallResult {
-  match read_lines_or_create(config_path) {
-    Ok(lines) => {
-      let config = Configuration::from_lines(&lines);
+  match read_config(config_path) {
+    Ok(source) => {
+      let config = Configuration::from_source(&source, state.default_timing);
 
       let total = config.commands.len();
-      let unreachable_sequences = config.unreachable_sequences.clone();
+      let diagnostics = config.diagnostics.clone();
 
       assign_global_state(config, state);
 
-      if unreachable_sequences.is_empty() {
+      if diagnostics.is_empty() {
         InstallResult::Ok(total)
       } else {
-        InstallResult::Unreachable((total, unreachable_sequences))
+        InstallResult::Diagnostics((total, diagnostics))
       }
     }
     Err(err) => InstallResult::FileError(err),
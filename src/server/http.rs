@@ -1,9 +1,17 @@
 use super::{
+  automata_manager::change_mode,
   global_context::MainProcessState,
   global_context_installer::{install_state_from_file, InstallResult},
   process_manager::ProcessManager,
+  results_command_exec::start_named,
+};
+use crate::{
+  cmd::{format_commands, Cmd},
+  event_observe::EventType,
+  sequence_automata::AutomataInstruction,
+  timing_settings::TimingSettings,
+  util::read_lines_or_create,
 };
-use crate::{cmd::Cmd, sequence_automata::AutomataInstruction, util::read_lines_or_create};
 use anyhow::{bail, Result};
 use rouille::{Request, Response, Server};
 use std::{
@@ -11,8 +19,16 @@ use std::{
   fs::OpenOptions,
   io::{Read, Write},
   sync::{mpsc::Sender, Arc, Mutex},
+  time::Instant,
 };
 
+/// Where the server sends sequences, and events (e.g. when the mode is
+/// switched).
+pub struct Senders {
+  pub sequence: Sender<AutomataInstruction>,
+  pub events: Sender<EventType>,
+}
+
 fn handle_response(response: Result<Response>) -> Response {
   match response {
     Ok(res) => res,
@@ -65,13 +81,27 @@ fn reinstall(
 
 #[allow(clippy::unnecessary_wraps)]
 fn curr_cmds(commands: &[Cmd]) -> Result<Response> {
-  let current_commands_text = commands
-    .iter()
-    .map(|cmd| format!("{} {}", cmd.sequence, cmd.command))
-    .collect::<Vec<String>>()
-    .join("\n");
+  Ok(Response::text(format_commands(commands)))
+}
 
-  Ok(Response::text(current_commands_text))
+#[allow(clippy::unnecessary_wraps)]
+fn get_mode(mode: &str) -> Result<Response> {
+  Ok(Response::text(mode))
+}
+
+/// The automata are woken up, so that they wait for the new mode's timeout.
+fn set_mode(
+  request: &Request,
+  state: &mut MainProcessState,
+  senders: &Senders,
+) -> Result<Response> {
+  let Some(name) = request.get_param("name") else {
+    bail!("Missing mode name");
+  };
+  change_mode(state, &senders.events, &name, Instant::now())?;
+  senders.sequence.send(AutomataInstruction::Wake)?;
+
+  Ok(Response::text(format!("Switched to {name}")))
 }
 
 fn send_sequence(
@@ -79,16 +109,31 @@ fn send_sequence(
   sequence_sender: &Sender<AutomataInstruction>,
 ) -> Result<Response> {
   let seq = get_body_as_string(request)?;
+  sequence_sender.send(AutomataInstruction::SentStart)?;
   sequence_sender.send(AutomataInstruction::Reset)?;
 
   for c in seq.chars() {
     sequence_sender.send(AutomataInstruction::Char(c))?;
   }
   sequence_sender.send(AutomataInstruction::Reset)?;
+  sequence_sender.send(AutomataInstruction::SentEnd)?;
 
   Ok(Response::empty_204())
 }
 
+fn run_named(request: &Request, state: &MainProcessState) -> Result<Response> {
+  let Some(name) = request.get_param("name") else {
+    bail!("Missing command name");
+  };
+  let pids = start_named(state, &name, request.get_param("argument").as_deref())?;
+  let started: Vec<String> = pids
+    .iter()
+    .map(|pid| format!("Started {name} (PID {pid})"))
+    .collect();
+
+  Ok(Response::text(started.join("\n")))
+}
+
 #[allow(clippy::unnecessary_wraps)]
 fn get_ps(process_manager: &ProcessManager) -> Result<Response> {
   Ok(Response::text(process_manager.format_information()))
@@ -99,11 +144,17 @@ fn get_tcp_port(tcp_port: u16) -> Result<Response> {
   Ok(Response::text(format!("{tcp_port}")))
 }
 
+#[allow(clippy::unnecessary_wraps)]
+fn get_timing(timing: TimingSettings) -> Result<Response> {
+  Ok(Response::text(timing.to_string()))
+}
+
+#[allow(clippy::too_many_lines)]
 fn build_http_server(
   port: u16,
   tcp_port: u16,
   config_path: &str,
-  sequence_sender: Sender<AutomataInstruction>,
+  senders: Senders,
   state: Arc<Mutex<MainProcessState>>,
 ) -> Result<Server<impl Fn(&Request) -> Response>, Box<dyn Error + Send + Sync>> {
   let conf_path = config_path.to_owned();
@@ -113,8 +164,12 @@ fn build_http_server(
       ("GET", "/current-config-file-content") => read_config_file(&conf_path),
       ("GET", "/ps") => get_ps(&state.lock().unwrap().process_manager),
       ("GET", "/tcp-port") => get_tcp_port(tcp_port),
+      ("GET", "/timing") => get_timing(state.lock().unwrap().effective_timing()),
       ("GET", "/current-installed-commands") => curr_cmds(&state.lock().unwrap().commands),
-      ("POST", "/send-sequence") => send_sequence(req, &sequence_sender),
+      ("GET", "/mode") => get_mode(&state.lock().unwrap().mode),
+      ("POST", "/mode") => set_mode(req, &mut state.lock().unwrap(), &senders),
+      ("POST", "/send-sequence") => send_sequence(req, &senders.sequence),
+      ("POST", "/run") => run_named(req, &state.lock().unwrap()),
       ("PUT", "/re-install") => reinstall(req, &conf_path, &mut state.lock().unwrap()),
       _ => Ok(Response::text("Not found").with_status_code(404)),
     })
@@ -125,10 +180,10 @@ pub fn start_http_server(
   port: u16,
   tcp_port: u16,
   config_path: &str,
-  sequence_sender: Sender<AutomataInstruction>,
+  senders: Senders,
   state: Arc<Mutex<MainProcessState>>,
 ) {
-  match build_http_server(port, tcp_port, config_path, sequence_sender, state) {
+  match build_http_server(port, tcp_port, config_path, senders, state) {
     Ok(server) => {
       println!("Listening on {:?}", server.server_addr());
       server.run();
@@ -140,3 +195,71 @@ pub fn start_http_server(
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    arming_settings::ArmingSettings,
+    constants::DEFAULT_INTERPRETER,
+    input_settings::InputSettings,
+    server::{
+      automata_manager::manage_automata, config_file_parser::Configuration,
+      global_context_installer::assign_global_state,
+    },
+  };
+  use std::{sync::mpsc, thread, time::Duration};
+
+  fn state_with_modes() -> Arc<Mutex<MainProcessState>> {
+    let mut state = MainProcessState::new(
+      DEFAULT_INTERPRETER,
+      TimingSettings::default(),
+      InputSettings::default(),
+      ArmingSettings::default(),
+      None,
+    );
+    let lines = [
+      "[mode b timeout=100ms]".to_owned(),
+      ".-.- echo b".to_owned(),
+    ];
+    assign_global_state(Configuration::from_lines(&lines), &mut state);
+    Arc::new(Mutex::new(state))
+  }
+
+  /// The automata thread, and the ends of the channels to talk to it.
+  fn spawn_automata(
+    state: &Arc<Mutex<MainProcessState>>,
+  ) -> (Senders, mpsc::Receiver<EventType>, thread::JoinHandle<()>) {
+    let (sequence, sequence_rec) = mpsc::channel();
+    let (events, events_rec) = mpsc::channel();
+    let automata_events = events.clone();
+    let automata_state = Arc::clone(state);
+    let automata = thread::spawn(move || {
+      let (results_sender, _results) = mpsc::channel();
+      manage_automata(
+        &results_sender,
+        &sequence_rec,
+        &automata_events,
+        &automata_state,
+      );
+    });
+
+    (Senders { sequence, events }, events_rec, automata)
+  }
+
+  #[test]
+  fn test_set_mode_times_out() {
+    let state = state_with_modes();
+    let (senders, _events, automata) = spawn_automata(&state);
+    // The automata wait without a timeout in the default mode.
+    thread::sleep(Duration::from_millis(50));
+
+    let request = Request::fake_http("POST", "/mode?name=b", vec![], vec![]);
+    set_mode(&request, &mut state.lock().unwrap(), &senders).unwrap();
+    thread::sleep(Duration::from_millis(400));
+    assert_eq!(state.lock().unwrap().mode, "default");
+
+    drop(senders);
+    automata.join().unwrap();
+  }
+}
@@ -0,0 +1,254 @@
+use rdev::Key;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+use super::global_context::MainProcessState;
+use crate::{
+  click_sequence_detector::{ClickSequenceDetector, MouseClickKind},
+  gesture_detector::GestureDetector,
+  input_event::{InputEvent, InputEventKind, InputRecorder},
+  input_settings::{InputButton, InputSettings, WHEEL_DOWN, WHEEL_UP},
+  input_source::InputSource,
+  sequence_automata::AutomataInstruction,
+  timing_calibration::TimingSample,
+};
+
+static GESTURE_MIN_DISTANCE: f64 = 80.0;
+
+/// A single scroll produces several wheel events in a row.
+static WHEEL_DEBOUNCE_MS: u128 = 300;
+
+/// Turns input events into automata instructions.
+pub struct InputHandler {
+  click_detector: ClickSequenceDetector,
+  key_down: bool,
+  arm_key_down: bool,
+  position: (f64, f64),
+  gesture: Option<GestureDetector>,
+  last_wheel: Option<(char, u128)>,
+  recorder: Option<InputRecorder>,
+  seq_sender: Sender<AutomataInstruction>,
+  state: Arc<Mutex<MainProcessState>>,
+}
+
+impl InputHandler {
+  pub const fn new(
+    seq_sender: Sender<AutomataInstruction>,
+    state: Arc<Mutex<MainProcessState>>,
+    recorder: Option<InputRecorder>,
+  ) -> Self {
+    Self {
+      click_detector: ClickSequenceDetector::new(),
+      key_down: false,
+      arm_key_down: false,
+      position: (0.0, 0.0),
+      gesture: None,
+      last_wheel: None,
+      recorder,
+      seq_sender,
+      state,
+    }
+  }
+
+  fn send(&self, instruction: AutomataInstruction) {
+    self
+      .seq_sender
+      .send(instruction)
+      .expect("Should send sequence instruction");
+  }
+
+  fn input_settings(&self) -> InputSettings {
+    self.state.lock().unwrap().input
+  }
+
+  /// A pause longer than the reset time ends the sequence before the symbol.
+  fn start_symbol(&self, time_between_inputs: u128, sequence_reset_ms: u128) {
+    if time_between_inputs > sequence_reset_ms {
+      self.send(AutomataInstruction::Reset);
+    }
+
+    self.send(AutomataInstruction::Press);
+  }
+
+  fn press(&mut self, now: u128) {
+    let time_between_inputs = self.click_detector.click(now);
+    let timing = self
+      .state
+      .lock()
+      .unwrap()
+      .observe_timing(TimingSample::Gap(time_between_inputs));
+
+    self.start_symbol(time_between_inputs, timing.sequence_reset_ms);
+  }
+
+  /// Wheel notches aren't clicks, so they don't teach the click timing.
+  fn wheel_press(&mut self, now: u128) {
+    let time_between_inputs = self.click_detector.click(now);
+    let timing = self.state.lock().unwrap().effective_timing();
+
+    self.start_symbol(time_between_inputs, timing.sequence_reset_ms);
+  }
+
+  fn release(&mut self, symbols: InputButton, now: u128) {
+    let press_duration = self.click_detector.release(now);
+    let timing = self
+      .state
+      .lock()
+      .unwrap()
+      .observe_timing(TimingSample::Press(press_duration));
+    let click_kind = MouseClickKind::from_duration(press_duration, timing.long_press_ms);
+
+    self.send(AutomataInstruction::Char(symbols.symbol(click_kind)));
+  }
+
+  fn is_trigger_key(&self, key: Key) -> bool {
+    self.input_settings().key == Some(key)
+  }
+
+  fn is_arm_key(&self, key: Key) -> bool {
+    self.state.lock().unwrap().arming_gate.settings().key == Some(key)
+  }
+
+  /// Holding a key down repeats the press event, so only the first one counts.
+  fn handle_key_press(&mut self, key: Key, now: u128) {
+    if !self.key_down && self.is_trigger_key(key) {
+      self.key_down = true;
+      self.press(now);
+    } else if !self.arm_key_down && self.is_arm_key(key) {
+      self.arm_key_down = true;
+      self.send(AutomataInstruction::Arm);
+    }
+  }
+
+  fn handle_key_release(&mut self, key: Key, now: u128) {
+    if self.key_down && self.is_trigger_key(key) {
+      self.key_down = false;
+      self.release(InputButton::Left, now);
+    } else if self.arm_key_down && self.is_arm_key(key) {
+      self.arm_key_down = false;
+      self.send(AutomataInstruction::Disarm);
+    }
+  }
+
+  fn handle_button_press(&mut self, button: Option<InputButton>, now: u128) {
+    let input = self.input_settings();
+
+    if input.symbols_for(button).is_some() {
+      self.press(now);
+
+      if input.gestures {
+        self.gesture = Some(GestureDetector::new(self.position, GESTURE_MIN_DISTANCE));
+      }
+    }
+  }
+
+  /// A press that made a gesture doesn't also count as a click.
+  fn handle_button_release(&mut self, button: Option<InputButton>, now: u128) {
+    if let Some(symbols) = self.input_settings().symbols_for(button) {
+      if self.gesture.take().is_some_and(|g| g.has_gestures()) {
+        self.click_detector.cancel(now);
+      } else {
+        self.release(symbols, now);
+      }
+    }
+  }
+
+  fn handle_mouse_move(&mut self, x: f64, y: f64) {
+    self.position = (x, y);
+
+    if let Some(symbol) = self.gesture.as_mut().and_then(|g| g.moved((x, y))) {
+      self.send(AutomataInstruction::Char(symbol));
+    }
+  }
+
+  fn is_repeated_wheel(&self, symbol: char, now: u128) -> bool {
+    self.last_wheel.is_some_and(|(last_symbol, time)| {
+      last_symbol == symbol && now.saturating_sub(time) < WHEEL_DEBOUNCE_MS
+    })
+  }
+
+  fn handle_wheel(&mut self, delta_y: i64, now: u128) {
+    let symbol = match delta_y.signum() {
+      1 => WHEEL_UP,
+      -1 => WHEEL_DOWN,
+      _ => return,
+    };
+
+    let repeated = self.is_repeated_wheel(symbol, now);
+    self.last_wheel = Some((symbol, now));
+
+    if !repeated && self.input_settings().wheel {
+      self.wheel_press(now);
+      self.send(AutomataInstruction::Char(symbol));
+    }
+  }
+
+  /// Whether the event can affect the result. Other keys and pointer movements
+  /// are not recorded.
+  fn is_relevant(&self, kind: InputEventKind) -> bool {
+    match kind {
+      InputEventKind::KeyPress(key) | InputEventKind::KeyRelease(key) => {
+        self.is_trigger_key(key) || self.is_arm_key(key)
+      }
+      InputEventKind::MouseMove(..) => self.gesture.is_some(),
+      _ => true,
+    }
+  }
+
+  /// Gestures start where the pointer is when pressing, so that position is
+  /// recorded before the press.
+  fn events_to_record(&self, event: InputEvent) -> Vec<InputEvent> {
+    if !self.is_relevant(event.kind) {
+      return vec![];
+    }
+
+    if matches!(event.kind, InputEventKind::ButtonPress(_)) && self.input_settings().gestures {
+      let (x, y) = self.position;
+      let kind = InputEventKind::MouseMove(x, y);
+      return vec![InputEvent { kind, ..event }, event];
+    }
+
+    vec![event]
+  }
+
+  fn record(&mut self, event: InputEvent) {
+    if self.recorder.is_none() {
+      return;
+    }
+
+    let events = self.events_to_record(event);
+
+    let Some(recorder) = self.recorder.as_mut() else {
+      return;
+    };
+
+    if let Err(e) = events.into_iter().try_for_each(|e| recorder.write(e)) {
+      eprintln!("Cannot record input: {e}");
+      self.recorder = None;
+    }
+  }
+
+  pub fn handle(&mut self, event: InputEvent) {
+    self.record(event);
+
+    let now = event.time_ms;
+
+    match event.kind {
+      InputEventKind::ButtonPress(button) => self.handle_button_press(button, now),
+      InputEventKind::ButtonRelease(button) => self.handle_button_release(button, now),
+      InputEventKind::KeyPress(key) => self.handle_key_press(key, now),
+      InputEventKind::KeyRelease(key) => self.handle_key_release(key, now),
+      InputEventKind::MouseMove(x, y) => self.handle_mouse_move(x, y),
+      InputEventKind::Wheel(delta_y) => self.handle_wheel(delta_y, now),
+    }
+  }
+}
+
+pub fn input_handler(mut handler: InputHandler, source: Box<dyn InputSource>) {
+  let listen_result = source.listen(Box::new(move |event| handler.handle(event)));
+
+  if let Err(error) = listen_result {
+    eprintln!("{error}");
+    std::process::exit(1);
+  }
+}
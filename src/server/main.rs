@@ -5,18 +5,25 @@ use std::{
 };
 
 use crate::{
+  active_window::CommandWindowProvider,
+  arming_settings::ArmingSettings,
   event_observe::{notify_watch_observers, EventType},
+  input_event::InputRecorder,
+  input_settings::InputSettings,
+  input_source::{InputSource, InputSourceKind},
   ipc_tcp::start_tcp_server,
   server::{
     global_context::MainProcessState,
     global_context_installer::{install_state_from_file, InstallResult},
-    http::start_http_server,
+    http::{start_http_server, Senders},
   },
+  timing_settings::TimingSettings,
 };
 
 use super::{
-  automata_manager::manage_automata, mouse_events::mouse_handler,
-  results_command_exec::listen_results_execute_command,
+  automata_manager::manage_automata,
+  input_events::{input_handler, InputHandler},
+  results_command_exec::{listen_results_execute_command, MatchedCommand},
 };
 
 use std::sync::mpsc;
@@ -33,23 +40,72 @@ fn install(config_path: &str, state: &mut MainProcessState) {
   }
 }
 
+fn create_input_source(kind: &InputSourceKind) -> Box<dyn InputSource> {
+  kind.create().unwrap_or_else(|err| {
+    eprintln!("Cannot start input source: {err}");
+    std::process::exit(1);
+  })
+}
+
+fn create_recorder(record_path: &str) -> InputRecorder {
+  println!("Recording input to: {record_path}");
+
+  InputRecorder::create(record_path).unwrap_or_else(|err| {
+    eprintln!("Cannot record input: {err}");
+    std::process::exit(1);
+  })
+}
+
+/// What the server is started with (from the command line).
+pub struct ServerOptions<'a> {
+  pub port: u16,
+  pub config_path: &'a str,
+  pub interpreter: &'a str,
+  pub timing: TimingSettings,
+  pub input: InputSettings,
+  pub arming: ArmingSettings,
+  pub calibration_path: &'a str,
+  /// Where the raw input events are written, if anywhere.
+  pub record_path: Option<&'a str>,
+  pub input_source: &'a InputSourceKind,
+  pub window_command: &'a str,
+}
+
+fn create_state(options: &ServerOptions) -> MainProcessState {
+  let mut state = MainProcessState::new(
+    options.interpreter,
+    options.timing,
+    options.input,
+    options.arming.clone(),
+    Some(options.calibration_path),
+  );
+  state.window_provider = Arc::new(CommandWindowProvider::new(options.window_command));
+
+  install(options.config_path, &mut state);
+  state
+}
+
 #[allow(clippy::too_many_lines)]
-pub fn start(port: u16, config_path: &str, interpreter: &str) {
+pub fn start(options: &ServerOptions) {
   let (sequence_sender, sequence_rec) = mpsc::channel();
-  let (results_sender, results_rec) = mpsc::channel::<usize>();
+  let (results_sender, results_rec) = mpsc::channel::<Vec<MatchedCommand>>();
   let sequence_sender_clone = sequence_sender.clone();
 
-  let mut state = MainProcessState::new(interpreter);
+  let state = create_state(options);
 
-  install(config_path, &mut state);
+  let recorder = options.record_path.map(create_recorder);
+  let source = create_input_source(options.input_source);
 
   let main_process_state = Arc::new(Mutex::new(state));
 
+  let handler = InputHandler::new(sequence_sender, Arc::clone(&main_process_state), recorder);
+
   let (events_sender, events_rec) = mpsc::channel::<EventType>();
 
   let observers: Mutex<HashMap<u16, TcpStream>> = Mutex::new(HashMap::new());
 
   let tcp_listener = TcpListener::bind("0.0.0.0:0").unwrap();
+  let events_sender_clone = events_sender.clone();
 
   std::thread::scope(|scope| {
     scope.spawn(|| listen_results_execute_command(results_rec, &main_process_state));
@@ -57,20 +113,24 @@ pub fn start(port: u16, config_path: &str, interpreter: &str) {
     scope.spawn(|| start_tcp_server(&tcp_listener, &observers));
 
     scope.spawn(|| {
+      let receiver = sequence_rec;
       manage_automata(
         &results_sender,
-        sequence_rec,
+        &receiver,
         &events_sender,
         &main_process_state,
       );
     });
-    scope.spawn(|| mouse_handler(sequence_sender));
+    scope.spawn(|| input_handler(handler, source));
     scope.spawn(|| {
       start_http_server(
-        port,
+        options.port,
         tcp_listener.local_addr().unwrap().port(),
-        config_path,
-        sequence_sender_clone,
+        options.config_path,
+        Senders {
+          sequence: sequence_sender_clone,
+          events: events_sender_clone,
+        },
         Arc::clone(&main_process_state),
       );
     });
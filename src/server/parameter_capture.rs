@@ -0,0 +1,118 @@
+use crate::{cmd::Parameter, sequence_automata::AutomataInstruction};
+
+/// A command to execute, with the argument decoded from its parameter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandMatch {
+  pub id: usize,
+  pub argument: Option<String>,
+}
+
+impl CommandMatch {
+  pub const fn new(id: usize) -> Self {
+    Self { id, argument: None }
+  }
+}
+
+/// Collects the symbols clicked after a sequence with a parameter, until the
+/// sequence ends.
+pub struct ParameterCapture {
+  commands: Vec<(usize, Parameter)>,
+  symbols: String,
+  holding: bool,
+}
+
+impl ParameterCapture {
+  pub const fn new(commands: Vec<(usize, Parameter)>) -> Self {
+    Self {
+      commands,
+      symbols: String::new(),
+      holding: false,
+    }
+  }
+
+  /// Whether the sequence ends if nothing else comes (i.e. no button is being
+  /// pressed).
+  pub const fn is_waiting(&self) -> bool {
+    !self.holding
+  }
+
+  /// Returns whether the sequence ended.
+  pub fn put(&mut self, instruction: AutomataInstruction) -> bool {
+    match instruction {
+      AutomataInstruction::Char(c) => {
+        self.symbols.push(c);
+        self.holding = false;
+      }
+      AutomataInstruction::Press => self.holding = true,
+      AutomataInstruction::Reset => return true,
+      AutomataInstruction::Arm
+      | AutomataInstruction::Disarm
+      | AutomataInstruction::SentStart
+      | AutomataInstruction::SentEnd
+      | AutomataInstruction::Wake => {}
+    }
+
+    false
+  }
+
+  /// Commands whose parameter could be decoded.
+  pub fn finish(self) -> Vec<CommandMatch> {
+    let symbols = self.symbols;
+
+    self
+      .commands
+      .into_iter()
+      .filter_map(|(id, parameter)| {
+        let argument = parameter.decode(&symbols);
+
+        if argument.is_none() {
+          eprintln!("Cannot read the {} parameter `{symbols}`", parameter.tag());
+        }
+
+        Some(CommandMatch {
+          id,
+          argument: Some(argument?),
+        })
+      })
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn capture(symbols: &str) -> Vec<CommandMatch> {
+    let mut capture = ParameterCapture::new(vec![(0, Parameter::Number), (3, Parameter::Symbols)]);
+
+    for c in symbols.chars() {
+      assert!(!capture.put(AutomataInstruction::Press));
+      assert!(!capture.is_waiting());
+      assert!(!capture.put(AutomataInstruction::Char(c)));
+      assert!(capture.is_waiting());
+    }
+
+    assert!(capture.put(AutomataInstruction::Reset));
+    capture.finish()
+  }
+
+  #[test]
+  fn test_capture() {
+    let matches = capture("..---");
+    assert_eq!(matches.len(), 2);
+    assert_eq!(matches[0].argument.as_deref(), Some("2"));
+    assert_eq!(matches[1].argument.as_deref(), Some("..---"));
+  }
+
+  #[test]
+  fn test_capture_invalid_number() {
+    let matches = capture(".-");
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].id, 3);
+  }
+
+  #[test]
+  fn test_capture_nothing() {
+    assert!(capture("").is_empty());
+  }
+}
@@ -5,11 +5,16 @@ use std::io::Write;
 use std::sync::{Arc, Mutex};
 use std::{
   io::BufReader,
+  os::unix::process::CommandExt,
+  path::Path,
   process::{Child, Command, ExitStatus, Stdio},
+  time::{Duration, Instant},
 };
 use tempfile::NamedTempFile;
 
 use crate::{
+  cmd::Cmd,
+  command_options::CommandOptions,
   logger::{log_stdout, redirect_output},
   util::seconds_elapsed,
 };
@@ -60,18 +65,54 @@ fn format_exit_status(exit_status: ExitStatus) -> String {
   }
 }
 
-fn create_child(interpreter: &str, pre_script: &str, cmd: &str) -> Result<Child> {
-  let full_command = format!("{pre_script}\n{cmd}\n");
+/// How often a process with a timeout is checked.
+const TIMEOUT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The argument is given as `$1`, and as the `HORSETAB_ARG` variable. The
+/// process gets its own group, so that a timeout kills what it started too.
+fn interpreter_command(
+  interpreter: &str,
+  script: &Path,
+  options: &CommandOptions,
+  argument: Option<&str>,
+) -> Command {
+  let mut command = Command::new(interpreter);
+  command
+    .arg(script)
+    .envs(options.env.iter().cloned())
+    .process_group(0);
+
+  if let Some(cwd) = options.working_dir() {
+    command.current_dir(cwd);
+  }
+
+  if let Some(arg) = argument {
+    command.arg(arg).env("HORSETAB_ARG", arg);
+  }
+
+  command
+}
+
+/// The script before the commands is written for the default interpreter, so
+/// it's left out when the command has its own.
+fn create_child(
+  interpreter: &str,
+  pre_script: &str,
+  cmd: &Cmd,
+  argument: Option<&str>,
+) -> Result<Child> {
+  let shell = cmd.options.interpreter.as_deref().unwrap_or(interpreter);
+  let script = if shell == interpreter { pre_script } else { "" };
+  let full_command = format!("{script}\n{}\n", cmd.command);
 
   let file = Arc::new(Mutex::new(NamedTempFile::new()?));
   write!(file.lock().unwrap(), "{full_command}").unwrap();
 
-  let child = Command::new(interpreter)
-    .arg(file.lock().unwrap().path())
+  let child = interpreter_command(shell, file.lock().unwrap().path(), &cmd.options, argument)
     .stdout(Stdio::piped())
     .stderr(Stdio::piped())
     .spawn()
-    .with_context(|| format!("({interpreter}) Cannot execute:\n{full_command}"));
+    .with_context(|| format!("({shell}) Cannot execute:\n{full_command}"));
 
   std::thread::spawn(|| {
     std::thread::sleep(std::time::Duration::from_secs(10));
@@ -81,13 +122,52 @@ fn create_child(interpreter: &str, pre_script: &str, cmd: &str) -> Result<Child>
   child
 }
 
+fn poll_until(child: &mut Child, deadline: Instant) -> Option<ExitStatus> {
+  while Instant::now() < deadline {
+    if let Some(status) = child.try_wait().expect("Should check child") {
+      return Some(status);
+    }
+    std::thread::sleep(TIMEOUT_POLL_INTERVAL);
+  }
+
+  None
+}
+
+/// Kills the whole process group (the interpreter and what it started).
+fn kill_group(child: &mut Child) {
+  let killed = Command::new("kill")
+    .args(["-KILL", "--", &format!("-{}", child.id())])
+    .status()
+    .is_ok_and(|status| status.success());
+
+  if !killed {
+    child.kill().ok();
+  }
+}
+
+/// Kills the process if it's still running after the timeout.
+fn wait_child(child: &mut Child, timeout: Option<Duration>) -> ExitStatus {
+  if let Some(limit) = timeout {
+    if poll_until(child, Instant::now() + limit).is_none() {
+      log_stdout(
+        child.id(),
+        &format!("Timed out after {}s", limit.as_secs_f64()),
+      );
+      kill_group(child);
+    }
+  }
+
+  child.wait().expect("Should wait child")
+}
+
 fn handle_child_exit(
   mut child: Child,
+  timeout: Option<Duration>,
   process_map: &Mutex<HashMap<u32, Process>>,
   start_time: DateTime<Local>,
 ) {
   let pid = child.id();
-  let status = child.wait().expect("Should wait child");
+  let status = wait_child(&mut child, timeout);
   let end_time = Local::now();
 
   if let Some(process) = process_map.lock().unwrap().get_mut(&pid) {
@@ -108,6 +188,7 @@ fn handle_child_exit(
 
 fn handle_child(
   mut child: Child,
+  timeout: Option<Duration>,
   start_time: DateTime<Local>,
   initial_cmd: &str,
   process_map: &Mutex<HashMap<u32, Process>>,
@@ -121,24 +202,45 @@ fn handle_child(
   std::thread::scope(|scope| {
     scope.spawn(|| redirect_output(BufReader::new(stdout), pid, true));
     scope.spawn(|| redirect_output(BufReader::new(stderr), pid, false));
-    scope.spawn(|| handle_child_exit(child, process_map, start_time));
+    scope.spawn(|| handle_child_exit(child, timeout, process_map, start_time));
   });
 }
 
+/// Commands that continue on several lines are shown on one.
+fn single_line(command: &str) -> String {
+  command
+    .lines()
+    .map(|line| line.trim().trim_end_matches('\\').trim_end())
+    .filter(|line| !line.is_empty())
+    .collect::<Vec<&str>>()
+    .join(" ")
+}
+
+/// Named commands are shown by their name.
+fn label(cmd: &Cmd, argument: Option<&str>) -> String {
+  let text = cmd
+    .name()
+    .map_or_else(|| single_line(&cmd.command), str::to_owned);
+  argument.map_or_else(|| text.clone(), |arg| format!("{text} (argument: {arg})"))
+}
+
 fn spawn_process(
   interpreter: &str,
   pre_script: &str,
-  cmd: String,
+  cmd: &Cmd,
+  argument: Option<&str>,
   process_map: Arc<Mutex<HashMap<u32, Process>>>,
 ) -> Result<Process> {
-  let child = create_child(interpreter, pre_script, &cmd)?;
+  let child = create_child(interpreter, pre_script, cmd, argument)?;
 
   let pid = child.id();
+  let label = label(cmd, argument);
 
-  let process = Process::new(pid, &cmd);
+  let process = Process::new(pid, &label);
+  let timeout = cmd.options.timeout;
 
   std::thread::spawn(move || {
-    handle_child(child, process.start_time, &cmd, &process_map);
+    handle_child(child, timeout, process.start_time, &label, &process_map);
     std::thread::sleep(std::time::Duration::from_secs(5));
     process_map.lock().unwrap().remove(&pid);
   });
@@ -179,10 +281,16 @@ impl ProcessManager {
       .join("\n")
   }
 
-  pub fn start(&self, interpreter: &str, pre_script: &str, cmd: &str) -> Result<u32> {
+  pub fn start(
+    &self,
+    interpreter: &str,
+    pre_script: &str,
+    cmd: &Cmd,
+    argument: Option<&str>,
+  ) -> Result<u32> {
     let process_map = Arc::clone(&self.process_map);
 
-    let process = spawn_process(interpreter, pre_script, cmd.into(), process_map)?;
+    let process = spawn_process(interpreter, pre_script, cmd, argument, process_map)?;
 
     let pid = process.pid;
 
@@ -191,3 +299,37 @@ impl ProcessManager {
     Ok(pid)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::{BufRead, BufReader};
+
+  /// Zombies are dead too (they are waiting to be reaped).
+  fn is_running(pid: &str) -> bool {
+    std::fs::read_to_string(format!("/proc/{pid}/stat")).is_ok_and(|stat| {
+      stat
+        .split_whitespace()
+        .nth(2)
+        .is_some_and(|state| state != "Z")
+    })
+  }
+
+  #[test]
+  fn test_timeout_kills_the_process_group() {
+    let mut child = Command::new("sh")
+      .args(["-c", "sleep 30 & echo $!; wait"])
+      .stdout(Stdio::piped())
+      .process_group(0)
+      .spawn()
+      .unwrap();
+    let mut pid = String::new();
+    BufReader::new(child.stdout.take().unwrap())
+      .read_line(&mut pid)
+      .unwrap();
+
+    wait_child(&mut child, Some(Duration::from_millis(200)));
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(!is_running(pid.trim()));
+  }
+}
@@ -0,0 +1,414 @@
+use anyhow::{bail, Result};
+use std::{
+  sync::{
+    mpsc::{self, Receiver, Sender},
+    Arc, Mutex,
+  },
+  time::{Duration, Instant},
+};
+
+use super::{
+  automata_manager::{process_instruction, switch_mode_after},
+  global_context::MainProcessState,
+  global_context_installer::{install_state_from_file, InstallResult},
+  input_events::InputHandler,
+  parameter_capture::CommandMatch,
+  results_command_exec::{pick_commands, with_commands},
+};
+use crate::{
+  arming_settings::ArmingSettings,
+  cmd::Cmd,
+  constants::DEFAULT_INTERPRETER,
+  event_observe::EventType,
+  input_event::{read_recording, InputEvent},
+  input_settings::InputSettings,
+  sequence_automata::AutomataInstruction,
+  timing_settings::TimingSettings,
+};
+
+/// Replay output: one line per sequence, followed by the matched commands.
+#[derive(Default)]
+struct ReplayPrinter {
+  line: String,
+  lines: Vec<String>,
+}
+
+impl ReplayPrinter {
+  fn end_line(&mut self) {
+    if !self.line.is_empty() {
+      self.lines.push(std::mem::take(&mut self.line));
+    }
+  }
+
+  fn own_line(&mut self, text: String) {
+    self.end_line();
+    self.lines.push(text);
+  }
+
+  fn event(&mut self, event: EventType) {
+    match event {
+      EventType::SequenceItem(c) => self.line.push(c),
+      EventType::SequenceReset => self.end_line(),
+      EventType::FoundResults | EventType::FoundNamedResults(_) => {}
+      EventType::Armed => self.own_line("Armed".to_owned()),
+      EventType::Disarmed => self.own_line("Disarmed".to_owned()),
+      EventType::NearMiss(sequences) => self.own_line(format!("  ? {}", sequences.join(" or "))),
+      EventType::ModeChanged(mode) => self.own_line(format!("Mode: {mode}")),
+    }
+  }
+
+  fn matched(&mut self, commands: &[String]) {
+    self.line.push_str("  -> ");
+    self.line.push_str(&commands.join("; "));
+    self.end_line();
+  }
+
+  fn finish(mut self) -> String {
+    self.end_line();
+    self.lines.join("\n")
+  }
+}
+
+fn format_match(command: &str, argument: Option<&str>) -> String {
+  argument.map_or_else(
+    || command.to_owned(),
+    |arg| format!("{command} (argument: {arg})"),
+  )
+}
+
+struct Replay {
+  state: Arc<Mutex<MainProcessState>>,
+  handler: InputHandler,
+  sequence_rec: Receiver<AutomataInstruction>,
+  events: (Sender<EventType>, Receiver<EventType>),
+  printer: ReplayPrinter,
+  /// Where the recording starts (its times are relative to it), so that
+  /// timeouts are measured with the recorded times.
+  start: Instant,
+  last_ms: Option<u128>,
+}
+
+impl Replay {
+  fn new(state: MainProcessState) -> Self {
+    let shared_state = Arc::new(Mutex::new(state));
+    let (sequence_sender, sequence_rec) = mpsc::channel();
+
+    Self {
+      handler: InputHandler::new(sequence_sender, Arc::clone(&shared_state), None),
+      state: shared_state,
+      sequence_rec,
+      events: mpsc::channel(),
+      printer: ReplayPrinter::default(),
+      start: Instant::now(),
+      last_ms: None,
+    }
+  }
+
+  fn print_events(&mut self) {
+    for event in self.events.1.try_iter() {
+      self.printer.event(event);
+    }
+  }
+
+  fn time(&self, time_ms: u128) -> Instant {
+    self.start + Duration::from_millis(u64::try_from(time_ms).unwrap_or(u64::MAX))
+  }
+
+  /// The window isn't known, so commands that depend on it are picked as when
+  /// it can't be found. Modes are switched after the matched commands are
+  /// printed.
+  fn matched(&mut self, state: &mut MainProcessState, results: Vec<CommandMatch>, now: Instant) {
+    let picked = pick_commands(with_commands(state, results), || None);
+    let lines: Vec<String> = (picked.iter())
+      .map(|(r, cmd)| format_match(&cmd.command, r.argument.as_deref()))
+      .collect();
+    let commands: Vec<Cmd> = picked.into_iter().map(|(_, cmd)| cmd).collect();
+    self.printer.matched(&lines);
+    switch_mode_after(&commands, state, &self.events.0, now);
+    self.print_events();
+  }
+
+  /// Without an instruction, the idle timeout elapsed.
+  fn instruction(&mut self, instruction: Option<AutomataInstruction>, time_ms: u128) {
+    let now = self.time(time_ms);
+    let state = Arc::clone(&self.state);
+    let mut guard = state.lock().unwrap();
+    let results = process_instruction(instruction, now, &mut guard, &self.events.0);
+    self.print_events();
+
+    if let Some(matches) = results {
+      self.matched(&mut guard, matches, now);
+    }
+  }
+
+  /// When the server would end the sequence, after the last event.
+  fn idle_ms(&self) -> u128 {
+    let reset_ms = self
+      .state
+      .lock()
+      .unwrap()
+      .effective_timing()
+      .sequence_reset_ms;
+    self.last_ms.unwrap_or_default() + reset_ms
+  }
+
+  /// The server ends a waiting sequence when there are no instructions for a
+  /// while, so the same is done when there's a pause between events.
+  fn is_idle(&self, now: u128) -> bool {
+    let elapsed_ms = now.saturating_sub(self.last_ms.unwrap_or(now));
+    let state = self.state.lock().unwrap();
+    state.is_waiting() && elapsed_ms > state.effective_timing().sequence_reset_ms
+  }
+
+  fn event(&mut self, event: InputEvent) {
+    if self.is_idle(event.time_ms) {
+      self.instruction(None, self.idle_ms());
+    }
+
+    self.last_ms = Some(event.time_ms);
+    self.handler.handle(event);
+
+    while let Ok(instruction) = self.sequence_rec.try_recv() {
+      self.instruction(Some(instruction), event.time_ms);
+    }
+  }
+}
+
+/// Pushes the events through the same handlers the server uses, without
+/// executing commands.
+fn replay_events(events: &[InputEvent], state: MainProcessState) -> String {
+  let mut replay = Replay::new(state);
+
+  for event in events {
+    replay.event(*event);
+  }
+
+  replay.instruction(None, replay.idle_ms());
+  replay.printer.finish()
+}
+
+pub fn replay(
+  recording_path: &str,
+  config_path: &str,
+  timing: TimingSettings,
+  input: InputSettings,
+  arming: ArmingSettings,
+) -> Result<String> {
+  let events = read_recording(recording_path)?;
+  let mut state = MainProcessState::new(DEFAULT_INTERPRETER, timing, input, arming, None);
+
+  if let InstallResult::FileError(err) = install_state_from_file(config_path, &mut state) {
+    bail!("Cannot read config file: {err}");
+  }
+
+  Ok(replay_events(&events, state))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    input_event::parse_events,
+    server::{config_file_parser::Configuration, global_context_installer::assign_global_state},
+  };
+
+  fn state_with_config(
+    config: &[&str],
+    timing: TimingSettings,
+    input: InputSettings,
+  ) -> MainProcessState {
+    let mut state = MainProcessState::new(
+      DEFAULT_INTERPRETER,
+      timing,
+      input,
+      ArmingSettings::default(),
+      None,
+    );
+    let lines: Vec<String> = config.iter().map(|l| (*l).to_owned()).collect();
+    assign_global_state(Configuration::from_lines(&lines), &mut state);
+    state
+  }
+
+  fn run_with(
+    config: &[&str],
+    recording: &str,
+    timing: TimingSettings,
+    input: InputSettings,
+  ) -> String {
+    let events = parse_events(recording.lines()).unwrap();
+    replay_events(&events, state_with_config(config, timing, input))
+  }
+
+  fn left_clicks(times: &[(u128, u128)]) -> String {
+    let lines: Vec<String> = times
+      .iter()
+      .map(|(press, release)| format!("{press} press left\n{release} release left"))
+      .collect();
+    lines.join("\n")
+  }
+
+  fn run(config: &[&str], recording: &str) -> String {
+    run_with(
+      config,
+      recording,
+      TimingSettings::default(),
+      InputSettings::default(),
+    )
+  }
+
+  #[test]
+  fn test_replay_short_and_long() {
+    let recording = "
+      0 press left
+      100 release left
+      200 press left
+      500 release left";
+    assert_eq!(run(&[".- echo a"], recording), ".-  -> echo a");
+  }
+
+  #[test]
+  fn test_replay_pause_resets_sequence() {
+    let recording = "
+      0 press left
+      100 release left
+      1000 press left
+      1100 release left
+      1200 press left
+      1500 release left";
+    assert_eq!(run(&[".- echo a"], recording), ".\n.-  -> echo a");
+  }
+
+  #[test]
+  fn test_replay_unknown_keys_are_ignored() {
+    let recording = "0 key-press Pause\n400 key-release Pause";
+    assert_eq!(run(&[".- echo a"], recording), "");
+  }
+
+  #[test]
+  fn test_replay_gesture() {
+    let input = InputSettings {
+      gestures: true,
+      ..InputSettings::default()
+    };
+    let recording = "
+      0 move 500 500
+      0 press left
+      50 move 500 700
+      100 move 800 700
+      150 release left";
+    let output = run_with(&["v> echo g"], recording, TimingSettings::default(), input);
+    assert_eq!(output, "v>  -> echo g");
+  }
+
+  #[test]
+  fn test_replay_wait_for_longer() {
+    let timing = TimingSettings {
+      wait_for_longer: true,
+      ..TimingSettings::default()
+    };
+    let recording = left_clicks(&[
+      (0, 100),
+      (200, 300),
+      (2000, 2100),
+      (2200, 2300),
+      (2400, 2500),
+    ]);
+    let config = [".. echo short", "... echo long"];
+    let output = run_with(&config, &recording, timing, InputSettings::default());
+    assert_eq!(output, "..  -> echo short\n...  -> echo long");
+  }
+
+  #[test]
+  fn test_replay_match_anywhere() {
+    let recording = left_clicks(&[(0, 300), (400, 500), (600, 900)]);
+    assert_eq!(run(&[".- echo a"], &recording), "-.-");
+    let config = ["HORSETAB_MATCH_ANYWHERE=1", ".- echo a"];
+    assert_eq!(run(&config, &recording), "-.-  -> echo a");
+  }
+
+  #[test]
+  fn test_replay_fuzzy() {
+    // `..-` then `.-`.
+    let recording = left_clicks(&[(0, 100), (200, 300), (400, 700), (2000, 2100), (2200, 2500)]);
+    let ambiguous = ["HORSETAB_FUZZY_DISTANCE=1", "..-. echo a", "..-- echo b"];
+    assert_eq!(run(&ambiguous, &recording), "..-\n  ? ..-. or ..--\n.-");
+    let unique = ["HORSETAB_FUZZY_DISTANCE=1", "..-. echo a", "-- echo b"];
+    assert_eq!(run(&unique, &recording), "..-\n  -> echo a\n.-");
+  }
+
+  #[test]
+  fn test_replay_parameter() {
+    // `..` then `...--` (3).
+    let recording = left_clicks(&[
+      (0, 100),
+      (200, 300),
+      (400, 500),
+      (600, 700),
+      (800, 900),
+      (1000, 1300),
+      (1400, 1700),
+    ]);
+    let output = run(&[".. #n echo $1"], &recording);
+    assert_eq!(output, ".....--  -> echo $1 (argument: 3)");
+  }
+
+  #[test]
+  fn test_replay_modes() {
+    // `..-` twice.
+    let clicks = [(0, 100), (200, 300), (400, 700)];
+    let later = clicks.map(|(press, release)| (press + 2000, release + 2000));
+    let recording = left_clicks(&[clicks, later].concat());
+    let config = [
+      "..- [switch=b] echo a",
+      "[mode b]",
+      "..- [switch=default] echo b",
+    ];
+    assert_eq!(
+      run(&config, &recording),
+      "..-  -> echo a\nMode: b\n..-  -> echo b\nMode: default"
+    );
+  }
+
+  #[test]
+  fn test_replay_picks_commands_without_a_window() {
+    let recording = left_clicks(&[(0, 100), (200, 500)]);
+    let config = [".- [class=firefox] echo firefox", ".- echo other"];
+    assert_eq!(run(&config, &recording), ".-  -> echo other");
+  }
+
+  #[test]
+  fn test_replay_mode_timeout_uses_recorded_times() {
+    // `..-`, then `.-` (in the mode), and `.-` again after the timeout.
+    let in_mode = [(1500, 1600), (1700, 2000), (5000, 5100), (5200, 5500)];
+    let recording = left_clicks(&[&[(0, 100), (200, 300), (400, 700)], &in_mode[..]].concat());
+    let config = [
+      "..- [switch=b] echo a",
+      ".- echo default",
+      "[mode b timeout=2s]",
+      ".- echo b",
+    ];
+    assert_eq!(
+      run(&config, &recording),
+      "..-  -> echo a\nMode: b\n.-  -> echo b\nMode: default\n.-  -> echo default"
+    );
+  }
+
+  #[test]
+  fn test_replay_arm_timeout_uses_recorded_times() {
+    // `..` arms, then `.-` in time, and `.-` again after the timeout.
+    let recording = left_clicks(&[
+      (0, 100),
+      (200, 300),
+      (1000, 1100),
+      (1200, 1500),
+      (9000, 9100),
+      (9200, 9500),
+    ]);
+    let config = [
+      "HORSETAB_ARM_SEQUENCE=..",
+      "HORSETAB_ARM_TIMEOUT_MS=3000",
+      ".- echo a",
+    ];
+    assert_eq!(run(&config, &recording), "..\nArmed\n.-  -> echo a\n.-");
+  }
+}
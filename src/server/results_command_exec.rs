@@ -1,23 +1,178 @@
 use super::global_context::MainProcessState;
+use super::parameter_capture::CommandMatch;
+use crate::{
+  active_window::{ActiveWindow, WindowProvider},
+  cmd::Cmd,
+};
+use anyhow::{bail, Result};
 use std::sync::mpsc::Receiver;
 use std::sync::Mutex;
 
+/// A match, with a copy of the command it matched (so that it runs even if
+/// the configuration is installed again in between).
+pub type MatchedCommand = (CommandMatch, Cmd);
+
+/// Whether the command can run in this window (without knowing the window,
+/// only commands without a condition can).
+fn can_run_in(cmd: &Cmd, window: Option<&ActiveWindow>) -> bool {
+  let options = &cmd.options;
+  window.map_or_else(
+    || !options.has_window_condition(),
+    |w| options.matches_window(w),
+  )
+}
+
+/// Among the commands with the same sequence, only the first one that can run
+/// is picked when some of them depend on the window.
+fn is_picked(commands: &[&Cmd], index: usize, window: Option<&ActiveWindow>) -> bool {
+  let sequence = &commands[index].sequence;
+  let group = || (commands.iter().enumerate()).filter(|(_, c)| c.sequence == *sequence);
+  let first = group().find(|(_, c)| can_run_in(c, window)).map(|(i, _)| i);
+
+  !group().any(|(_, c)| c.options.has_window_condition()) || first == Some(index)
+}
+
+/// When some of the matched commands with the same sequence depend on the
+/// window, only the first one whose condition matches runs (a command without
+/// one always matches). Otherwise they all run. The window is only asked for
+/// when a condition has to be evaluated.
+pub fn pick_commands(
+  matched: Vec<MatchedCommand>,
+  window: impl FnOnce() -> Option<ActiveWindow>,
+) -> Vec<MatchedCommand> {
+  let commands: Vec<&Cmd> = matched.iter().map(|(_, cmd)| cmd).collect();
+  let has_condition = commands.iter().any(|c| c.options.has_window_condition());
+  let active = has_condition.then(window).flatten();
+  let picked: Vec<bool> = (0..commands.len())
+    .map(|i| is_picked(&commands, i, active.as_ref()))
+    .collect();
+
+  (matched.into_iter().zip(picked))
+    .filter_map(|(pair, keep)| keep.then_some(pair))
+    .collect()
+}
+
+/// The commands are copied while the state is locked, along with the matches
+/// (whose ids are only valid until the next installation).
+pub fn with_commands(state: &MainProcessState, results: Vec<CommandMatch>) -> Vec<MatchedCommand> {
+  (results.into_iter())
+    .map(|result| {
+      let cmd = state.commands[result.id].clone();
+      (result, cmd)
+    })
+    .collect()
+}
+
+/// The window is looked up (which runs an external command) without holding
+/// the lock.
+pub fn pick_matches(
+  matched: Vec<MatchedCommand>,
+  provider: &dyn WindowProvider,
+) -> Vec<MatchedCommand> {
+  pick_commands(matched, || {
+    provider
+      .active_window()
+      .inspect_err(|e| eprintln!("{e}"))
+      .ok()
+  })
+}
+
+fn start_command(state: &MainProcessState, result: &CommandMatch, cmd: &Cmd) {
+  let start_result = state.process_manager.start(
+    &state.interpreter,
+    &state.pre_script,
+    cmd,
+    result.argument.as_deref(),
+  );
+
+  if let Err(e) = start_result {
+    eprintln!("{e}");
+  }
+}
+
 pub fn listen_results_execute_command(
-  results_rec: Receiver<usize>,
+  results_rec: Receiver<Vec<MatchedCommand>>,
   state: &Mutex<MainProcessState>,
 ) {
-  for result_id in results_rec {
+  for results in results_rec {
     let state_guard = state.lock().unwrap();
 
-    let cmd = state_guard.commands[result_id].command.clone();
+    for (result, cmd) in &results {
+      start_command(&state_guard, result, cmd);
+    }
+  }
+}
 
-    let start_result =
-      state_guard
+/// Starts the commands with this name, and returns their PIDs.
+pub fn start_named(
+  state: &MainProcessState,
+  name: &str,
+  argument: Option<&str>,
+) -> Result<Vec<u32>> {
+  let commands = state.commands.iter().filter(|cmd| cmd.name() == Some(name));
+  let pids = commands
+    .map(|cmd| {
+      state
         .process_manager
-        .start(&state_guard.interpreter, &state_guard.pre_script, &cmd);
+        .start(&state.interpreter, &state.pre_script, cmd, argument)
+    })
+    .collect::<Result<Vec<u32>>>()?;
 
-    if let Err(e) = start_result {
-      eprintln!("{e}");
-    }
+  if pids.is_empty() {
+    bail!("No command named {name}");
+  }
+
+  Ok(pids)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::cmd::parse_commands;
+  use std::cell::Cell;
+  use test_case::test_case;
+
+  const COMMANDS: &str = "
+.- [class=firefox] echo firefox
+.- [title=vim] echo vim
+.- echo other
+-. echo a
+-. echo b
+";
+
+  fn matched(ids: &[usize]) -> Vec<MatchedCommand> {
+    let commands = parse_commands(COMMANDS);
+    (ids.iter())
+      .map(|id| (CommandMatch::new(*id), commands[*id].clone()))
+      .collect()
+  }
+
+  #[test_case(&[0, 1, 2], Some("Firefox") => vec![0]; "first condition")]
+  #[test_case(&[0, 1, 2], Some("xterm") => vec![1]; "title condition")]
+  #[test_case(&[0, 2], Some("xterm") => vec![2]; "unconditional fallback")]
+  #[test_case(&[0, 1, 2], None => vec![2]; "no window")]
+  #[test_case(&[0], Some("xterm") => Vec::<usize>::new(); "nothing matches")]
+  #[test_case(&[3, 4, 0, 2], Some("firefox") => vec![3, 4, 0]; "groups")]
+  fn test_pick_commands(ids: &[usize], class: Option<&str>) -> Vec<usize> {
+    let window = || {
+      class.map(|c| ActiveWindow {
+        class: c.to_owned(),
+        title: "notes - VIM".to_owned(),
+      })
+    };
+
+    let picked = pick_commands(matched(ids), window);
+    picked.into_iter().map(|(r, _)| r.id).collect()
+  }
+
+  #[test]
+  fn test_pick_commands_without_conditions() {
+    let asked = Cell::new(false);
+    let window = || {
+      asked.set(true);
+      None
+    };
+    assert_eq!(pick_commands(matched(&[3, 4]), window), matched(&[3, 4]));
+    assert!(!asked.get());
   }
 }
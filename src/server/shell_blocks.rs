@@ -0,0 +1,411 @@
+use std::{cell::OnceCell, collections::VecDeque, fmt::Display, sync::Mutex};
+
+use regex::Regex;
+
+use crate::cmd::{continues, Cmd};
+
+/// Replaces a quoted or escaped character that means something to the shell.
+const QUOTED: char = '_';
+
+/// Quotes and backslashes are removed from the shape of a line.
+const DROPPED: char = '\0';
+
+/// Characters that mean something to the shell when they aren't quoted.
+const SPECIAL: &str = ";&|()<>#";
+
+/// Words after which another command starts.
+const RESERVED: [&str; 6] = ["then", "do", "else", "elif", "!", "time"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Block {
+  If,
+  Case,
+  Loop,
+  Braces,
+  Function,
+  Subshell,
+  Arithmetic,
+}
+
+impl Block {
+  /// The block a word opens, at the start of a command.
+  fn opened_by(word: &str, function: bool) -> Option<Self> {
+    match word {
+      "if" => Some(Self::If),
+      "case" => Some(Self::Case),
+      "for" | "while" | "until" | "select" => Some(Self::Loop),
+      "{" if function => Some(Self::Function),
+      "{" => Some(Self::Braces),
+      _ => None,
+    }
+  }
+
+  const fn closed_by(self) -> &'static str {
+    match self {
+      Self::If => "fi",
+      Self::Case => "esac",
+      Self::Loop => "done",
+      Self::Braces | Self::Function => "}",
+      Self::Subshell => ")",
+      Self::Arithmetic => "))",
+    }
+  }
+}
+
+impl Display for Block {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let text = match self {
+      Self::If => "an if block",
+      Self::Case => "a case block",
+      Self::Loop => "a loop",
+      Self::Braces => "a { } block",
+      Self::Function => "a function",
+      Self::Subshell => "a subshell",
+      Self::Arithmetic => "an arithmetic expression",
+    };
+
+    write!(f, "{text}")
+  }
+}
+
+/// What a line of the configuration file is, given the shell code before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Placement {
+  Script,
+  /// A command, with the innermost block it's written in (it's defined
+  /// anyway).
+  Command(Option<Block>),
+  /// The rest of a command whose line ends with `\`.
+  Continuation,
+  /// Part of a heredoc, or of a quoted string with several lines.
+  Text,
+}
+
+struct Heredoc {
+  delimiter: String,
+  /// `<<-` ignores the tabs before the delimiter.
+  strip_tabs: bool,
+  /// The line it starts on.
+  line: usize,
+}
+
+impl Heredoc {
+  fn ends_at(&self, line: &str) -> bool {
+    let stripped = if self.strip_tabs {
+      line.trim_start_matches('\t')
+    } else {
+      line
+    };
+
+    stripped == self.delimiter
+  }
+}
+
+/// Follows the shell code well enough to tell which blocks, heredocs or
+/// quoted strings each line is in. It doesn't need to be exact, only to
+/// handle what is usually written in the file.
+#[derive(Default)]
+struct ShellScanner {
+  blocks: Vec<Block>,
+  heredocs: VecDeque<Heredoc>,
+  quote: Option<char>,
+  /// The line the open quote starts on.
+  quote_line: usize,
+  escaped: bool,
+  /// The command on the line before continues on the next one.
+  continued: bool,
+  /// The line being scanned (starting at 1).
+  line: usize,
+}
+
+static TOKEN_REGEX: Mutex<OnceCell<Regex>> = Mutex::new(OnceCell::new());
+
+/// Heredocs, parentheses, operators and words.
+fn tokens(shape: &str) -> Vec<String> {
+  let guard = TOKEN_REGEX.lock().unwrap();
+  let re = guard.get_or_init(|| {
+    Regex::new(r"<<-?\s*[^\s;&|()<>]*|\$?\(\(|\)\)|\$?\(|\)|;;|&&|\|\||[;&|]|[^\s;&|()<>]+")
+      .unwrap()
+  });
+
+  re.find_iter(shape).map(|m| m.as_str().to_owned()).collect()
+}
+
+fn is_operator(token: &str) -> bool {
+  token.starts_with([';', '&', '|'])
+}
+
+fn starts_word(previous: Option<char>) -> bool {
+  previous.is_none_or(|p| p.is_whitespace() || SPECIAL.contains(p))
+}
+
+impl ShellScanner {
+  fn unquoted_char(&mut self, c: char, previous: Option<char>) -> Option<char> {
+    match c {
+      '\'' | '"' | '`' => {
+        self.quote = Some(c);
+        self.quote_line = self.line;
+      }
+      '\\' => self.escaped = true,
+      '#' if starts_word(previous) => return None,
+      _ => return Some(c),
+    }
+
+    Some(DROPPED)
+  }
+
+  fn quoted_char(&mut self, quote: char, c: char) -> char {
+    if c == quote {
+      self.quote = None;
+      DROPPED
+    } else if c == '\\' && quote == '"' {
+      self.escaped = true;
+      DROPPED
+    } else if c.is_whitespace() || SPECIAL.contains(c) {
+      QUOTED
+    } else {
+      c
+    }
+  }
+
+  /// `None` when a comment starts.
+  fn shape_char(&mut self, c: char, previous: Option<char>) -> Option<char> {
+    if std::mem::take(&mut self.escaped) {
+      return Some(QUOTED);
+    }
+
+    match self.quote {
+      Some(quote) => Some(self.quoted_char(quote, c)),
+      None => self.unquoted_char(c, previous),
+    }
+  }
+
+  /// The line without quotes, escapes and comments, where quoted characters
+  /// don't mean anything to the shell.
+  fn shape(&mut self, line: &str) -> String {
+    let mut previous = None;
+    self.escaped = false;
+
+    line
+      .chars()
+      .map_while(|c| self.shape_char(c, previous.replace(c)))
+      .filter(|c| *c != DROPPED)
+      .collect()
+  }
+
+  fn close(&mut self, closer: &str) {
+    if self.blocks.last().is_some_and(|b| b.closed_by() == closer) {
+      self.blocks.pop();
+    }
+  }
+
+  /// `))` can also close two subshells.
+  fn close_parentheses(&mut self, token: &str) {
+    if token == "))" && self.blocks.last() != Some(&Block::Arithmetic) {
+      self.close(")");
+      self.close(")");
+    } else {
+      self.close(token);
+    }
+  }
+
+  /// `<<` in arithmetic is a shift.
+  fn add_heredoc(&mut self, operator: &str) {
+    let (strip_tabs, word) = operator
+      .strip_prefix('-')
+      .map_or((false, operator), |rest| (true, rest));
+    let delimiter = word.trim();
+
+    if !delimiter.is_empty() && self.blocks.last() != Some(&Block::Arithmetic) {
+      self.heredocs.push_back(Heredoc {
+        delimiter: delimiter.to_owned(),
+        strip_tabs,
+        line: self.line,
+      });
+    }
+  }
+
+  /// Returns whether another command starts after it.
+  fn put_word(&mut self, word: &str, function: bool) -> bool {
+    if let Some(block) = Block::opened_by(word, function) {
+      self.blocks.push(block);
+      return true;
+    }
+
+    self.close(word);
+    RESERVED.contains(&word)
+  }
+
+  /// Returns whether another command starts after it.
+  fn put_token(&mut self, token: &str, at_command: bool, function: bool) -> bool {
+    if let Some(operator) = token.strip_prefix("<<") {
+      self.add_heredoc(operator);
+      return false;
+    }
+
+    match token {
+      "((" | "$((" => self.blocks.push(Block::Arithmetic),
+      "(" | "$(" => self.blocks.push(Block::Subshell),
+      ")" | "))" => self.close_parentheses(token),
+      _ if is_operator(token) => {}
+      _ => return (at_command || function) && self.put_word(token, function),
+    }
+
+    true
+  }
+
+  fn scan(&mut self, line: &str) {
+    let shape = self.shape(line);
+    let mut at_command = true;
+    let mut previous: [&str; 2] = ["", ""];
+
+    for token in &tokens(&shape) {
+      let function = previous == ["(", ")"] || previous[0] == "function";
+      at_command = self.put_token(token, at_command, function);
+      previous = [previous[1], token];
+    }
+  }
+
+  fn in_heredoc(&mut self, line: &str) -> bool {
+    let Some(heredoc) = self.heredocs.front() else {
+      return false;
+    };
+
+    if heredoc.ends_at(line) {
+      self.heredocs.pop_front();
+    }
+
+    true
+  }
+
+  /// Heredocs and multi-line quoted strings.
+  fn in_text(&mut self, line: &str) -> bool {
+    if self.in_heredoc(line) {
+      return true;
+    }
+
+    let quoted = self.quote.is_some();
+    if quoted {
+      self.scan(line);
+    }
+
+    quoted
+  }
+
+  fn continue_command(&mut self, line: &str) -> bool {
+    let continued = self.continued;
+    self.continued = continued && continues(line);
+    continued
+  }
+
+  fn placement(&mut self, line: &str) -> Placement {
+    self.line += 1;
+
+    if self.continue_command(line) {
+      return Placement::Continuation;
+    }
+
+    if self.in_text(line) {
+      return Placement::Text;
+    }
+
+    if Cmd::parse(line).is_ok() {
+      self.continued = continues(line);
+      return Placement::Command(self.blocks.last().copied());
+    }
+
+    self.scan(line);
+    Placement::Script
+  }
+
+  /// The heredoc or quoted string that is still open: the line it starts on,
+  /// and what starts it.
+  fn unclosed(&self) -> Option<(usize, String)> {
+    (self.heredocs.front())
+      .map(|heredoc| (heredoc.line, format!("<<{}", heredoc.delimiter)))
+      .or_else(|| self.quote.map(|quote| (self.quote_line, quote.to_string())))
+  }
+}
+
+/// The placement of each line of the file.
+pub fn placements(lines: &[String]) -> Vec<Placement> {
+  let mut scanner = ShellScanner::default();
+  lines.iter().map(|line| scanner.placement(line)).collect()
+}
+
+/// A heredoc or quoted string that isn't closed by the end of the file (which
+/// turns every line after it into text).
+pub fn unclosed_text(lines: &[String]) -> Option<(usize, String)> {
+  let mut scanner = ShellScanner::default();
+
+  for line in lines {
+    scanner.placement(line);
+  }
+
+  scanner.unclosed()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test_case::test_case;
+  use Placement::{Command, Continuation, Script, Text};
+
+  fn placements_of(script: &str) -> Vec<Placement> {
+    let lines: Vec<String> = script.lines().map(str::to_owned).collect();
+    placements(&lines)
+  }
+
+  #[test_case("if true; then\n.-.- a\nfi\n..-- b" => vec![Script, Command(Some(Block::If)), Script, Command(None)]; "if block")]
+  #[test_case("case $A in\n  x) echo\n.-.- a\nesac" => vec![Script, Script, Command(Some(Block::Case)), Script]; "case")]
+  #[test_case("for i in 1 2; do\n.-.- a\ndone" => vec![Script, Command(Some(Block::Loop)), Script]; "loop block")]
+  #[test_case("f() {\n.-.- a\n}" => vec![Script, Command(Some(Block::Function)), Script]; "function")]
+  #[test_case("function f {\n.-.- a\n}" => vec![Script, Command(Some(Block::Function)), Script]; "function keyword")]
+  #[test_case("{ echo a\n.-.- a\n}" => vec![Script, Command(Some(Block::Braces)), Script]; "braces")]
+  #[test_case("A=$(\n.-.- a\n)" => vec![Script, Command(Some(Block::Subshell)), Script]; "subshell")]
+  #[test_case("if true; then echo; fi\n.-.- a" => vec![Script, Command(None)]; "one line")]
+  #[test_case("f() { echo; }\n.-.- a" => vec![Script, Command(None)]; "one line function")]
+  #[test_case("echo if then { case\n.-.- a" => vec![Script, Command(None)]; "keywords as arguments")]
+  #[test_case("echo \"if\n.-.- a\n\"\n..-- b" => vec![Script, Text, Text, Command(None)]; "quoted string")]
+  #[test_case("echo 'a # b' # if\n.-.- a" => vec![Script, Command(None)]; "comment")]
+  #[test_case("echo \\\"if\n.-.- a" => vec![Script, Command(None)]; "escaped quote")]
+  #[test_case("echo $((1 << 2))\n.-.- a" => vec![Script, Command(None)]; "shift")]
+  #[test_case("echo $(date $(echo))\n.-.- a" => vec![Script, Command(None)]; "nested subshells")]
+  fn test_placements(script: &str) -> Vec<Placement> {
+    placements_of(script)
+  }
+
+  #[test_case("cat <<EOF\n.-.- a\nEOF\n..-- b" => vec![Script, Text, Text, Command(None)]; "heredoc")]
+  #[test_case("cat << 'EOF' > f\n.-.- a\nEOF\n..-- b" => vec![Script, Text, Text, Command(None)]; "quoted delimiter")]
+  #[test_case("cat <<-EOF\n.-.- a\n\tEOF\n..-- b" => vec![Script, Text, Text, Command(None)]; "tabs")]
+  #[test_case("cat <<A <<B\n.- a\nA\n.- b\nB\n..-- b" => vec![Script, Text, Text, Text, Text, Command(None)]; "two heredocs")]
+  #[test_case("cat <<<'.-.- a'\n..-- b" => vec![Script, Command(None)]; "here string")]
+  fn test_placements_heredoc(script: &str) -> Vec<Placement> {
+    placements_of(script)
+  }
+
+  #[test_case(".-.- a \\\n  if b; then \\\n  c; fi\n..-- d" => vec![Command(None), Continuation, Continuation, Command(None)]; "command")]
+  #[test_case("echo \\\n..-- d" => vec![Script, Command(None)]; "script")]
+  #[test_case(".-.- a \\\\\n..-- d" => vec![Command(None), Command(None)]; "escaped backslash")]
+  fn test_placements_continuation(script: &str) -> Vec<Placement> {
+    placements_of(script)
+  }
+
+  #[test_case("A=1\necho \"a\n.-.- a" => Some((2, "\"".to_owned())); "quote")]
+  #[test_case("echo 'a'\ncat <<EOF\n.-.- a" => Some((2, "<<EOF".to_owned())); "heredoc")]
+  #[test_case("echo \"a\nb\"\ncat <<EOF\nEOF" => None; "closed")]
+  #[test_case(".-.- echo \"a\n..-- b" => None; "quote in a command")]
+  fn test_unclosed_text(script: &str) -> Option<(usize, String)> {
+    let lines: Vec<String> = script.lines().map(str::to_owned).collect();
+    unclosed_text(&lines)
+  }
+
+  #[test]
+  fn test_placements_nested() {
+    let script = "if true; then\n  while true; do\n.-.- a\n  done\n..-- b\nfi";
+    assert_eq!(
+      placements_of(script)[2..5],
+      [Command(Some(Block::Loop)), Script, Command(Some(Block::If))]
+    );
+  }
+}
@@ -0,0 +1,341 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+use crate::timing_settings::TimingSettings;
+
+const BUCKET_MS: u128 = 10;
+const BUCKET_COUNT: u32 = 300;
+const MIN_SAMPLES: u32 = 20;
+const MAX_SAMPLES: u32 = 2000;
+const SAVE_EVERY: u32 = 20;
+/// Gaps longer than this many times the sequence reset time are idle time,
+/// not pauses between sequences.
+const IDLE_GAP_FACTOR: u128 = 3;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DurationHistogram {
+  counts: Vec<u32>,
+}
+
+fn bucket_of(ms: u128) -> Option<usize> {
+  usize::try_from(ms.div_euclid(BUCKET_MS))
+    .ok()
+    .filter(|b| *b < BUCKET_COUNT as usize)
+}
+
+fn weighted_sum(counts: &[u32], first_bucket: u32) -> (f64, f64) {
+  counts
+    .iter()
+    .zip(first_bucket..)
+    .fold((0.0, 0.0), |(weight, sum), (count, i)| {
+      let c = f64::from(*count);
+      (weight + c, f64::from(i).mul_add(c, sum))
+    })
+}
+
+fn between_class_variance(counts: &[u32], split: u32) -> f64 {
+  let (lo, hi) = counts.split_at(split as usize);
+  let (w0, s0) = weighted_sum(lo, 0);
+  let (w1, s1) = weighted_sum(hi, split);
+
+  if w0 == 0.0 || w1 == 0.0 {
+    return 0.0;
+  }
+
+  let diff = s0 / w0 - s1 / w1;
+  w0 * w1 * diff * diff
+}
+
+/// Splits anywhere in the empty area between clusters score the same (up to
+/// rounding), so the middle one is chosen.
+fn best_plateau_middle(variances: &[f64]) -> Option<u128> {
+  let best = variances.iter().copied().fold(0.0, f64::max);
+
+  if best == 0.0 {
+    return None;
+  }
+
+  let is_best = |v: &f64| *v >= best * (1.0 - 1e-9);
+  let first = u128::try_from(variances.iter().position(is_best)?).ok()?;
+  let last = u128::try_from(variances.iter().rposition(is_best)?).ok()?;
+
+  Some(first.midpoint(last))
+}
+
+impl DurationHistogram {
+  pub fn new() -> Self {
+    Self {
+      counts: vec![0; BUCKET_COUNT as usize],
+    }
+  }
+
+  /// Durations past the last bucket are ignored, so that they don't pull the
+  /// boundary away from the clusters.
+  pub fn add(&mut self, ms: u128) {
+    let Some(bucket) = bucket_of(ms) else {
+      return;
+    };
+
+    self.counts[bucket] += 1;
+
+    if self.total() > MAX_SAMPLES {
+      self.counts.iter_mut().for_each(|c| *c >>= 1);
+    }
+  }
+
+  fn total(&self) -> u32 {
+    self.counts.iter().sum()
+  }
+
+  /// Splits the samples into two clusters (Otsu's method), and returns a
+  /// boundary in the middle of the empty area between them.
+  pub fn boundary(&self) -> Option<u128> {
+    if self.total() < MIN_SAMPLES {
+      return None;
+    }
+
+    let variances: Vec<f64> = (1..BUCKET_COUNT)
+      .map(|split| between_class_variance(&self.counts, split))
+      .collect();
+
+    best_plateau_middle(&variances).map(|i| (i + 1) * BUCKET_MS)
+  }
+}
+
+#[derive(Clone, Copy)]
+pub enum TimingSample {
+  Press(u128),
+  Gap(u128),
+}
+
+impl TimingSample {
+  /// A gap much longer than the sequence reset time is the user doing
+  /// something else, and would drag the learned reset time up.
+  pub const fn is_idle(self, timing: &TimingSettings) -> bool {
+    matches!(self, Self::Gap(ms) if ms > timing.sequence_reset_ms * IDLE_GAP_FACTOR)
+  }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TimingCalibration {
+  press: DurationHistogram,
+  gap: DurationHistogram,
+  #[serde(skip)]
+  unsaved: u32,
+}
+
+impl TimingCalibration {
+  pub fn new() -> Self {
+    Self {
+      press: DurationHistogram::new(),
+      gap: DurationHistogram::new(),
+      unsaved: 0,
+    }
+  }
+
+  pub fn load(path: &str) -> Result<Self> {
+    let file = File::open(path)?;
+    Ok(bincode::deserialize_from(BufReader::new(file))?)
+  }
+
+  pub fn load_or_new(path: &str) -> Self {
+    Self::load(path).unwrap_or_else(|_| Self::new())
+  }
+
+  pub fn save(&mut self, path: &str) -> Result<()> {
+    let file = File::create(path)?;
+    bincode::serialize_into(BufWriter::new(file), self)?;
+    self.unsaved = 0;
+    Ok(())
+  }
+
+  pub fn record(&mut self, sample: TimingSample) {
+    match sample {
+      TimingSample::Press(ms) => self.press.add(ms),
+      TimingSample::Gap(ms) => self.gap.add(ms),
+    }
+
+    self.unsaved += 1;
+  }
+
+  pub const fn should_save(&self) -> bool {
+    self.unsaved >= SAVE_EVERY
+  }
+
+  pub fn learned_timing(&self, base: TimingSettings) -> TimingSettings {
+    TimingSettings {
+      long_press_ms: self.press.boundary().unwrap_or(base.long_press_ms),
+      sequence_reset_ms: self.gap.boundary().unwrap_or(base.sequence_reset_ms),
+      ..base
+    }
+  }
+}
+
+#[derive(Default)]
+pub struct LabeledSamples {
+  short: Vec<u128>,
+  long: Vec<u128>,
+  inner_gaps: Vec<u128>,
+  outer_gaps: Vec<u128>,
+}
+
+impl LabeledSamples {
+  fn add_press(&mut self, symbol: char, press: u128) {
+    if symbol == '-' {
+      self.long.push(press);
+    } else {
+      self.short.push(press);
+    }
+  }
+
+  /// Labels `(gap, press)` durations using the pattern the user was asked to
+  /// click (repeatedly, with a pause between repetitions).
+  pub fn from_clicks(pattern: &str, clicks: &[(u128, u128)]) -> Self {
+    let mut result = Self::default();
+    let symbols: Vec<char> = pattern.chars().collect();
+
+    for (i, (gap, press)) in clicks.iter().enumerate() {
+      let pos = i % symbols.len();
+
+      result.add_press(symbols[pos], *press);
+
+      match (i, pos) {
+        (0, _) => {}
+        (_, 0) => result.outer_gaps.push(*gap),
+        _ => result.inner_gaps.push(*gap),
+      }
+    }
+
+    result
+  }
+
+  pub fn timing(&self, base: TimingSettings) -> Option<TimingSettings> {
+    Some(TimingSettings {
+      long_press_ms: labeled_boundary(&self.short, &self.long)?,
+      sequence_reset_ms: labeled_boundary(&self.inner_gaps, &self.outer_gaps)?,
+      ..base
+    })
+  }
+
+  pub fn to_calibration(&self) -> TimingCalibration {
+    let mut result = TimingCalibration::new();
+
+    for ms in self.short.iter().chain(&self.long) {
+      result.record(TimingSample::Press(*ms));
+    }
+
+    for ms in self.inner_gaps.iter().chain(&self.outer_gaps) {
+      result.record(TimingSample::Gap(*ms));
+    }
+
+    result
+  }
+}
+
+/// Boundary between two groups of durations known to be different (e.g. short
+/// and long presses). Fails if the groups overlap.
+pub fn labeled_boundary(lo: &[u128], hi: &[u128]) -> Option<u128> {
+  let lo_max = lo.iter().max()?;
+  let hi_min = hi.iter().min()?;
+
+  (lo_max < hi_min).then(|| lo_max.midpoint(*hi_min))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn histogram(samples: &[(u128, u32)]) -> DurationHistogram {
+    let mut result = DurationHistogram::new();
+
+    for (ms, times) in samples {
+      for _ in 0..*times {
+        result.add(*ms);
+      }
+    }
+
+    result
+  }
+
+  #[test]
+  fn test_boundary_two_clusters() {
+    let h = histogram(&[(100, 10), (110, 10), (400, 10), (420, 5)]);
+    assert_eq!(h.boundary(), Some(260));
+  }
+
+  #[test]
+  fn test_boundary_not_enough_samples() {
+    let h = histogram(&[(100, 5), (400, 5)]);
+    assert_eq!(h.boundary(), None);
+  }
+
+  #[test]
+  fn test_boundary_single_value() {
+    let h = histogram(&[(100, 30)]);
+    assert_eq!(h.boundary(), None);
+  }
+
+  #[test]
+  fn test_boundary_long_durations_are_ignored() {
+    let one_cluster = histogram(&[(150, 20), (60_000, 5)]);
+    assert_eq!(one_cluster.boundary(), None);
+
+    let two_clusters = histogram(&[(100, 10), (110, 10), (400, 10), (420, 5), (60_000, 5)]);
+    assert_eq!(two_clusters.boundary(), Some(260));
+  }
+
+  #[test]
+  fn test_idle_gaps() {
+    let timing = TimingSettings::default();
+    assert!(!TimingSample::Gap(timing.sequence_reset_ms * 2).is_idle(&timing));
+    assert!(TimingSample::Gap(60_000).is_idle(&timing));
+    assert!(!TimingSample::Press(60_000).is_idle(&timing));
+  }
+
+  #[test]
+  fn test_labeled_boundary() {
+    assert_eq!(labeled_boundary(&[100, 150, 120], &[300, 400]), Some(225));
+    assert_eq!(labeled_boundary(&[100, 350], &[300, 400]), None);
+    assert_eq!(labeled_boundary(&[], &[300, 400]), None);
+  }
+
+  #[test]
+  fn test_labeled_samples() {
+    let clicks = [
+      (0, 90),
+      (120, 300),
+      (100, 80),
+      (900, 110),
+      (130, 280),
+      (80, 100),
+    ];
+    let samples = LabeledSamples::from_clicks(".-.", &clicks);
+    let timing = samples.timing(TimingSettings::default()).unwrap();
+    assert_eq!(timing.long_press_ms, 195);
+    assert_eq!(timing.sequence_reset_ms, 515);
+  }
+
+  #[test]
+  fn test_labeled_samples_overlapping() {
+    let clicks = [(0, 90), (120, 300), (100, 350)];
+    let samples = LabeledSamples::from_clicks(".-.", &clicks);
+    assert!(samples.timing(TimingSettings::default()).is_none());
+  }
+
+  #[test]
+  fn test_learned_timing_falls_back_to_base() {
+    let mut calibration = TimingCalibration::new();
+
+    for _ in 0..10 {
+      calibration.record(TimingSample::Press(100));
+      calibration.record(TimingSample::Press(500));
+    }
+
+    let base = TimingSettings::default();
+    let learned = calibration.learned_timing(base);
+    assert_eq!(learned.long_press_ms, 300);
+    assert_eq!(learned.sequence_reset_ms, base.sequence_reset_ms);
+  }
+}
@@ -0,0 +1,116 @@
+use std::{collections::HashMap, fmt::Display};
+
+use crate::constants::{DEFAULT_LONG_PRESS_MS, DEFAULT_SEQUENCE_RESET_MS};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TimingSettings {
+  pub long_press_ms: u128,
+  pub sequence_reset_ms: u128,
+  pub adaptive: bool,
+  /// When a sequence is also the start of a longer one, wait until the pause
+  /// after it before executing it.
+  pub wait_for_longer: bool,
+}
+
+impl Default for TimingSettings {
+  fn default() -> Self {
+    Self {
+      long_press_ms: DEFAULT_LONG_PRESS_MS,
+      sequence_reset_ms: DEFAULT_SEQUENCE_RESET_MS,
+      adaptive: false,
+      wait_for_longer: false,
+    }
+  }
+}
+
+/// A duration setting, which can't be 0 (a sequence reset after 0 ms would end
+/// every sequence at its first click).
+pub fn parse_ms_value(value: &str) -> Option<u128> {
+  value.parse().ok().filter(|ms| *ms > 0)
+}
+
+fn parse_ms(settings: &HashMap<String, String>, key: &str) -> Option<u128> {
+  settings.get(key).and_then(|value| parse_ms_value(value))
+}
+
+pub fn parse_flag_value(value: &str) -> Option<bool> {
+  match value.to_lowercase().as_ref() {
+    "1" | "true" | "yes" | "on" => Some(true),
+    "0" | "false" | "no" | "off" => Some(false),
+    _ => None,
+  }
+}
+
+pub fn parse_flag(settings: &HashMap<String, String>, key: &str) -> Option<bool> {
+  settings.get(key).and_then(|value| parse_flag_value(value))
+}
+
+impl TimingSettings {
+  pub fn with_overrides(self, settings: &HashMap<String, String>) -> Self {
+    Self {
+      long_press_ms: parse_ms(settings, "LONG_PRESS_MS").unwrap_or(self.long_press_ms),
+      sequence_reset_ms: parse_ms(settings, "SEQUENCE_RESET_MS").unwrap_or(self.sequence_reset_ms),
+      adaptive: parse_flag(settings, "ADAPTIVE").unwrap_or(self.adaptive),
+      wait_for_longer: parse_flag(settings, "WAIT_FOR_LONGER").unwrap_or(self.wait_for_longer),
+    }
+  }
+}
+
+impl Display for TimingSettings {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    writeln!(f, "long_press_ms={}", self.long_press_ms)?;
+    writeln!(f, "sequence_reset_ms={}", self.sequence_reset_ms)?;
+    writeln!(f, "adaptive={}", self.adaptive)?;
+    write!(f, "wait_for_longer={}", self.wait_for_longer)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn settings<const N: usize>(pairs: [(&str, &str); N]) -> HashMap<String, String> {
+    pairs
+      .iter()
+      .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+      .collect()
+  }
+
+  #[test]
+  fn test_with_overrides() {
+    let timing = TimingSettings::default().with_overrides(&settings([("LONG_PRESS_MS", "300")]));
+    assert_eq!(timing.long_press_ms, 300);
+    assert_eq!(timing.sequence_reset_ms, DEFAULT_SEQUENCE_RESET_MS);
+  }
+
+  #[test]
+  fn test_with_overrides_adaptive() {
+    let timing = TimingSettings::default().with_overrides(&settings([("ADAPTIVE", "Yes")]));
+    assert!(timing.adaptive);
+  }
+
+  #[test]
+  fn test_with_overrides_invalid_values_ignored() {
+    let base = TimingSettings {
+      long_press_ms: 1,
+      sequence_reset_ms: 2,
+      adaptive: true,
+      wait_for_longer: false,
+    };
+    let timing = base.with_overrides(&settings([
+      ("LONG_PRESS_MS", "abc"),
+      ("SEQUENCE_RESET_MS", "0"),
+      ("ADAPTIVE", "maybe"),
+      ("WAIT_FOR_LONGER", ""),
+    ]));
+    assert_eq!(timing, base);
+  }
+
+  #[test]
+  fn test_display() {
+    assert_eq!(
+      TimingSettings::default().to_string(),
+      "long_press_ms=200\nsequence_reset_ms=500\nadaptive=false\nwait_for_longer=false"
+    );
+  }
+}
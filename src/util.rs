@@ -4,9 +4,10 @@ use chrono::{
   format::{DelayedFormat, StrftimeItems},
   DateTime, Local,
 };
+use home::home_dir;
 use serde::Serialize;
 use std::io::{BufRead, BufReader};
-use std::{fs::OpenOptions, ops::Deref};
+use std::{fs::OpenOptions, ops::Deref, path::PathBuf};
 
 pub fn format_date<'a>(date: DateTime<Local>) -> DelayedFormat<StrftimeItems<'a>> {
   date.format("%Y-%m-%d %H:%M:%S")
@@ -30,6 +31,14 @@ pub fn read_lines_or_create(file_path: &str) -> Result<Vec<String>, std::io::Err
     .collect::<Result<Vec<String>, std::io::Error>>()
 }
 
+/// `~` at the start of a path is the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+  match (path.strip_prefix('~'), home_dir()) {
+    (Some(rest), Some(home)) => home.join(rest.trim_start_matches('/')),
+    _ => PathBuf::from(path),
+  }
+}
+
 pub struct PayloadOverwriter {
   inner: Vec<u8>,
 }
@@ -40,7 +49,8 @@ impl PayloadOverwriter {
   }
 
   pub fn overwrite_serialize<T: Serialize>(&mut self, value: T) -> Result<(), Box<ErrorKind>> {
-    assert!(self.inner.len() < 100);
+    // Events with sequences (e.g. suggestions) are larger than the others.
+    assert!(self.inner.len() < 1000);
     self.inner.clear();
     bincode::serialize_into(&mut self.inner, &value)
   }